@@ -0,0 +1,135 @@
+// Benchmarks for `search` covering a range of text sizes, pattern lengths and
+// alphabets. Run with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use approx_string_match_rs::search;
+
+/// Generate `len` UTF-16 code units of text made of words drawn from `words`,
+/// using a fixed seed so that runs are comparable.
+fn random_text(words: &[&str], sep: &str, len: usize) -> Vec<u16> {
+    let mut seed: u64 = 1;
+    let mut text = Vec::with_capacity(len);
+    while text.len() < len {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let word = words[(seed >> 33) as usize % words.len()];
+        text.extend(word.encode_utf16());
+        text.extend(sep.encode_utf16());
+    }
+    text.truncate(len);
+    text
+}
+
+const ENGLISH_WORDS: &[&str] = &[
+    "the", "quick", "brown", "fox", "jumps", "over", "lazy", "dog", "and", "runs", "away", "from",
+    "hunter", "into", "forest", "where", "it", "hides", "under", "old", "tree",
+];
+
+const JAPANESE_WORDS: &[&str] = &[
+    "吾輩",
+    "は",
+    "猫",
+    "である",
+    "名前",
+    "まだ",
+    "無い",
+    "どこ",
+    "で",
+    "生れた",
+    "か",
+    "とんと",
+    "見当",
+    "が",
+    "つかぬ",
+    "何",
+    "でも",
+    "薄暗い",
+    "じめじめ",
+    "した",
+    "所",
+];
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
+
+/// Benchmark searching for `pattern` in `text` with each of `max_errors`.
+fn bench_search(c: &mut Criterion, name: &str, text: &[u16], pattern: &[u16], max_errors: &[u32]) {
+    let mut group = c.benchmark_group(name);
+    for &k in max_errors {
+        group.bench_with_input(BenchmarkId::from_parameter(k), &k, |b, &k| {
+            b.iter(|| search(black_box(text), black_box(pattern), k))
+        });
+    }
+    group.finish();
+}
+
+fn short_pattern_short_text(c: &mut Criterion) {
+    let text = random_text(ENGLISH_WORDS, " ", 100);
+    bench_search(
+        c,
+        "short_pattern_short_text",
+        &text,
+        &utf16("hunter"),
+        &[0, 1, 2],
+    );
+}
+
+fn short_pattern_large_text(c: &mut Criterion) {
+    let text = random_text(ENGLISH_WORDS, " ", 1 << 20);
+    bench_search(
+        c,
+        "short_pattern_1m_text",
+        &text,
+        &utf16("huntre"),
+        &[0, 1, 2],
+    );
+}
+
+fn long_pattern_medium_text(c: &mut Criterion) {
+    let text = random_text(ENGLISH_WORDS, " ", 64 * 1024);
+    let pattern = text[10_000..10_300].to_vec();
+    bench_search(c, "long_pattern_64k_text", &text, &pattern, &[0, 30, 150]);
+}
+
+fn repeated_char_pattern(c: &mut Criterion) {
+    let text = random_text(&["a", "aa", "b", "aaa"], "", 64 * 1024);
+    bench_search(
+        c,
+        "repeated_char_pattern",
+        &text,
+        &utf16(&"a".repeat(40)),
+        &[0, 4, 10],
+    );
+}
+
+fn many_matches(c: &mut Criterion) {
+    // Finding the start of each match takes a separate reverse scan, which
+    // dominates the cost when matches are frequent.
+    let text = random_text(&["hunter", "hunt", "her"], " ", 64 * 1024);
+    bench_search(c, "many_matches_64k_text", &text, &utf16("hunter"), &[0, 1]);
+}
+
+fn non_ascii_text(c: &mut Criterion) {
+    let text = random_text(JAPANESE_WORDS, "、", 256 * 1024);
+    bench_search(
+        c,
+        "japanese_256k_text",
+        &text,
+        &utf16("薄暗いじめじめした所"),
+        &[0, 1, 3],
+    );
+}
+
+criterion_group!(
+    benches,
+    short_pattern_short_text,
+    short_pattern_large_text,
+    long_pattern_medium_text,
+    repeated_char_pattern,
+    many_matches,
+    non_ascii_text
+);
+criterion_main!(benches);
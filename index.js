@@ -9,6 +9,7 @@ class WasmString {
    */
   constructor(wasm, str) {
     this.wasm = wasm;
+    this.length = str.length;
     this.buffer = wasm.char_buf_alloc(str.length);
 
     const data = wasm.char_buf_data(this.buffer);
@@ -28,6 +29,8 @@ class WasmString {
  * Search for approximate matches for `pattern` in `text` allowing up to
  * `maxErrors` errors.
  *
+ * Match offsets are UTF-16 code unit indices, as used by `String.prototype.slice`.
+ *
  * @param {Object} wasm - Exports object of the compiled WebAssembly implementation
  * @param {WasmString} text - Text to search in
  * @param {WasmString} pattern - String to search for in `text`
@@ -53,4 +56,145 @@ function search(wasm, text, pattern, maxErrors) {
   return matches;
 }
 
-module.exports = { WasmString, search };
+/**
+ * Variant of `search` which fetches the matches from WebAssembly as a single
+ * JSON string, rather than making several calls for each match.
+ *
+ * @param {Object} wasm - Exports object of the compiled WebAssembly implementation
+ * @param {WasmString} text - Text to search in
+ * @param {WasmString} pattern - String to search for in `text`
+ * @param {number} maxErrors - Maximum number of errors to allow
+ * @return {Match[]} Array of matches
+ */
+function searchJSON(wasm, text, pattern, maxErrors) {
+  const json = wasm.search_json(
+    wasm.char_buf_data(text.buffer),
+    text.length,
+    wasm.char_buf_data(pattern.buffer),
+    pattern.length,
+    maxErrors
+  );
+  const bytes = new Uint8Array(wasm.memory.buffer, json, wasm.json_len(json));
+  const matches = JSON.parse(new TextDecoder().decode(bytes));
+  wasm.json_free(json);
+  return matches;
+}
+
+/**
+ * Search for approximate matches for each of `patterns` in `text` allowing up
+ * to `maxErrors` errors, with a single call into WebAssembly.
+ *
+ * @param {Object} wasm - Exports object of the compiled WebAssembly implementation
+ * @param {WasmString} text - Text to search in
+ * @param {string[]} patterns - Strings to search for in `text`
+ * @param {number} maxErrors - Maximum number of errors to allow
+ * @return {Match[][]} Array of matches for each pattern
+ */
+function searchMulti(wasm, text, patterns, maxErrors) {
+  const packed = new WasmString(wasm, patterns.join(""));
+  const lens = wasm.len_buf_alloc(patterns.length);
+  const lensData = new Uint32Array(
+    wasm.memory.buffer,
+    wasm.len_buf_data(lens),
+    patterns.length
+  );
+  patterns.forEach((pattern, i) => {
+    lensData[i] = pattern.length;
+  });
+
+  const matchVec = wasm.multi_match_vec_alloc();
+  wasm.search_multi(matchVec, text.buffer, packed.buffer, lens, maxErrors);
+
+  const matches = patterns.map(() => []);
+  const matchCount = wasm.multi_match_vec_len(matchVec);
+  for (let m = 0; m < matchCount; m++) {
+    const index = wasm.multi_match_vec_pattern(matchVec, m);
+    const match = wasm.multi_match_vec_get(matchVec, m);
+    const start = wasm.match_start(match);
+    const end = wasm.match_end(match);
+    const errors = wasm.match_errors(match);
+    matches[index].push({ start, end, errors });
+  }
+
+  wasm.multi_match_vec_free(matchVec);
+  wasm.len_buf_free(lens);
+  packed.free();
+  return matches;
+}
+
+/**
+ * Return the similarity of `pattern` to its best match in `text`, from 0
+ * (no similarity) to 1 (an exact match).
+ *
+ * @param {Object} wasm - Exports object of the compiled WebAssembly implementation
+ * @param {WasmString} text - Text to search in
+ * @param {WasmString} pattern - String to search for in `text`
+ * @return {number}
+ */
+function similarity(wasm, text, pattern) {
+  return wasm.similarity(
+    wasm.char_buf_data(text.buffer),
+    text.length,
+    wasm.char_buf_data(pattern.buffer),
+    pattern.length
+  );
+}
+
+/**
+ * A pattern which has been preprocessed for searching in many texts.
+ */
+class CompiledPattern {
+  /**
+   * @param {object} wasm - WebAssembly exports from `lib/wasm.rs`.
+   * @param {WasmString} pattern
+   */
+  constructor(wasm, pattern) {
+    this.wasm = wasm;
+    this.handle = wasm.compiled_pattern_new(
+      wasm.char_buf_data(pattern.buffer),
+      pattern.length
+    );
+  }
+
+  /**
+   * Search for approximate matches for this pattern in `text` allowing up to
+   * `maxErrors` errors.
+   *
+   * @param {WasmString} text - Text to search in
+   * @param {number} maxErrors - Maximum number of errors to allow
+   * @return {Match[]} Array of matches
+   */
+  search(text, maxErrors) {
+    const wasm = this.wasm;
+    const matchVec = wasm.match_vec_alloc();
+
+    wasm.compiled_pattern_search(this.handle, matchVec, text.buffer, maxErrors);
+
+    const matchCount = wasm.match_vec_len(matchVec);
+    const matches = [];
+    for (let m = 0; m < matchCount; m++) {
+      const match = wasm.match_vec_get(matchVec, m);
+      const start = wasm.match_start(match);
+      const end = wasm.match_end(match);
+      const errors = wasm.match_errors(match);
+      matches.push({ start, end, errors });
+    }
+
+    wasm.match_vec_free(matchVec);
+    return matches;
+  }
+
+  free() {
+    this.wasm.compiled_pattern_free(this.handle);
+    this.handle = 0;
+  }
+}
+
+module.exports = {
+  CompiledPattern,
+  WasmString,
+  search,
+  searchJSON,
+  searchMulti,
+  similarity,
+};
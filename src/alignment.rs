@@ -0,0 +1,514 @@
+// This module computes alignments between a pattern and the text of a match,
+// describing which characters were inserted, deleted or substituted.
+//
+// The bit-vector search algorithm only computes error counts, so once a match
+// has been located the alignment is recovered by computing the full dynamic
+// programming table for just the matched region of the text and tracing back
+// through it.
+
+use crate::{search, search_all, Match};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// An edit operation in the alignment of a pattern with the matched text.
+///
+/// `text_pos` is an offset into the text that was searched and `pattern_pos` is
+/// an offset into the pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EditOp {
+    /// A pattern character which matches the text character.
+    Match { text_pos: usize, pattern_pos: usize },
+
+    /// A character in the text which is not in the pattern.
+    Insert { text_pos: usize },
+
+    /// A character in the pattern which is missing from the text.
+    Delete { pattern_pos: usize },
+
+    /// A pattern character which was replaced by a different text character.
+    Substitute { text_pos: usize, pattern_pos: usize },
+}
+
+/// A match together with the alignment of the pattern against the matched text.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AlignedMatch {
+    #[cfg_attr(feature = "serde", serde(rename = "match"))]
+    match_: Match,
+    ops: Vec<EditOp>,
+}
+
+impl AlignedMatch {
+    /// The location and error count of the match.
+    pub fn as_match(&self) -> &Match {
+        &self.match_
+    }
+
+    /// Edit operations which transform the pattern into the matched text, in text
+    /// order. The number of operations other than `EditOp::Match` is equal to
+    /// the error count of the match.
+    pub fn ops(&self) -> &[EditOp] {
+        &self.ops
+    }
+}
+
+/// A match together with a division of the matched text into parts which are
+/// aligned with the pattern and parts which are errors, for highlighting.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HighlightedMatch {
+    #[cfg_attr(feature = "serde", serde(rename = "match"))]
+    match_: Match,
+    spans: Vec<(Range<usize>, bool)>,
+}
+
+impl HighlightedMatch {
+    /// The location and error count of the match.
+    pub fn as_match(&self) -> &Match {
+        &self.match_
+    }
+
+    /// Consecutive ranges of the text which together cover the match, each
+    /// with a flag which is true if the characters match the pattern, or false
+    /// if they are errors.
+    ///
+    /// Inserted and substituted characters are errors. A pattern character
+    /// which is missing from the text is marked by an empty error range at the
+    /// point where it is missing. Adjacent ranges never have the same flag.
+    pub fn spans(&self) -> &[(Range<usize>, bool)] {
+        &self.spans
+    }
+}
+
+impl Match {
+    /// Break down the errors in this match into the number of insertions,
+    /// deletions and substitutions, returned as an `(insertions, deletions,
+    /// substitutions)` tuple.
+    ///
+    /// `text` and `pattern` must be the inputs of the search which produced this
+    /// match. The counts are computed on demand from a minimal alignment of the
+    /// pattern against the matched text (see `search_with_alignment`), so only
+    /// callers which need them pay for the traceback.
+    pub fn edit_counts(&self, text: &[u16], pattern: &[u16]) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for op in align(text, pattern, self) {
+            match op {
+                EditOp::Match { .. } => {}
+                EditOp::Insert { .. } => counts.0 += 1,
+                EditOp::Delete { .. } => counts.1 += 1,
+                EditOp::Substitute { .. } => counts.2 += 1,
+            }
+        }
+        counts
+    }
+}
+
+/// Compute a minimal alignment of `pattern` against `text[m.start..m.end]`.
+///
+/// Where several alignments have the same cost, substitutions are preferred over
+/// deletions, and deletions over insertions.
+pub(crate) fn align(text: &[u16], pattern: &[u16], m: &Match) -> Vec<EditOp> {
+    let segment = &text[m.start()..m.end()];
+    let rows = pattern.len() + 1;
+    let cols = segment.len() + 1;
+
+    // Table of edit distances between prefixes of the pattern and the segment.
+    let mut dist = vec![0; rows * cols];
+    for i in 0..rows {
+        for j in 0..cols {
+            dist[i * cols + j] = if i == 0 {
+                j
+            } else if j == 0 {
+                i
+            } else {
+                let sub_cost = (pattern[i - 1] != segment[j - 1]) as usize;
+                (dist[(i - 1) * cols + j - 1] + sub_cost)
+                    .min(dist[(i - 1) * cols + j] + 1)
+                    .min(dist[i * cols + j - 1] + 1)
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(rows.max(cols));
+    let (mut i, mut j) = (pattern.len(), segment.len());
+    while i > 0 || j > 0 {
+        let d = dist[i * cols + j];
+        if i > 0 && j > 0 {
+            let same = pattern[i - 1] == segment[j - 1];
+            if d == dist[(i - 1) * cols + j - 1] + (!same as usize) {
+                i -= 1;
+                j -= 1;
+                let text_pos = m.start() + j;
+                ops.push(if same {
+                    EditOp::Match {
+                        text_pos,
+                        pattern_pos: i,
+                    }
+                } else {
+                    EditOp::Substitute {
+                        text_pos,
+                        pattern_pos: i,
+                    }
+                });
+                continue;
+            }
+        }
+        if i > 0 && d == dist[(i - 1) * cols + j] + 1 {
+            i -= 1;
+            ops.push(EditOp::Delete { pattern_pos: i });
+        } else {
+            j -= 1;
+            ops.push(EditOp::Insert {
+                text_pos: m.start() + j,
+            });
+        }
+    }
+    ops.reverse();
+    ops
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, and compute the alignment of the pattern against each
+/// match.
+///
+/// Matches are located using `search`. The alignment for each match then costs
+/// O(m * l) time and space, where `m` is the length of the pattern and `l` is
+/// the length of the match.
+pub fn search_with_alignment(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<AlignedMatch> {
+    search(text, pattern, max_errors)
+        .into_iter()
+        .map(|m| AlignedMatch {
+            ops: align(text, pattern, &m),
+            match_: m,
+        })
+        .collect()
+}
+
+/// Divide the text of `m` into spans which are aligned with the pattern or are
+/// errors, using the alignment given by `ops`.
+fn highlight(m: &Match, ops: &[EditOp]) -> Vec<(Range<usize>, bool)> {
+    let mut spans: Vec<(Range<usize>, bool)> = Vec::new();
+    let mut pos = m.start();
+    for op in ops {
+        let (range, is_match) = match *op {
+            EditOp::Match { text_pos, .. } => (text_pos..text_pos + 1, true),
+            EditOp::Insert { text_pos } | EditOp::Substitute { text_pos, .. } => {
+                (text_pos..text_pos + 1, false)
+            }
+            EditOp::Delete { .. } => (pos..pos, false),
+        };
+        pos = range.end;
+        match spans.last_mut() {
+            Some((prev, prev_is_match)) if *prev_is_match == is_match => prev.end = range.end,
+            _ => spans.push((range, is_match)),
+        }
+    }
+    spans
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, and divide the text of each match into parts which
+/// are aligned with the pattern and parts which are errors.
+///
+/// This uses the same alignment as `search_with_alignment`, in a form which is
+/// convenient for highlighting matches, eg. by showing the aligned parts in
+/// bold.
+pub fn search_with_spans(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<HighlightedMatch> {
+    search(text, pattern, max_errors)
+        .into_iter()
+        .map(|m| HighlightedMatch {
+            spans: highlight(&m, &align(text, pattern, &m)),
+            match_: m,
+        })
+        .collect()
+}
+
+/// Return the lowest total error count of an alignment of `pattern` with
+/// `segment` which uses at most `max_substitutions` substitutions and
+/// `max_indels` insertions and deletions, or `None` if there is no such
+/// alignment.
+fn split_budget_errors(
+    pattern: &[u16],
+    segment: &[u16],
+    max_substitutions: usize,
+    max_indels: usize,
+) -> Option<usize> {
+    const INF: usize = usize::MAX / 2;
+
+    // `row[j * subs + s]` is the lowest number of indels in an alignment of the
+    // pattern prefix processed so far with `segment[..j]` which uses `s`
+    // substitutions.
+    let subs = max_substitutions + 1;
+    let mut row = vec![INF; (segment.len() + 1) * subs];
+    for j in 0..=segment.len() {
+        row[j * subs] = j;
+    }
+
+    let mut next = row.clone();
+    for (i, &p) in pattern.iter().enumerate() {
+        next.fill(INF);
+        next[0] = i + 1;
+        for (j, &t) in segment.iter().enumerate() {
+            let j = j + 1;
+            for s in 0..subs {
+                let diagonal = if p == t {
+                    row[(j - 1) * subs + s]
+                } else if s > 0 {
+                    row[(j - 1) * subs + s - 1]
+                } else {
+                    INF
+                };
+                next[j * subs + s] = diagonal
+                    .min(row[j * subs + s] + 1)
+                    .min(next[(j - 1) * subs + s] + 1);
+            }
+        }
+        core::mem::swap(&mut row, &mut next);
+    }
+
+    row[segment.len() * subs..]
+        .iter()
+        .enumerate()
+        .filter(|&(_, &indels)| indels <= max_indels)
+        .map(|(s, &indels)| s + indels)
+        .min()
+}
+
+/// Search for approximate matches of `pattern` in `text` with separate budgets
+/// for substitutions and for insertions and deletions ("indels").
+///
+/// A match is accepted if the pattern can be aligned with the matched text
+/// using at most `max_substitutions` substitutions and at most `max_indels`
+/// indels. The bit-vector search only counts errors of all kinds together, so
+/// the candidate ends are those of the matches found by `search_all` with up
+/// to `max_substitutions + max_indels` errors. For each candidate end, every
+/// start which leaves a match no longer than the pattern plus `max_indels` is
+/// then checked using a dynamic programming table which tracks both counts,
+/// since the start chosen by `search_all` may need more substitutions or
+/// indels than another one. This costs O(m² * l * s) time per candidate, where
+/// `m` is the length of the pattern, `l` the length of the match and `s` the
+/// substitution budget.
+///
+/// Like `search_all`, a match is returned for every end which has an accepted
+/// start, in the same order. The start is the one with the lowest total error
+/// count, preferring the earliest, and the error count is the total for the
+/// match.
+pub fn search_split_budget(
+    text: &[u16],
+    pattern: &[u16],
+    max_substitutions: u32,
+    max_indels: u32,
+) -> Vec<Match> {
+    let candidates = search_all(text, pattern, max_substitutions.saturating_add(max_indels));
+    let max_substitutions = max_substitutions as usize;
+    let max_indels = max_indels as usize;
+    let max_len = pattern.len().saturating_add(max_indels);
+
+    candidates
+        .into_iter()
+        .filter_map(|m| {
+            let end = m.end();
+            (end.saturating_sub(max_len)..=end)
+                .filter_map(|start| {
+                    let errors = split_budget_errors(
+                        pattern,
+                        &text[start..end],
+                        max_substitutions,
+                        max_indels,
+                    )?;
+                    Some(Match { start, end, errors })
+                })
+                .min_by_key(|m| m.errors)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{search_split_budget, search_with_alignment, search_with_spans, EditOp};
+    use crate::test_util::Random;
+    use crate::{search, Match};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_aligns_pattern_with_match() {
+        let text = utf16_str("a regullar exprssion");
+        let pattern = utf16_str("regular expression");
+
+        let matches = search_with_alignment(&text, &pattern, 3);
+
+        assert_eq!(matches.len(), 1);
+        let m = &matches[0];
+        assert_eq!(m.as_match().errors(), 2);
+
+        let edits: Vec<EditOp> = m
+            .ops()
+            .iter()
+            .cloned()
+            .filter(|op| !matches!(op, EditOp::Match { .. }))
+            .collect();
+        assert_eq!(
+            edits,
+            vec![
+                EditOp::Insert { text_pos: 6 },
+                EditOp::Delete { pattern_pos: 12 },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_counts_edits_by_kind() {
+        let text = utf16_str("a regullar exprezzion");
+        let pattern = utf16_str("regular expression");
+
+        let matches = search(&text, &pattern, 4);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].edit_counts(&text, &pattern), (1, 0, 2));
+    }
+
+    #[test]
+    fn it_divides_match_into_spans() {
+        let text = utf16_str("the quixk brown fox");
+        let matches = search_with_spans(&text, &utf16_str("quick brown"), 1);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].as_match().range(), 4..15);
+        assert_eq!(
+            matches[0].spans(),
+            &[(4..7, true), (7..8, false), (8..15, true)]
+        );
+
+        // A missing character is marked by an empty span.
+        let matches = search_with_spans(&text, &utf16_str("brownn fox"), 1);
+        assert_eq!(
+            matches[0].spans(),
+            &[(10..14, true), (14..14, false), (14..19, true)]
+        );
+    }
+
+    #[test]
+    fn it_applies_separate_substitution_and_indel_budgets() {
+        // One substitution, then one deletion.
+        let text = utf16_str("abcxef abdef");
+        let pattern = utf16_str("abcdef");
+        let ranges = |max_substitutions, max_indels| -> Vec<_> {
+            search_split_budget(&text, &pattern, max_substitutions, max_indels)
+                .iter()
+                .map(|m| m.range())
+                .collect()
+        };
+
+        assert_eq!(ranges(1, 0), vec![0..6]);
+        assert_eq!(ranges(0, 1), vec![7..12]);
+        assert!(ranges(1, 1).contains(&(0..6)));
+        assert!(ranges(1, 1).contains(&(7..12)));
+        assert!(ranges(0, 0).is_empty());
+
+        // The start chosen by `search_all` for the end at 2 is the empty match
+        // before "a", which needs an indel, but "a" fits with a substitution.
+        let text = utf16_str("caaccca");
+        let matches = search_split_budget(&text, &utf16_str("c"), 1, 0);
+        assert!(matches.iter().any(|m| m.range() == (1..2)));
+    }
+
+    /// Return the lowest total error count of an alignment of `pattern` with
+    /// `segment` within the budgets, by trying every sequence of edits.
+    fn enumerate_alignments(
+        pattern: &[u16],
+        segment: &[u16],
+        max_substitutions: usize,
+        max_indels: usize,
+    ) -> Option<usize> {
+        match (pattern.split_first(), segment.split_first()) {
+            (None, _) | (_, None) => {
+                let indels = pattern.len() + segment.len();
+                (indels <= max_indels).then_some(indels)
+            }
+            (Some((&p, pattern_rest)), Some((&t, segment_rest))) => {
+                let aligned = if p == t {
+                    enumerate_alignments(pattern_rest, segment_rest, max_substitutions, max_indels)
+                } else if max_substitutions > 0 {
+                    enumerate_alignments(
+                        pattern_rest,
+                        segment_rest,
+                        max_substitutions - 1,
+                        max_indels,
+                    )
+                    .map(|errors| errors + 1)
+                } else {
+                    None
+                };
+                let indel = if max_indels > 0 {
+                    let deleted = enumerate_alignments(
+                        pattern_rest,
+                        segment,
+                        max_substitutions,
+                        max_indels - 1,
+                    );
+                    let inserted = enumerate_alignments(
+                        pattern,
+                        segment_rest,
+                        max_substitutions,
+                        max_indels - 1,
+                    );
+                    deleted
+                        .into_iter()
+                        .chain(inserted)
+                        .min()
+                        .map(|errors| errors + 1)
+                } else {
+                    None
+                };
+                aligned.into_iter().chain(indel).min()
+            }
+        }
+    }
+
+    #[test]
+    fn it_matches_split_budgets_by_brute_force() {
+        let mut random = Random::new(5);
+
+        for case in 0..300 {
+            let pattern = random.chars(1 + case % 5, b"abc");
+            let text = random.chars(case % 13, b"abc");
+            let (max_substitutions, max_indels) = (case % 3, (case / 3) % 3);
+
+            // Check every part of the text, keeping the best start for each end.
+            // As in `search_all`, no match ends before the first character.
+            let expected: Vec<Match> = (1..=text.len())
+                .filter_map(|end| {
+                    (0..=end)
+                        .filter_map(|start| {
+                            let errors = enumerate_alignments(
+                                &pattern,
+                                &text[start..end],
+                                max_substitutions,
+                                max_indels,
+                            )?;
+                            Some(Match { start, end, errors })
+                        })
+                        .min_by_key(|m| m.errors)
+                })
+                .collect();
+
+            assert_eq!(
+                search_split_budget(&text, &pattern, max_substitutions as u32, max_indels as u32),
+                expected,
+                "text={:?} pattern={:?} max_substitutions={} max_indels={}",
+                alloc::string::String::from_utf16_lossy(&text),
+                alloc::string::String::from_utf16_lossy(&pattern),
+                max_substitutions,
+                max_indels
+            );
+        }
+    }
+}
@@ -0,0 +1,551 @@
+// This module implements searches which are anchored to the start or end of the
+// text, where characters of the text outside the match count as errors.
+
+use crate::{reverse, BlockWord, ColumnState, Match, MatchOptions, PatternBits};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Find the best approximate match of `pattern` which starts at the beginning
+/// of `text`, allowing up to `max_errors` errors.
+///
+/// Unlike `search`, text characters before the match are not skipped for free,
+/// so the match always has a `start` of zero. If several prefixes of the text
+/// match with the lowest error count, the shortest is returned. Returns `None`
+/// if there is no such match or the pattern is empty.
+pub fn search_prefix(text: &[u16], pattern: &[u16], max_errors: u32) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::new(pattern.len(), max_errors, true);
+    state.anchored = true;
+
+    // The empty prefix matches if every pattern character can be deleted.
+    let mut best = if pattern.len() <= max_errors {
+        Some(Match {
+            start: 0,
+            end: 0,
+            errors: pattern.len(),
+        })
+    } else {
+        None
+    };
+
+    // A prefix which is more than `max_errors` longer than the pattern cannot
+    // match, so the scan can stop there.
+    let max_len = pattern.len() + max_errors;
+
+    for (j, &ch) in text.iter().take(max_len).enumerate() {
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            if best.as_ref().is_none_or(|m| errors < m.errors) {
+                best = Some(Match {
+                    start: 0,
+                    end: j + 1,
+                    errors,
+                });
+            }
+        }
+    }
+
+    best
+}
+
+/// Return the length of the part of `text` which `search_prefix` finds as the
+/// best approximate match of `pattern` at the start of `text`, and its error
+/// count, as `(consumed_len, errors)`.
+///
+/// This is intended for incremental parsing, where the caller continues with
+/// `&text[consumed_len..]`. The consumed length may differ from the length of
+/// the pattern if characters were inserted or deleted. If several lengths have
+/// the lowest error count, the shortest is chosen.
+pub fn fuzzy_strip_prefix(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> Option<(usize, usize)> {
+    search_prefix(text, pattern, max_errors).map(|m| (m.end, m.errors))
+}
+
+/// Find the best approximate match of `pattern` which ends at the end of
+/// `text`, allowing up to `max_errors` errors.
+///
+/// This is the counterpart of `search_prefix`, so the match always has an
+/// `end` of `text.len()`.
+pub fn search_suffix(text: &[u16], pattern: &[u16], max_errors: u32) -> Option<Match> {
+    let m = search_prefix(&reverse(text), &reverse(pattern), max_errors)?;
+    Some(Match {
+        start: text.len() - m.end,
+        end: text.len(),
+        errors: m.errors,
+    })
+}
+
+/// Compute the Levenshtein distance between `a` and `b`.
+///
+/// This is the number of insertions, deletions and substitutions needed to turn
+/// the whole of `a` into the whole of `b`, computed with the same bit-vector
+/// algorithm as `search` but anchored at both ends of `b`.
+pub fn edit_distance(a: &[u16], b: &[u16]) -> usize {
+    if a.is_empty() {
+        return b.len();
+    }
+    if b.is_empty() {
+        return a.len();
+    }
+
+    let bits = PatternBits::<u16, BlockWord>::new(a, MatchOptions::default());
+    distance_with_bits(&bits, a.len(), b)
+}
+
+/// Compute the Levenshtein distance between a non-empty string of length
+/// `a_len`, given its bit vectors, and `b`.
+fn distance_with_bits(bits: &PatternBits<u16, BlockWord>, a_len: usize, b: &[u16]) -> usize {
+    let mut state = ColumnState::new(a_len, a_len, false);
+    state.anchored = true;
+
+    // The distance can be up to `max(a.len(), b.len())`. Raise the threshold
+    // above that so that no blocks are skipped and every column reports its
+    // error count.
+    state.max_errors = (a_len + b.len()) as isize;
+
+    let mut distance = a_len;
+    for &ch in b {
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            distance = errors;
+        }
+    }
+    distance
+}
+
+/// Compute the Levenshtein distance between every pair of `strings`.
+///
+/// Entry `[i][j]` of the result is `edit_distance(strings[i], strings[j])`, so
+/// the matrix is symmetric with zeros on the diagonal. Each string is
+/// preprocessed once, and only the distances for `i < j` are computed, so this
+/// is cheaper than calling `edit_distance` for each pair. For finding only the
+/// pairs which are close together, `near_duplicate_pairs` is much faster.
+pub fn distance_matrix(strings: &[&[u16]]) -> Vec<Vec<usize>> {
+    let n = strings.len();
+    let mut matrix = vec![vec![0; n]; n];
+    for (i, a) in strings.iter().enumerate() {
+        let bits =
+            (!a.is_empty()).then(|| PatternBits::<u16, BlockWord>::new(a, MatchOptions::default()));
+        for (j, b) in strings.iter().enumerate().skip(i + 1) {
+            let distance = match &bits {
+                Some(bits) if !b.is_empty() => distance_with_bits(bits, a.len(), b),
+                _ => a.len().max(b.len()),
+            };
+            matrix[i][j] = distance;
+            matrix[j][i] = distance;
+        }
+    }
+    matrix
+}
+
+/// Find the pairs of `strings` whose Levenshtein distance is at most
+/// `max_errors`.
+///
+/// Each pair is returned as `(i, j, distance)` with `i < j`, in order of `i`
+/// and then `j`. Pairs are compared with `fuzzy_equals`, so pairs whose
+/// lengths differ by more than `max_errors` are skipped without comparing
+/// their contents, and other comparisons stop as soon as the distance is known
+/// to exceed `max_errors`.
+pub fn near_duplicate_pairs(strings: &[&[u16]], max_errors: u32) -> Vec<(usize, usize, usize)> {
+    let mut pairs = Vec::new();
+    for (i, a) in strings.iter().enumerate() {
+        for (j, b) in strings.iter().enumerate().skip(i + 1) {
+            if let Some(distance) = fuzzy_equals(a, b, max_errors) {
+                pairs.push((i, j, distance));
+            }
+        }
+    }
+    pairs
+}
+
+/// Return the Levenshtein distance between `a` and `b` if it is at most
+/// `max_errors`, or `None` otherwise.
+///
+/// This is a whole-string comparison, like `edit_distance`, for checking
+/// whether two strings are near-duplicates. It only computes the part of the
+/// dynamic programming table within `max_errors` of the diagonal, and stops as
+/// soon as the distance is known to exceed `max_errors`, so it takes O(k * n)
+/// time where `k` is `max_errors` and `n` is the length of `a`. In particular
+/// it returns immediately if the lengths of `a` and `b` differ by more than
+/// `max_errors`.
+pub fn fuzzy_equals(a: &[u16], b: &[u16], max_errors: u32) -> Option<usize> {
+    let k = max_errors as usize;
+    if a.len().abs_diff(b.len()) > k {
+        return None;
+    }
+
+    // Distances above `k` are stored as `k + 1`, including for cells outside
+    // the band, which are never computed.
+    let over = k + 1;
+
+    // `prev[j]` and `row[j]` are the distances between `b[..j]` and the prefix
+    // of `a` before and after the current character.
+    let mut prev: Vec<usize> = (0..=b.len()).map(|j| j.min(over)).collect();
+    let mut row = prev.clone();
+
+    for (i, &ch) in a.iter().enumerate() {
+        let i = i + 1;
+        let lo = i.saturating_sub(k);
+        let hi = (i + k).min(b.len());
+
+        if lo == 0 {
+            row[0] = i.min(over);
+        } else {
+            row[lo - 1] = over;
+        }
+
+        // Lowest distance which a path through this row could have at the
+        // end. Each cell is at least as far from the end in one string as the
+        // other, and closing that gap needs one insertion or deletion per
+        // character.
+        let mut lower_bound = over;
+        for j in lo.max(1)..=hi {
+            let substitute = prev[j - 1] + (ch != b[j - 1]) as usize;
+            let distance = substitute.min(prev[j] + 1).min(row[j - 1] + 1).min(over);
+            row[j] = distance;
+
+            let remaining = (a.len() - i).abs_diff(b.len() - j);
+            lower_bound = lower_bound.min(distance + remaining);
+        }
+        if lo == 0 {
+            lower_bound = lower_bound.min(row[0] + (a.len() - i).abs_diff(b.len()));
+        }
+        if lower_bound > k {
+            return None;
+        }
+
+        core::mem::swap(&mut prev, &mut row);
+    }
+
+    let distance = prev[b.len()];
+    (distance <= k).then_some(distance)
+}
+
+/// Rank `candidates` by how closely they match `query`, for autocompletion.
+///
+/// The similarity of a candidate is `1.0 - errors / query.len()`, as with
+/// `best_similarity`. If `prefix_only` is false, `errors` is the edit distance
+/// between the query and the whole candidate. If it is true, it is the error
+/// count of the best match of the query against a prefix of the candidate, as
+/// with `search_prefix`, so that eg. "autoc" fully matches "autocomplete".
+///
+/// Candidates with a similarity below `min_similarity` are dropped. The rest
+/// are returned as `(index, similarity)` pairs, sorted by decreasing similarity
+/// and then by index. Returns an empty list if the query is empty, or if
+/// `min_similarity` is greater than 1.0 or NaN.
+///
+/// The query is preprocessed once for all candidates. `min_similarity`
+/// determines an error threshold, which serves as a cutoff for each
+/// candidate: in whole-string mode, candidates whose length differs from the
+/// query's by more than the threshold are skipped without being scanned, and
+/// in both modes only the part of the query within the threshold is processed
+/// for each character, as in a search.
+pub fn rank_candidates(
+    query: &[u16],
+    candidates: &[&[u16]],
+    min_similarity: f64,
+    prefix_only: bool,
+) -> Vec<(usize, f64)> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let max_errors = crate::max_errors_for_ratio(query.len(), 1.0 - min_similarity) as usize;
+    let bits = PatternBits::<u16, BlockWord>::new(query, MatchOptions::default());
+    let mut state = ColumnState::new(query.len(), max_errors, prefix_only);
+
+    let mut ranked = Vec::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        if !prefix_only && query.len().abs_diff(candidate.len()) > max_errors {
+            continue;
+        }
+        state.reset(query.len(), max_errors, prefix_only);
+        state.anchored = true;
+
+        // No characters of the candidate are needed if every query character
+        // can be deleted.
+        let mut errors = (query.len() <= max_errors).then_some(query.len());
+        if prefix_only {
+            // A prefix which is more than `max_errors` longer than the query
+            // cannot match. The ratchet means that each error count reported
+            // is at least as good as the previous one.
+            for &ch in candidate.iter().take(query.len() + max_errors) {
+                if let Some(e) = state.advance(bits.get(ch), &mut ()) {
+                    if errors.is_none_or(|best| e < best) {
+                        errors = Some(e);
+                    }
+                }
+            }
+        } else {
+            for &ch in candidate.iter() {
+                errors = state.advance(bits.get(ch), &mut ());
+            }
+        }
+
+        if let Some(errors) = errors {
+            // The error threshold allows any error count within 1e-9 of the
+            // limit, and is 0 rather than negative when `min_similarity` is
+            // more than 1.0 or NaN, so the similarity is checked as well.
+            let similarity = 1.0 - errors as f64 / query.len() as f64;
+            if similarity >= min_similarity - 1e-9 {
+                ranked.push((index, similarity));
+            }
+        }
+    }
+
+    ranked.sort_by(|(i_a, sim_a), (i_b, sim_b)| sim_b.total_cmp(sim_a).then(i_a.cmp(i_b)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{
+        distance_matrix, edit_distance, fuzzy_equals, fuzzy_strip_prefix, near_duplicate_pairs,
+        rank_candidates, search_prefix, search_suffix,
+    };
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    /// Compute the lowest edit distance between `pattern` and a prefix of
+    /// `text` using the classical dynamic programming algorithm.
+    fn naive_prefix_distance(text: &[u16], pattern: &[u16]) -> (usize, usize) {
+        let mut col: Vec<usize> = (0..=pattern.len()).collect();
+        let mut best = (col[pattern.len()], 0);
+        for (j, &t) in text.iter().enumerate() {
+            let mut next = vec![j + 1; pattern.len() + 1];
+            for i in 1..=pattern.len() {
+                let sub = col[i - 1] + if pattern[i - 1] == t { 0 } else { 1 };
+                next[i] = sub.min(col[i] + 1).min(next[i - 1] + 1);
+            }
+            col = next;
+            if col[pattern.len()] < best.0 {
+                best = (col[pattern.len()], j + 1);
+            }
+        }
+        best
+    }
+
+    #[test]
+    fn it_ranks_autocomplete_candidates() {
+        let candidates: Vec<Vec<u16>> = ["commit", "comet", "checkout", "cherry-pick", "comit"]
+            .iter()
+            .map(|s| utf16_str(s))
+            .collect();
+        let candidates: Vec<&[u16]> = candidates.iter().map(|c| c.as_slice()).collect();
+        let query = utf16_str("commit");
+
+        assert_eq!(
+            rank_candidates(&query, &candidates, 0.5, false),
+            vec![(0, 1.0), (4, 1.0 - 1.0 / 6.0), (1, 1.0 - 2.0 / 6.0)]
+        );
+
+        // In prefix mode, a partially typed query matches the start of longer
+        // candidates.
+        let query = utf16_str("che");
+        assert_eq!(
+            rank_candidates(&query, &candidates, 0.3, true),
+            vec![
+                (2, 1.0),
+                (3, 1.0),
+                (0, 1.0 - 2.0 / 3.0),
+                (1, 1.0 - 2.0 / 3.0),
+                (4, 1.0 - 2.0 / 3.0)
+            ]
+        );
+        assert!(rank_candidates(&query, &candidates, 0.8, false).is_empty());
+        assert!(rank_candidates(&[], &candidates, 0.0, true).is_empty());
+        assert!(rank_candidates(&query, &candidates, 1.5, true).is_empty());
+        assert!(rank_candidates(&query, &candidates, f64::NAN, true).is_empty());
+
+        // The results are the same as computing each similarity directly.
+        for prefix_only in [false, true] {
+            for (index, similarity) in rank_candidates(&query, &candidates, 0.0, prefix_only) {
+                let errors = if prefix_only {
+                    search_prefix(candidates[index], &query, 3)
+                        .unwrap()
+                        .errors()
+                } else {
+                    edit_distance(&query, candidates[index])
+                };
+                assert_eq!(similarity, 1.0 - errors as f64 / 3.0);
+            }
+        }
+    }
+
+    #[test]
+    fn it_strips_fuzzy_prefix() {
+        // The prefix has one inserted character.
+        let text = utf16_str("SELEECT name FROM users");
+        let (consumed, errors) = fuzzy_strip_prefix(&text, &utf16_str("SELECT"), 1).unwrap();
+        assert_eq!((consumed, errors), (7, 1));
+        assert_eq!(text[consumed..], utf16_str(" name FROM users"));
+
+        assert_eq!(fuzzy_strip_prefix(&text, &utf16_str("FROM"), 1), None);
+    }
+
+    #[test]
+    fn it_finds_prefix_match() {
+        let text = utf16_str("comit -m message");
+
+        let m = search_prefix(&text, &utf16_str("commit"), 1).unwrap();
+        assert_eq!(m.range(), 0..5);
+        assert_eq!(m.errors(), 1);
+
+        // "message" occurs in the text, but not at the start.
+        assert_eq!(search_prefix(&text, &utf16_str("message"), 2), None);
+        assert_eq!(search_prefix(&text, &[], 2), None);
+    }
+
+    #[test]
+    fn it_matches_naive_prefix_distance() {
+        let long_pattern: Vec<u16> = (0..150).map(|i| b"abcde"[i % 5] as u16).collect();
+        let mut long_text = long_pattern.clone();
+        long_text.remove(70);
+        long_text[100] = 'x' as u16;
+        long_text.extend(utf16_str("abc"));
+
+        let cases = [
+            (utf16_str("xhello world"), utf16_str("hello")),
+            (utf16_str("helo"), utf16_str("hello")),
+            (utf16_str("ab"), utf16_str("abcd")),
+            (long_text, long_pattern),
+        ];
+
+        for (text, pattern) in cases.iter() {
+            let (errors, end) = naive_prefix_distance(text, pattern);
+            for max_errors in 0..4 {
+                let expected = if errors <= max_errors {
+                    Some((0..end, errors))
+                } else {
+                    None
+                };
+                let actual = search_prefix(text, pattern, max_errors as u32)
+                    .map(|m| (m.range(), m.errors()));
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_suffix_match() {
+        let text = utf16_str("report.pdv");
+
+        let m = search_suffix(&text, &utf16_str(".pdf"), 1).unwrap();
+        assert_eq!(m.range(), 6..10);
+        assert_eq!(m.errors(), 1);
+
+        assert_eq!(search_suffix(&text, &utf16_str(".pdf"), 0), None);
+        assert_eq!(search_suffix(&text, &utf16_str("report"), 2), None);
+    }
+
+    #[test]
+    fn it_computes_edit_distance() {
+        let long_a: Vec<u16> = (0..200).map(|i| (i % 7) as u16).collect();
+        let mut long_b = long_a.clone();
+        long_b.remove(10);
+        long_b[120] = 99;
+        long_b.extend_from_slice(&[1, 2, 3]);
+
+        let cases = [
+            ("kitten", "sitting", 3),
+            ("flaw", "lawn", 2),
+            ("abc", "abc", 0),
+            ("", "abc", 3),
+            ("abc", "", 3),
+            ("", "", 0),
+            ("a", "bbbbbbbb", 8),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(edit_distance(&utf16_str(a), &utf16_str(b)), expected);
+        }
+        assert_eq!(edit_distance(&long_a, &long_b), 5);
+    }
+
+    #[test]
+    fn it_checks_fuzzy_equality() {
+        // "kitten" and "sitting" are 3 edits apart.
+        let a = utf16_str("kitten");
+        let b = utf16_str("sitting");
+        assert_eq!(fuzzy_equals(&a, &b, 3), Some(3));
+        assert_eq!(fuzzy_equals(&a, &b, 4), Some(3));
+        assert_eq!(fuzzy_equals(&a, &b, 2), None);
+
+        // Lengths which differ by more than the budget.
+        assert_eq!(fuzzy_equals(&utf16_str("a"), &utf16_str("abcd"), 2), None);
+        assert_eq!(fuzzy_equals(&[], &utf16_str("ab"), 2), Some(2));
+        assert_eq!(fuzzy_equals(&[], &[], 0), Some(0));
+
+        let words = ["", "a", "ab", "ba", "abc", "acb", "bca", "abcabc", "cabcab"];
+        for a in words {
+            for b in words {
+                let (a, b) = (utf16_str(a), utf16_str(b));
+                let distance = edit_distance(&a, &b);
+                for k in 0..7 {
+                    let expected = Some(distance).filter(|&d| d <= k as usize);
+                    assert_eq!(fuzzy_equals(&a, &b, k), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_dissimilar_strings_early() {
+        // These would take around 10^10 steps to compare in full. The length
+        // difference rejects the first pair before anything is allocated, and
+        // the second is rejected once the first few rows of the band have
+        // been computed.
+        let a = vec![b'a' as u16; 100_000];
+        let b = vec![b'b' as u16; 100_000];
+        assert_eq!(fuzzy_equals(&a, &b[..99_000], 3), None);
+        assert_eq!(fuzzy_equals(&a, &b, 3), None);
+
+        // Near the budget, the result is still exact.
+        let mut c = a.clone();
+        c[10] = b'x' as u16;
+        c[50_000] = b'y' as u16;
+        c.push(b'z' as u16);
+        assert_eq!(fuzzy_equals(&a, &c, 3), Some(3));
+        assert_eq!(fuzzy_equals(&a, &c, 2), None);
+    }
+
+    #[test]
+    fn it_computes_distance_matrix() {
+        let words: Vec<Vec<u16>> = ["kitten", "sitting", "", "mitten"]
+            .iter()
+            .map(|w| utf16_str(w))
+            .collect();
+        let words: Vec<&[u16]> = words.iter().map(|w| w.as_slice()).collect();
+
+        let matrix = distance_matrix(&words);
+
+        for (i, a) in words.iter().enumerate() {
+            for (j, b) in words.iter().enumerate() {
+                assert_eq!(matrix[i][j], edit_distance(a, b));
+            }
+        }
+        assert!(distance_matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn it_finds_near_duplicate_pairs() {
+        let words: Vec<Vec<u16>> = ["kitten", "sitting", "mitten", "kitchen", "dog"]
+            .iter()
+            .map(|w| utf16_str(w))
+            .collect();
+        let words: Vec<&[u16]> = words.iter().map(|w| w.as_slice()).collect();
+
+        assert_eq!(near_duplicate_pairs(&words, 2), [(0, 2, 1), (0, 3, 2)]);
+        assert_eq!(near_duplicate_pairs(&words, 0), []);
+    }
+}
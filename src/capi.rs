@@ -0,0 +1,107 @@
+// This module defines a C API for native (non-WASM) programs.
+//
+// Unlike the WASM API, this does not allocate memory on the caller's behalf or
+// set a global allocator. Results are written into buffers provided by the
+// caller.
+
+use crate::search_impl;
+
+/// A match returned by `asm_search`, with the same fields as `Match`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AsmMatch {
+    /// Offset of the first UTF-16 code unit of the match in the text.
+    pub start: usize,
+    /// Offset of the code unit after the last code unit of the match.
+    pub end: usize,
+    /// Number of errors in the match.
+    pub errors: usize,
+}
+
+/// Create a slice from a pointer and length, allowing a null pointer if the
+/// length is zero.
+unsafe fn slice_from_raw<'a>(ptr: *const u16, len: usize) -> &'a [u16] {
+    if len == 0 {
+        &[]
+    } else {
+        core::slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Search for approximate matches of a pattern in a text allowing up to
+/// `max_errors` errors, as `search` does.
+///
+/// Up to `out_cap` matches are written to `out`. The return value is the total
+/// number of matches, which may be greater than `out_cap`, in which case the
+/// search can be repeated with a larger buffer to get all of them.
+///
+/// # Safety
+///
+/// `text` and `pat` must point to `text_len` and `pat_len` valid UTF-16 code
+/// units respectively, and `out` must point to space for `out_cap` matches.
+/// Pointers may be null if the corresponding length is zero.
+#[no_mangle]
+pub unsafe extern "C" fn asm_search(
+    text: *const u16,
+    text_len: usize,
+    pat: *const u16,
+    pat_len: usize,
+    max_errors: u32,
+    out: *mut AsmMatch,
+    out_cap: usize,
+) -> usize {
+    let text = slice_from_raw(text, text_len);
+    let pat = slice_from_raw(pat, pat_len);
+    let matches = search_impl(text, pat, max_errors);
+    for (i, m) in matches.iter().take(out_cap).enumerate() {
+        out.add(i).write(AsmMatch {
+            start: m.start,
+            end: m.end,
+            errors: m.errors,
+        });
+    }
+    matches.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{asm_search, AsmMatch};
+
+    #[test]
+    fn it_writes_matches_to_buffer() {
+        let text: Vec<u16> = "a cat, a cat, a cat".encode_utf16().collect();
+        let pattern: Vec<u16> = "cat".encode_utf16().collect();
+        let search = |out: &mut [AsmMatch]| unsafe {
+            asm_search(
+                text.as_ptr(),
+                text.len(),
+                pattern.as_ptr(),
+                pattern.len(),
+                0,
+                out.as_mut_ptr(),
+                out.len(),
+            )
+        };
+
+        let mut out = vec![AsmMatch::default(); 4];
+        assert_eq!(search(&mut out), 3);
+        assert_eq!(
+            out[0],
+            AsmMatch {
+                start: 2,
+                end: 5,
+                errors: 0
+            }
+        );
+        assert_eq!(out[2].start, 16);
+        assert_eq!(out[3], AsmMatch::default());
+
+        // If the buffer is too small, the total count is still returned.
+        let mut out = vec![AsmMatch::default(); 1];
+        assert_eq!(search(&mut out), 3);
+        assert_eq!((out[0].start, out[0].end), (2, 5));
+    }
+}
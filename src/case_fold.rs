@@ -0,0 +1,140 @@
+// This module implements case-insensitive matching using Unicode case folding.
+
+use alloc::vec::Vec;
+use caseless::Caseless;
+
+use crate::offsets::dedup_ranges;
+use crate::{search, Match};
+
+/// UTF-16 text which has been case folded, along with a mapping from offsets in
+/// the folded text back to offsets in the original text.
+struct FoldedText {
+    chars: Vec<u16>,
+
+    // For each code unit in `chars`, the offsets of the start and end of the
+    // character in the original text which it was produced from.
+    source_start: Vec<usize>,
+    source_end: Vec<usize>,
+}
+
+impl FoldedText {
+    fn new(text: &[u16]) -> FoldedText {
+        let mut folded = FoldedText {
+            chars: Vec::with_capacity(text.len()),
+            source_start: Vec::with_capacity(text.len()),
+            source_end: Vec::with_capacity(text.len()),
+        };
+
+        let mut pos = 0;
+        for ch in char::decode_utf16(text.iter().cloned()) {
+            match ch {
+                Ok(ch) => {
+                    let end = pos + ch.len_utf16();
+                    let mut buf = [0; 2];
+                    for folded_ch in core::iter::once(ch).default_case_fold() {
+                        for unit in folded_ch.encode_utf16(&mut buf) {
+                            folded.push(*unit, pos, end);
+                        }
+                    }
+                    pos = end;
+                }
+                Err(err) => {
+                    // Lone surrogates are passed through unchanged.
+                    folded.push(err.unpaired_surrogate(), pos, pos + 1);
+                    pos += 1;
+                }
+            }
+        }
+
+        folded
+    }
+
+    fn push(&mut self, unit: u16, start: usize, end: usize) {
+        self.chars.push(unit);
+        self.source_start.push(start);
+        self.source_end.push(end);
+    }
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, ignoring differences in case.
+///
+/// Both the text and the pattern are converted using Unicode default
+/// (non-Turkic) case folding before matching. Folding can expand one character
+/// into several, for example "ß" folds to "ss", so "STRASSE" matches "straße"
+/// exactly. Error counts are measured in the folded text, and match offsets are
+/// mapped back to the original `text` such that a match never covers only part
+/// of a character which expanded when folded. Where several matches in the
+/// folded text map to the same part of `text`, only the one with the fewest
+/// errors is returned.
+pub fn search_unicode_case_insensitive(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> Vec<Match> {
+    let folded_text = FoldedText::new(text);
+    let folded_pattern = FoldedText::new(pattern);
+
+    let mut matches: Vec<Match> = search(&folded_text.chars, &folded_pattern.chars, max_errors)
+        .into_iter()
+        .map(|m| Match {
+            start: folded_text
+                .source_start
+                .get(m.start)
+                .cloned()
+                .unwrap_or(text.len()),
+            end: if m.end > 0 {
+                folded_text.source_end[m.end - 1]
+            } else {
+                0
+            },
+            errors: m.errors,
+        })
+        .collect();
+
+    dedup_ranges(&mut matches);
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::search_unicode_case_insensitive;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_matches_expanded_case_folds() {
+        let text = utf16_str("Die Straße ist lang");
+        let pattern = utf16_str("STRASSE");
+
+        let matches = search_unicode_case_insensitive(&text, &pattern, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 4..10);
+        assert_eq!(matches[0].errors(), 0);
+    }
+
+    #[test]
+    fn it_folds_non_ascii_letters() {
+        let text = utf16_str("ΟΔΥΣΣΕΥΣ");
+        let pattern = utf16_str("οδυσσευς");
+
+        let matches = search_unicode_case_insensitive(&text, &pattern, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 0..text.len());
+    }
+
+    #[test]
+    fn it_reports_expanded_characters_once() {
+        // Both "s" characters which "ß" folds to match, but they map back to
+        // the same character.
+        let matches = search_unicode_case_insensitive(&utf16_str("Maße"), &utf16_str("s"), 0);
+        let ranges: Vec<_> = matches.iter().map(|m| (m.range(), m.errors())).collect();
+        assert_eq!(ranges, [(2..3, 0)]);
+    }
+}
@@ -0,0 +1,475 @@
+// This module implements approximate matching using the classical dynamic
+// programming solution to the problem, which computes the full error count
+// table one column at a time.
+//
+// This is much slower than the bit-vector algorithm in `lib.rs`, but is easily
+// extended to cost models which the bit-vector algorithm cannot express. To
+// limit the work done, each column is only computed down to one row past the
+// last row whose cost is within the threshold ("Ukkonen's cutoff"), since any
+// rows beyond that are guaranteed to exceed it.
+
+use crate::{search, Match, Report};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Costs of each kind of edit for `search_weighted`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EditCosts {
+    /// Cost of a character in the text which is not in the pattern.
+    pub insert: u32,
+
+    /// Cost of a character in the pattern which is missing from the text.
+    pub delete: u32,
+
+    /// Cost of a character in the pattern being replaced by a different
+    /// character in the text.
+    pub substitute: u32,
+}
+
+impl Default for EditCosts {
+    fn default() -> EditCosts {
+        EditCosts {
+            insert: 1,
+            delete: 1,
+            substitute: 1,
+        }
+    }
+}
+
+/// Costs of each kind of edit used by `dp_search`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DpCosts<'a> {
+    // Cost of a character in the text which is not in the pattern.
+    pub insert: usize,
+
+    // Cost of a character in the pattern which is not in the text.
+    pub delete: usize,
+
+    // Cost of a character in the pattern being replaced by a different one.
+    pub substitute: usize,
+
+    // Cost of two adjacent characters in the pattern appearing in the opposite
+    // order in the text, or `None` to disallow transpositions.
+    pub transpose: Option<usize>,
+
+    // If set, characters in the text which are not in the pattern are free
+    // when they are between two pattern characters, rather than costing
+    // `insert`.
+    pub free_gaps: bool,
+
+    // Multiplier for the cost of substituting or deleting each pattern
+    // character, or `None` to weight every character equally.
+    pub position_weights: Option<&'a [u32]>,
+}
+
+impl Default for DpCosts<'_> {
+    fn default() -> Self {
+        DpCosts {
+            insert: 1,
+            delete: 1,
+            substitute: 1,
+            transpose: None,
+            free_gaps: false,
+            position_weights: None,
+        }
+    }
+}
+
+// Value used for cells of the table which exceed the cost threshold.
+const INF: usize = usize::MAX / 2;
+
+/// A cell in the table. `start` is the offset in the text where the cheapest
+/// alignment ending at this cell begins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Cell {
+    cost: usize,
+    start: usize,
+}
+
+const INF_CELL: Cell = Cell {
+    cost: INF,
+    start: 0,
+};
+
+fn extend(cell: Cell, cost: usize) -> Cell {
+    Cell {
+        cost: cell.cost + cost,
+        start: cell.start,
+    }
+}
+
+/// Return the cell with the lower cost, or if they have the same cost, the one
+/// with the earlier start, or the later start if `latest_start` is set.
+fn cheapest(a: Cell, b: Cell, latest_start: bool) -> Cell {
+    if a.cost != b.cost {
+        return if a.cost < b.cost { a } else { b };
+    }
+    if (a.start > b.start) == latest_start {
+        a
+    } else {
+        b
+    }
+}
+
+/// Search for approximate matches of `pattern` in `text` with a total edit cost
+/// of at most `max_cost`.
+///
+/// `report` determines whether only the matches with the lowest cost are
+/// returned, as with `search`, or all matches within the threshold. Where
+/// several alignments end at the same position, the one with the earliest start
+/// is chosen, unless `costs.free_gaps` is set, in which case the latest is.
+pub(crate) fn dp_search<C: Copy + PartialEq>(
+    text: &[C],
+    pattern: &[C],
+    max_cost: usize,
+    costs: DpCosts,
+    report: Report,
+) -> Vec<Match> {
+    let m = pattern.len();
+    if m == 0 {
+        return Vec::new();
+    }
+
+    // Columns for the previous two text positions and the current one.
+    let mut prev2 = vec![INF_CELL; m + 1];
+    let mut prev = vec![INF_CELL; m + 1];
+    let mut cur = vec![INF_CELL; m + 1];
+
+    // Multiplier for edits of the pattern character at row `i`.
+    let weight = |i: usize| costs.position_weights.map_or(1, |w| w[i - 1] as usize);
+
+    // Initial column, where all pattern chars are deleted.
+    let mut last_active = 0;
+    let mut cost = 0;
+    for (i, cell) in prev.iter_mut().enumerate() {
+        if i > 0 {
+            cost += costs.delete * weight(i);
+        }
+        if cost > max_cost {
+            break;
+        }
+        *cell = Cell { cost, start: 0 };
+        last_active = i;
+    }
+    let mut prev_last_active = last_active;
+
+    let mut best = max_cost;
+    let mut matches = Vec::new();
+
+    // With free gaps, alignments can start arbitrarily early, so prefer the
+    // shortest.
+    let latest_start = costs.free_gaps;
+
+    for j in 1..=text.len() {
+        // The cutoff relies on each edit of a pattern character costing the
+        // same, so with position weights every row is computed.
+        let limit = if costs.position_weights.is_some() {
+            m
+        } else {
+            m.min(last_active + 1).max(if costs.transpose.is_some() {
+                m.min(prev_last_active + 2)
+            } else {
+                0
+            })
+        };
+
+        cur[0] = Cell { cost: 0, start: j };
+        let mut new_last_active = 0;
+        for i in 1..=limit {
+            let sub_cost = if pattern[i - 1] == text[j - 1] {
+                0
+            } else {
+                costs.substitute * weight(i)
+            };
+            let insert_cost = if costs.free_gaps && i < m {
+                0
+            } else {
+                costs.insert
+            };
+            let mut cell = cheapest(
+                cheapest(
+                    extend(prev[i - 1], sub_cost),
+                    extend(prev[i], insert_cost),
+                    latest_start,
+                ),
+                extend(cur[i - 1], costs.delete * weight(i)),
+                latest_start,
+            );
+
+            if let Some(transpose) = costs.transpose {
+                if i > 1 && j > 1 && pattern[i - 1] == text[j - 2] && pattern[i - 2] == text[j - 1]
+                {
+                    cell = cheapest(cell, extend(prev2[i - 2], transpose), latest_start);
+                }
+            }
+
+            if cell.cost > best {
+                cell = INF_CELL;
+            } else {
+                new_last_active = i;
+            }
+            cur[i] = cell;
+        }
+        for cell in cur.iter_mut().skip(limit + 1) {
+            *cell = INF_CELL;
+        }
+
+        let end = cur[m];
+        if end.cost <= best {
+            if report == Report::Best {
+                if end.cost < best {
+                    // Discard any earlier, worse matches.
+                    matches.clear();
+                }
+                best = end.cost;
+            }
+            matches.push(Match {
+                start: end.start,
+                end: j,
+                errors: end.cost,
+            });
+        }
+
+        prev_last_active = last_active;
+        last_active = new_last_active;
+        core::mem::swap(&mut prev2, &mut prev);
+        core::mem::swap(&mut prev, &mut cur);
+    }
+
+    matches
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, where swapping two adjacent characters counts as a single
+/// error.
+///
+/// The standard search counts a transposition such as "teh" for "the" as two
+/// errors. This variant uses the "optimal string alignment" form of the
+/// Damerau-Levenshtein distance, in which each transposed pair can not be
+/// further edited.
+///
+/// Transpositions are not supported by the bit-vector algorithm, so this uses the
+/// classical dynamic programming algorithm instead. Expect it to be
+/// substantially slower than `search`, especially for long patterns.
+pub fn search_damerau(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let costs = DpCosts {
+        transpose: Some(1),
+        ..DpCosts::default()
+    };
+    dp_search(text, pattern, max_errors, costs, Report::Best)
+}
+
+/// Search for approximate matches of `pattern` in `text` where each kind of edit
+/// has a different cost, allowing a total cost of up to `max_cost`.
+///
+/// Only the matches with the lowest total cost are returned, and the `errors` of
+/// each match is its total cost.
+///
+/// When all costs are 1 this is the same as `search` and uses the fast
+/// bit-vector algorithm. Otherwise the classical dynamic programming algorithm
+/// is used, which takes O(m * n) time in the worst case for a pattern of length
+/// `m` and a text of length `n`, compared with O(m/w * n) for the bit-vector
+/// algorithm. Low values of `max_cost` reduce the cost of both.
+pub fn search_weighted(
+    text: &[u16],
+    pattern: &[u16],
+    max_cost: u32,
+    costs: EditCosts,
+) -> Vec<Match> {
+    if costs == EditCosts::default() {
+        return search(text, pattern, max_cost);
+    }
+
+    let costs = DpCosts {
+        insert: costs.insert as usize,
+        delete: costs.delete as usize,
+        substitute: costs.substitute as usize,
+        transpose: None,
+        free_gaps: false,
+        position_weights: None,
+    };
+    dp_search(text, pattern, max_cost as usize, costs, Report::Best)
+}
+
+/// Search for approximate matches of `pattern` in `text` where errors at some
+/// positions of the pattern cost more than at others, allowing a total cost of
+/// up to `max_cost`.
+///
+/// Substituting or deleting `pattern[i]` costs `weights[i]`, and inserting a
+/// character into the text costs 1. For example, in name matching, giving the
+/// first few characters a higher weight ranks "Jonson" above "Bohnson" as a
+/// match for "Johnson". Only the matches with the lowest total cost are
+/// returned, and the `errors` of each match is its total cost.
+///
+/// This cost model is not supported by the bit-vector algorithm, so this uses
+/// the classical dynamic programming algorithm. Unlike the other searches
+/// which use it, the table cannot be cut off below the last row within the
+/// threshold, so this always takes O(m * n) time for a pattern of length `m`
+/// and a text of length `n`.
+///
+/// # Panics
+///
+/// Panics if `weights` and `pattern` have different lengths.
+pub fn search_positional_weights(
+    text: &[u16],
+    pattern: &[u16],
+    weights: &[u32],
+    max_cost: u32,
+) -> Vec<Match> {
+    assert_eq!(
+        weights.len(),
+        pattern.len(),
+        "each pattern character must have a weight"
+    );
+    let costs = DpCosts {
+        position_weights: Some(weights),
+        ..DpCosts::default()
+    };
+    dp_search(text, pattern, max_cost as usize, costs, Report::Best)
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, where text characters between two characters of the
+/// pattern are skipped for free.
+///
+/// This finds the pattern "spread out" in the text, eg. "nyc" matches "new
+/// york c" with no errors. Substituting a pattern character or deleting it
+/// from the text costs one error each, as with `search`, while any number of
+/// text characters may be inserted between the first and last pattern
+/// characters at no cost. Inserting text characters before the first or after
+/// the last pattern character would just give a longer match with the same or
+/// more errors, so a match always starts and ends with a pattern character
+/// unless it is substituted.
+///
+/// With no errors this is the same as finding the pattern as a subsequence of
+/// the text. Since a match can be arbitrarily long, its start is the latest
+/// that gives the lowest error count, which may differ from `search`. This
+/// cost model is not supported by the bit-vector algorithm, so this uses the
+/// classical dynamic programming algorithm, which takes O(m * n) time in the
+/// worst case for a pattern of length `m` and a text of length `n`.
+pub fn search_gapped(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let costs = DpCosts {
+        free_gaps: true,
+        ..DpCosts::default()
+    };
+    dp_search(text, pattern, max_errors, costs, Report::Best)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{
+        dp_search, search_damerau, search_gapped, search_positional_weights, search_weighted,
+        DpCosts, EditCosts,
+    };
+    use crate::{search, Report};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_finds_same_matches_as_bit_vector_search() {
+        let text = utf16_str("Escaping double-quotes can be cumbersome in some cases such as writing regular expressions or defining a JSON object as a string literal");
+        for pattern in ["reglar expressions", "some cas", "strng litteral", "xyz"] {
+            let pattern = utf16_str(pattern);
+            for max_errors in 0..4 {
+                let expected = search(&text, &pattern, max_errors);
+                let actual = dp_search(
+                    &text,
+                    &pattern,
+                    max_errors as usize,
+                    DpCosts::default(),
+                    Report::Best,
+                );
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn it_counts_transpositions_as_one_error() {
+        let text = utf16_str("see teh cat");
+        let pattern = utf16_str("the cat");
+
+        assert_eq!(search(&text, &pattern, 2)[0].errors(), 2);
+
+        let matches = search_damerau(&text, &pattern, 2);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].errors(), 1);
+        assert_eq!(matches[0].range(), 4..11);
+    }
+
+    #[test]
+    fn it_applies_custom_edit_costs() {
+        let text = utf16_str("dog cxt dog");
+        let pattern = utf16_str("cat");
+        let costs = EditCosts {
+            insert: 1,
+            delete: 1,
+            substitute: 3,
+        };
+
+        assert_eq!(
+            search_weighted(&text, &pattern, 3, EditCosts::default())[0].errors(),
+            1
+        );
+
+        // The substitution is more expensive than deleting the pattern's "a" and
+        // inserting the text's "x".
+        let matches = search_weighted(&text, &pattern, 3, costs);
+        assert!(!matches.is_empty());
+        assert!(matches.iter().all(|m| m.errors() == 2));
+        assert!(matches.iter().any(|m| m.range() == (4..7)));
+    }
+
+    #[test]
+    fn it_skips_gaps_in_text_for_free() {
+        let text = utf16_str("in new york city");
+        let ranges = |pattern: &str, max_errors| -> Vec<_> {
+            search_gapped(&text, &utf16_str(pattern), max_errors)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        assert_eq!(ranges("nyc", 0), vec![(3..13, 0)]);
+        assert_eq!(ranges("nyk", 1), vec![(3..11, 0)]);
+
+        // Substitutions and deletions still count.
+        assert_eq!(ranges("nxc", 1), vec![(3..13, 1)]);
+        assert_eq!(ranges("nyz", 0), vec![]);
+        assert!(search(&text, utf16_str("nyc"), 1).is_empty());
+    }
+
+    #[test]
+    fn it_weights_errors_by_pattern_position() {
+        let pattern = utf16_str("johnson");
+        let ranges = |text: &str, weights: &[u32], max_cost| -> Vec<_> {
+            search_positional_weights(&utf16_str(text), &pattern, weights, max_cost)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        // With equal weights this is the same as `search`.
+        let text = "bohnson, jonson";
+        let ones = [1; 7];
+        let expected: Vec<_> = search(utf16_str(text), &pattern, 1)
+            .iter()
+            .map(|m| (m.range(), m.errors()))
+            .collect();
+        assert_eq!(ranges(text, &ones, 1), expected);
+
+        // An error in the first character costs more than one later on.
+        let weights = [3, 2, 2, 1, 1, 1, 1];
+        assert_eq!(ranges(text, &weights, 3), vec![(9..15, 2)]);
+        assert_eq!(ranges("bohnson", &weights, 3), vec![(0..7, 3)]);
+        assert!(ranges("bohnson", &weights, 2).is_empty());
+    }
+}
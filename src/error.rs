@@ -0,0 +1,113 @@
+// This module defines the error types returned by the fallible search functions.
+
+use core::fmt;
+
+use crate::LimitedMatches;
+
+/// Reasons why the inputs to a search are invalid.
+///
+/// This is returned by `try_search` and `try_search_with`. There is no limit on
+/// the length of patterns, so that is never reported as an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SearchError {
+    /// The pattern is empty, so it cannot match anything.
+    EmptyPattern,
+
+    /// `max_errors` exceeds the length of the pattern. The infallible search
+    /// functions clamp it to the pattern length.
+    MaxErrorsExceedsPatternLength { max_errors: u32, pattern_len: usize },
+
+    /// `max_errors` equals the length of the pattern, so every position in the
+    /// text matches by deleting the whole pattern. This is only reported when
+    /// enabled with `SearchOptions::reject_trivial_threshold`.
+    TrivialThreshold { pattern_len: usize },
+}
+
+impl SearchError {
+    /// Check the inputs to a search, returning the first problem found.
+    pub(crate) fn check(pattern: &[u16], max_errors: u32) -> Result<(), SearchError> {
+        if pattern.is_empty() {
+            return Err(SearchError::EmptyPattern);
+        }
+        if max_errors as usize > pattern.len() {
+            return Err(SearchError::MaxErrorsExceedsPatternLength {
+                max_errors,
+                pattern_len: pattern.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SearchError::EmptyPattern => write!(f, "pattern is empty"),
+            SearchError::MaxErrorsExceedsPatternLength {
+                max_errors,
+                pattern_len,
+            } => write!(
+                f,
+                "max errors ({}) exceeds pattern length ({})",
+                max_errors, pattern_len
+            ),
+            SearchError::TrivialThreshold { pattern_len } => write!(
+                f,
+                "max errors equals pattern length ({}), so every position matches",
+                pattern_len
+            ),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for SearchError {}
+
+/// Error returned by `search_with_limits` when the search needed more work
+/// than allowed by `SearchLimits::max_work_units`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchLimitExceeded {
+    /// Matches found in the part of the text which was scanned before the
+    /// search was stopped, ie. `text[..scanned]`.
+    pub partial: LimitedMatches,
+
+    /// Number of text characters which were scanned.
+    pub scanned: usize,
+}
+
+impl fmt::Display for SearchLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "search work limit exceeded after {} characters",
+            self.scanned
+        )
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for SearchLimitExceeded {}
+
+/// Error returned by `validate_utf16` for text which is not well-formed UTF-16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Utf16Error {
+    pub(crate) index: usize,
+}
+
+impl Utf16Error {
+    /// Offset of the first surrogate which is not part of a surrogate pair.
+    ///
+    /// The code units before this offset are well-formed UTF-16.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for Utf16Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unpaired surrogate at index {}", self.index)
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for Utf16Error {}
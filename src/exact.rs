@@ -0,0 +1,109 @@
+// This module implements exact substring search, which is used instead of the
+// bit-vector scan when no errors are allowed.
+//
+// This uses the Knuth-Morris-Pratt algorithm, so the cost is linear in the
+// length of the text even for repetitive patterns. While no prefix of the
+// pattern has been matched, the scan skips ahead to the next occurrence of the
+// first pattern character.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Match;
+
+/// Find every occurrence of `pattern` in `text`, including overlapping ones.
+///
+/// If `ignore_ascii_case` is set, ASCII letters are compared case-insensitively,
+/// as with `MatchOptions::ignore_ascii_case`. The pattern must not be empty.
+pub(crate) fn find_exact(text: &[u16], pattern: &[u16], ignore_ascii_case: bool) -> Vec<Match> {
+    let fold = |ch: u16| {
+        if ignore_ascii_case && ch < 128 {
+            (ch as u8).to_ascii_lowercase() as u16
+        } else {
+            ch
+        }
+    };
+    let pattern: Vec<u16> = pattern.iter().map(|&ch| fold(ch)).collect();
+
+    // `prefix_len[i]` is the length of the longest proper prefix of
+    // `pattern[..=i]` which is also a suffix of it.
+    let mut prefix_len = vec![0; pattern.len()];
+    let mut k = 0;
+    for i in 1..pattern.len() {
+        while k > 0 && pattern[i] != pattern[k] {
+            k = prefix_len[k - 1];
+        }
+        if pattern[i] == pattern[k] {
+            k += 1;
+        }
+        prefix_len[i] = k;
+    }
+
+    let mut matches = Vec::new();
+
+    // Number of pattern characters matched by the text before `j`.
+    let mut k = 0;
+    let mut j = 0;
+    while j < text.len() {
+        if k == 0 {
+            match text[j..].iter().position(|&ch| fold(ch) == pattern[0]) {
+                Some(offset) => j += offset,
+                None => break,
+            }
+        }
+
+        let ch = fold(text[j]);
+        while k > 0 && ch != pattern[k] {
+            k = prefix_len[k - 1];
+        }
+        if ch == pattern[k] {
+            k += 1;
+        }
+        j += 1;
+
+        if k == pattern.len() {
+            matches.push(Match {
+                start: j - k,
+                end: j,
+                errors: 0,
+            });
+            k = prefix_len[k - 1];
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::find_exact;
+    use crate::test_util::Random;
+    use crate::{search_all, search_case_insensitive};
+
+    #[test]
+    fn it_matches_bit_vector_search() {
+        // A small alphabet, mixing cases, so that patterns occur in the text.
+        let mut random = Random::new(1);
+        for pattern_len in [1, 2, 3, 5, 8, 70] {
+            for _ in 0..20 {
+                let text = random.chars(500, b"abAB");
+                let pattern = random.chars(pattern_len, b"abAB");
+
+                assert_eq!(
+                    find_exact(&text, &pattern, false),
+                    search_all(&text, &pattern, 0)
+                );
+                assert_eq!(
+                    find_exact(&text, &pattern, true),
+                    search_case_insensitive(&text, &pattern, 0)
+                );
+            }
+        }
+
+        let text: Vec<u16> = "aaaaaaaa".encode_utf16().collect();
+        let pattern: Vec<u16> = "aaa".encode_utf16().collect();
+        assert_eq!(find_exact(&text, &pattern, false).len(), 6);
+    }
+}
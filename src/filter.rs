@@ -0,0 +1,126 @@
+// This module implements a "filter then verify" search, which uses a cheap
+// check to find the regions of the text which could contain a match and then
+// runs the full algorithm over only those regions.
+//
+// The filter is based on the pigeonhole principle: if the pattern is split into
+// `k + 1` pieces, any match with at most `k` errors must contain at least one
+// of the pieces exactly, since each error can affect at most one piece. An
+// exact occurrence of a piece at offset `o` in the pattern, found at position
+// `p` in the text, means that a match can only lie within `p - o - k` and
+// `p - o + m + k`, where `m` is the pattern length.
+
+use alloc::vec::Vec;
+
+use crate::{
+    find_match_ends, find_match_starts, reverse, search, BlockWord, Match, MatchOptions,
+    PatternBits, Report,
+};
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, skipping regions of the text which cannot contain a
+/// match.
+///
+/// This returns the same matches as `search`. It is faster when `max_errors` is
+/// small relative to the length of the pattern and matches are sparse, but can
+/// be slower otherwise, since every occurrence of any of the `max_errors + 1`
+/// pieces that the pattern is split into must be verified.
+pub fn search_filtered(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let piece_count = max_errors + 1;
+    if pattern.is_empty() || piece_count > pattern.len() {
+        return search(text, pattern, max_errors as u32);
+    }
+
+    let pieces: Vec<(usize, &[u16])> = (0..piece_count)
+        .map(|i| {
+            let offset = i * pattern.len() / piece_count;
+            let end = (i + 1) * pattern.len() / piece_count;
+            (offset, &pattern[offset..end])
+        })
+        .collect();
+
+    // Find the regions of the text around exact occurrences of each piece.
+    let mut windows = Vec::new();
+    for (pos, &ch) in text.iter().enumerate() {
+        for &(offset, piece) in pieces.iter() {
+            if piece[0] != ch || !text[pos..].starts_with(piece) {
+                continue;
+            }
+            let start = pos.saturating_sub(offset + max_errors);
+            let end = (pos + pattern.len() + max_errors - offset).min(text.len());
+            windows.push((start, end));
+        }
+    }
+
+    // Merge overlapping regions, so that each is verified once.
+    windows.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in windows {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    // Verify each region. All matches are collected because the lowest error
+    // count is not known until every region has been scanned.
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut ends = Vec::new();
+    for (start, end) in merged {
+        let region_ends = find_match_ends(
+            &text[start..end],
+            pattern.len(),
+            &mut bits,
+            max_errors,
+            Report::All,
+            &mut (),
+        );
+        ends.extend(region_ends.into_iter().map(|m| Match {
+            end: m.end + start,
+            ..m
+        }));
+    }
+
+    // Keep only the matches with the lowest error count, as `search` does.
+    let min_errors = ends.iter().map(|m| m.errors).min();
+    ends.retain(|m| Some(m.errors) == min_errors);
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    find_match_starts(text, pattern.len(), &mut rev_bits, ends)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::search_filtered;
+    use crate::search;
+    use crate::test_util::Random;
+
+    #[test]
+    fn it_matches_search() {
+        let mut random = Random::new(3);
+
+        for &(pattern_len, alphabet_len) in &[(1, 4), (5, 4), (12, 8), (40, 20), (100, 4)] {
+            let alphabet = &b"abcdefghijklmnopqrstuvwxyz"[..alphabet_len];
+            let pattern = random.chars(pattern_len, alphabet);
+            let mut text = random.chars(2000, alphabet);
+
+            // Plant copies of the pattern with a varying number of edits.
+            for (copy, pos) in [100, 700, 1500].iter().enumerate() {
+                for (i, &ch) in pattern.iter().enumerate() {
+                    text[pos + i] = if i % 7 < copy { b'z' as u16 } else { ch };
+                }
+            }
+
+            for max_errors in 0..6 {
+                assert_eq!(
+                    search_filtered(&text, &pattern, max_errors),
+                    search(&text, &pattern, max_errors),
+                    "pattern_len={} max_errors={}",
+                    pattern_len,
+                    max_errors
+                );
+            }
+        }
+    }
+}
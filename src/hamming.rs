@@ -0,0 +1,78 @@
+// This module implements search by Hamming distance, where the only errors
+// allowed are substitutions.
+
+use alloc::vec::Vec;
+
+use crate::Match;
+
+/// Search for windows of `text` which differ from `pattern` in at most
+/// `max_substitutions` positions.
+///
+/// Unlike `search`, characters cannot be inserted or deleted, so every match
+/// has the same length as the pattern, and its error count is the number of
+/// positions where the window differs from the pattern. This is the right
+/// measure for fixed-length codes such as barcodes. The pattern is compared
+/// against the window at every offset of the text and the comparison stops as
+/// soon as the threshold is exceeded, so the cost is O(n * k) for a text of
+/// length `n` when most windows are far from the pattern. Every window within
+/// the threshold is reported, in order of position, so matches can overlap. An
+/// empty pattern never matches.
+pub fn search_hamming(text: &[u16], pattern: &[u16], max_substitutions: u32) -> Vec<Match> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let max_substitutions = max_substitutions as usize;
+    let mut matches = Vec::new();
+    for (start, window) in text.windows(pattern.len()).enumerate() {
+        let mut errors = 0;
+        for (a, b) in window.iter().zip(pattern) {
+            if a != b {
+                errors += 1;
+                if errors > max_substitutions {
+                    break;
+                }
+            }
+        }
+        if errors <= max_substitutions {
+            matches.push(Match {
+                start,
+                end: start + pattern.len(),
+                errors,
+            });
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::search_hamming;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_finds_barcodes_with_substitutions() {
+        // A read containing the barcode "ACGTAC" with one substitution, after
+        // a window which differs from it in three positions.
+        let read = utf16_str("TTACGAGGACGTTCTT");
+        let barcode = utf16_str("ACGTAC");
+
+        let matches: Vec<_> = search_hamming(&read, &barcode, 1)
+            .iter()
+            .map(|m| (m.range(), m.errors()))
+            .collect();
+        assert_eq!(matches, [(8..14, 1)]);
+
+        let matches = search_hamming(&read, &barcode, 3);
+        assert!(matches.iter().all(|m| m.len() == barcode.len()));
+        assert!(matches.iter().any(|m| m.range() == (2..8)));
+        assert!(search_hamming(&read, &barcode, 0).is_empty());
+        assert!(search_hamming(&read, &[], 1).is_empty());
+        assert!(search_hamming(&barcode, &read, 20).is_empty());
+    }
+}
@@ -29,41 +29,392 @@
 // [2] Šošić, M. (2014). An simd dynamic programming c/c++ library (Doctoral
 // dissertation, Fakultet Elektrotehnike i računarstva, Sveučilište u Zagrebu).
 
+#![cfg_attr(feature = "no_std", no_std)]
+
+extern crate alloc;
+
+mod alignment;
+mod anchored;
+#[cfg(feature = "capi")]
+mod capi;
+#[cfg(feature = "unicode-case")]
+mod case_fold;
+mod dp;
+mod error;
+mod exact;
+mod filter;
+mod hamming;
+mod multi;
+#[cfg(feature = "unicode-normalization")]
+mod normalize;
+mod offsets;
+mod options;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(test)]
+mod reference;
+mod repeated;
+mod roles;
+mod searcher;
+mod select;
+#[cfg(feature = "simd")]
+mod simd;
+mod str_search;
+mod streaming;
+mod subsequence;
+#[cfg(test)]
+mod test_util;
+#[cfg(not(feature = "no_std"))]
 mod wasm;
 
+pub use alignment::{
+    search_split_budget, search_with_alignment, search_with_spans, AlignedMatch, EditOp,
+    HighlightedMatch,
+};
+pub use anchored::{
+    distance_matrix, edit_distance, fuzzy_equals, fuzzy_strip_prefix, near_duplicate_pairs,
+    rank_candidates, search_prefix, search_suffix,
+};
+#[cfg(feature = "capi")]
+pub use capi::{asm_search, AsmMatch};
+#[cfg(feature = "unicode-case")]
+pub use case_fold::search_unicode_case_insensitive;
+pub use dp::{
+    search_damerau, search_gapped, search_positional_weights, search_weighted, EditCosts,
+};
+pub use error::{SearchError, SearchLimitExceeded, Utf16Error};
+pub use filter::search_filtered;
+pub use hamming::search_hamming;
+pub use multi::{search_alternatives, search_multi, search_multi_ratio};
+pub use offsets::{
+    snap_to_char_boundaries, to_char_offsets, utf16_to_utf8_offsets, validate_utf16,
+};
+pub use options::{
+    search_with, try_search_with, MatchLengthPreference, SearchOptions, SurrogatePolicy,
+};
+#[cfg(feature = "rayon")]
+pub use parallel::search_parallel;
+pub use roles::{search_needle, Haystack, Needle};
+pub use searcher::Searcher;
+pub use select::{
+    cover_left_to_right, fuzzy_replace, matches_iter, merge_matches, select_non_overlapping,
+    split_around_matches, Segment, TextPart,
+};
+pub use str_search::{fuzzy_replace_str, matched_cow, matched_str, search_str, StrMatch};
+pub use streaming::{
+    search_iter_text, search_random_access, RandomAccessText, SearchState, StreamingSearcher,
+};
+pub use subsequence::{search_subsequence, search_subsequence_contiguous};
+
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::marker::PhantomData;
+use core::ops::ControlFlow;
+use core::ops::{BitAnd, BitOr, BitXor, Not, Shl};
+
+#[cfg(feature = "no_std")]
+use hashbrown::HashMap;
+#[cfg(not(feature = "no_std"))]
 use std::collections::HashMap;
-use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+/// An approximate match of a pattern within a text.
+///
+/// Offsets are indices into the text which was searched. As with Rust ranges,
+/// `start` is inclusive and `end` is exclusive, so the match covers
+/// `text[start..end]` and `end - start` is its length. Use `end_inclusive` for
+/// APIs which expect the offset of the last character instead.
+///
+/// Matches are ordered "best first": by error count ascending, then by `start`
+/// and finally by `end`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Match {
     start: usize,
     end: usize,
     errors: usize,
 }
 
-type BlockWord = u64;
+impl Match {
+    /// Create a match covering `start..end` with `errors` errors.
+    ///
+    /// This is mainly useful for constructing expected results in tests, eg.
+    /// `assert_eq!(search(text, pattern, 1), [Match::new(71, 90, 1)])`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start > end`.
+    pub fn new(start: usize, end: usize, errors: usize) -> Match {
+        assert!(start <= end, "match start {} is after end {}", start, end);
+        Match { start, end, errors }
+    }
+
+    /// Offset of the first character of the match in the text.
+    ///
+    /// If several start positions give a match ending at `end` with the same
+    /// error count, the earliest, which gives the longest match, is used.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Offset of the character after the last character of the match.
+    ///
+    /// This is exclusive, so for a match which ends at the end of the text, it
+    /// is the length of the text.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Offset of the last character of the match, `end - 1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the match is empty, since it then has no last character.
+    pub fn end_inclusive(&self) -> usize {
+        assert!(!self.is_empty(), "empty match has no last character");
+        self.end - 1
+    }
+
+    /// Number of errors (insertions, deletions or substitutions) in the match.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Return true if the match has no errors.
+    pub fn is_exact(&self) -> bool {
+        self.errors == 0
+    }
+
+    /// Return the `start..end` range of the match, for slicing the text.
+    pub fn range(&self) -> core::ops::Range<usize> {
+        self.start..self.end
+    }
+
+    /// Return the length of the match, `end - start`.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Return true if the match is empty, ie. `start == end`.
+    ///
+    /// This can happen when every pattern character is deleted, which is only
+    /// allowed when `max_errors` is at least the pattern length, and shorter
+    /// matches are preferred (see `MatchLengthPreference`).
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Return the part of `text` covered by `m`.
+///
+/// `m` must be a match found by searching `text`.
+///
+/// # Panics
+///
+/// Panics if the range of the match is out of bounds for `text`.
+pub fn matched_slice<'a>(text: &'a [u16], m: &Match) -> &'a [u16] {
+    &text[m.range()]
+}
+
+/// Formats the match as `[start..end] errors=N`, eg. `[2..5] errors=1`.
+///
+/// This format is stable and suitable for human-facing logs. Use `Debug` for
+/// diagnostics.
+impl core::fmt::Display for Match {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "[{}..{}] errors={}", self.start, self.end, self.errors)
+    }
+}
+
+/// Converts a match to a `(start, end, errors)` tuple.
+impl From<Match> for (usize, usize, usize) {
+    fn from(m: Match) -> Self {
+        (m.start, m.end, m.errors)
+    }
+}
+
+/// Creates a match from a `(start, end, errors)` tuple. This panics if
+/// `start > end`, as `Match::new` does.
+impl From<(usize, usize, usize)> for Match {
+    fn from((start, end, errors): (usize, usize, usize)) -> Self {
+        Match::new(start, end, errors)
+    }
+}
+
+impl Ord for Match {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.errors, self.start, self.end).cmp(&(other.errors, other.start, other.end))
+    }
+}
+
+impl PartialOrd for Match {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An unsigned integer type used to hold the bit vectors for one block of the
+/// pattern.
+///
+/// Each block represents `Word::BITS` characters of the pattern. Wider words
+/// mean fewer blocks for long patterns, narrower words mean less work per block
+/// for short patterns. This is implemented for `u32`, `u64` and `u128`.
+pub trait Word:
+    Copy
+    + Default
+    + Eq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + BitXor<Output = Self>
+    + Not<Output = Self>
+    + Shl<usize, Output = Self>
+{
+    /// Number of characters of the pattern represented by each block.
+    const BITS: usize;
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn wrapping_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(impl Word for $t {
+            const BITS: usize = <$t>::BITS as usize;
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+        })*
+    };
+}
+
+impl_word!(u32, u64, u128);
 
-// Number of characters of the pattern represented by each block.
-const BLOCK_LEN: usize = 64;
+/// Word type used by the default search functions.
+type BlockWord = u64;
 
 #[derive(Clone, Debug)]
-struct Block {
+struct Block<W> {
     // Bit flags indicating whether each row in this block has one more error
     // than the row above.
-    plus_v: BlockWord,
+    plus_v: W,
 
     // Bit flags indicating whether each row in this block has one less error
     // than the row above.
-    minus_v: BlockWord,
+    minus_v: W,
 
     // Mask with one bit set indicating which is the last used position in this
     // block.
-    last_row_mask: BlockWord,
+    last_row_mask: W,
+
+    // Error count at the last row of the block. This is at most the length of
+    // the pattern, or for anchored searches the pattern length plus the number
+    // of text characters scanned. `isize` is used so that this can't overflow
+    // for any slice, which has at most `isize::MAX` elements, on any target.
+    score: isize,
+}
+
+/// Counters describing how much work a search performed.
+///
+/// These are collected from the forward scan over the text which locates the
+/// end of each match. The shorter reverse scans used to locate match starts
+/// are not included.
+#[cfg(feature = "stats")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Number of characters of the text that were processed.
+    pub chars_scanned: usize,
+
+    /// Total number of block calculation steps (calls to `advance_block`).
+    pub block_advances: usize,
 
-    score: i32,
+    /// Highest index of the last-active block reached during the scan.
+    pub peak_y: usize,
+
+    /// Sum over the characters processed of the number of blocks which were
+    /// active after processing each one.
+    pub active_blocks: usize,
+}
+
+#[cfg(feature = "stats")]
+impl SearchStats {
+    /// Return the largest number of blocks which were active for any
+    /// character of the text, or zero if no characters were processed.
+    ///
+    /// Each block covers 64 characters of the pattern. The number of active
+    /// blocks grows with the error threshold, and is what makes a search with a
+    /// high threshold slower.
+    pub fn max_active_blocks(&self) -> usize {
+        if self.chars_scanned == 0 {
+            0
+        } else {
+            self.peak_y + 1
+        }
+    }
+
+    /// Return the average number of blocks which were active for each
+    /// character of the text, or zero if no characters were processed.
+    pub fn avg_active_blocks(&self) -> f64 {
+        if self.chars_scanned == 0 {
+            0.0
+        } else {
+            self.active_blocks as f64 / self.chars_scanned as f64
+        }
+    }
+}
+
+/// Hooks used by `find_match_ends` to record work done during a search.
+///
+/// The `()` implementation does nothing and compiles away entirely, so the
+/// default search path pays no cost for statistics collection.
+trait StatsRecorder {
+    fn record_char(&mut self) {}
+    fn record_block_advance(&mut self) {}
+    fn record_active_block(&mut self, _y: usize) {}
+}
+
+impl StatsRecorder for () {}
+
+#[cfg(feature = "stats")]
+impl StatsRecorder for SearchStats {
+    fn record_char(&mut self) {
+        self.chars_scanned += 1;
+    }
+
+    fn record_block_advance(&mut self) {
+        self.block_advances += 1;
+    }
+
+    fn record_active_block(&mut self, y: usize) {
+        self.peak_y = self.peak_y.max(y);
+        self.active_blocks += y + 1;
+    }
+}
+
+/// Settings which control how characters of the pattern and text are compared.
+#[derive(Clone, Copy, Debug, Default)]
+struct MatchOptions {
+    // Treat ASCII letters which differ only in case as equal.
+    ignore_ascii_case: bool,
+
+    // Pattern character which matches any text character.
+    wildcard: Option<u32>,
 }
 
-fn one_if_not_zero<T: PartialEq + Default>(n: T) -> i32 {
+/// A character type which the matcher can operate on.
+///
+/// Characters with codes below 256 are looked up in a dense table, others as
+/// described in `NonAsciiIndex`.
+trait Char: Copy + Eq + Hash + Into<u32> {}
+
+impl<T: Copy + Eq + Hash + Into<u32>> Char for T {}
+
+fn one_if_not_zero<T: PartialEq + Default>(n: T) -> isize {
     if n != Default::default() {
         1
     } else {
@@ -71,40 +422,118 @@ fn one_if_not_zero<T: PartialEq + Default>(n: T) -> i32 {
     }
 }
 
-fn reverse(chars: &[u16]) -> Vec<u16> {
+fn reverse<C: Char>(chars: &[C]) -> Vec<C> {
     chars.iter().rev().cloned().collect()
 }
 
-fn find_match_starts(text: &[u16], pattern: &[u16], matches: Vec<Match>) -> Vec<Match> {
-    let pat_rev = reverse(pattern);
+/// Source of the bit vectors indicating which positions in the pattern match
+/// each character of the text.
+trait MatchBits<C, W> {
+    fn match_bits(&mut self, ch: C) -> &[W];
+}
 
-    matches
-        .iter()
-        .map(|m| {
-            // Find start of each match by reversing the pattern and matching segment
-            // of text and searching for an approx match with the same number of
-            // errors.
-            let min_start = 0.max(m.end as i32 - pattern.len() as i32 - m.errors as i32) as usize;
-            let text_rev = reverse(&text[min_start..m.end]);
-
-            // If there are multiple possible start points, choose the one that
-            // maximizes the length of the match.
-            let match_ends = find_match_ends(&text_rev, &pat_rev, m.errors);
-            let mut start = m.end;
-
-            for rm in match_ends {
-                if m.end - rm.end < start {
-                    start = m.end - rm.end;
-                }
-            }
+/// Buffers used by `find_match_start`, which can be reused for each match.
+struct StartScratch<W> {
+    scan: ScanBuffers<W>,
+}
+
+impl<W: Word> StartScratch<W> {
+    fn new() -> StartScratch<W> {
+        StartScratch {
+            scan: ScanBuffers::new(),
+        }
+    }
+}
+
+/// Find the start position of a match, given its end position and error count.
+///
+/// Several start positions can give a match with the same error count. In that
+/// case `preference` determines which is chosen.
+fn find_match_start<W: Word, C: Char, B: MatchBits<C, W>>(
+    text: &[C],
+    pattern_len: usize,
+    rev_bits: &mut B,
+    m: &Match,
+    preference: MatchLengthPreference,
+    scratch: &mut StartScratch<W>,
+) -> usize {
+    // Find the start of the match by matching the reversed pattern against
+    // the reversed text, ending at the end of the match. A match cannot be
+    // longer than `pattern_len + errors`, which bounds the text to scan. The
+    // text is read backwards in place rather than copied.
+    let min_start = m.end.saturating_sub(pattern_len + m.errors);
 
-            Match {
-                start,
-                end: m.end,
-                errors: m.errors,
+    // The scan is anchored at the end of the match, so the error count at
+    // each column is that of the match which starts there. The lowest of
+    // these is `m.errors`, since that is the error count of the best match
+    // ending at `m.end`.
+    let state = &mut scratch.scan.state;
+    state.reset(pattern_len, m.errors, true);
+    state.anchored = true;
+
+    if preference == MatchLengthPreference::Shortest && m.errors >= pattern_len {
+        // The empty match, which deletes every pattern character.
+        return m.end;
+    }
+
+    // Column `j` of the reverse scan reads `text[m.end - 1 - j]`, ie. the
+    // first column reads the last character of the match, which is at the
+    // inclusive end `m.end - 1`. A match found at column `j` starts with that
+    // character.
+    let mut start = None;
+    for (j, &ch) in text[min_start..m.end].iter().rev().enumerate() {
+        if let Some(errors) = state.advance(rev_bits.match_bits(ch), &mut ()) {
+            debug_assert_eq!(errors, m.errors);
+            start = Some(m.end - 1 - j);
+            if preference == MatchLengthPreference::Shortest {
+                break;
             }
-        })
-        .collect()
+        }
+    }
+
+    // If no start was found, the only match with `m.errors` errors is the
+    // empty one, which deletes every pattern character. A non-empty match of
+    // one text character costs at most `pattern_len` errors, so this only
+    // happens when there is no text before `m.end` to scan.
+    let start = start.unwrap_or_else(|| {
+        debug_assert!(m.errors >= pattern_len);
+        m.end
+    });
+    debug_assert!(min_start <= start && start <= m.end);
+    start
+}
+
+/// Fill in the start positions of `matches`, given bit vectors for the
+/// reversed pattern.
+fn find_match_starts<W: Word, C: Char, B: MatchBits<C, W>>(
+    text: &[C],
+    pattern_len: usize,
+    rev_bits: &mut B,
+    matches: Vec<Match>,
+) -> Vec<Match> {
+    find_match_starts_preferring(
+        text,
+        pattern_len,
+        rev_bits,
+        matches,
+        MatchLengthPreference::Longest,
+    )
+}
+
+/// Variant of `find_match_starts` which chooses between equally good start
+/// positions according to `preference`.
+fn find_match_starts_preferring<W: Word, C: Char, B: MatchBits<C, W>>(
+    text: &[C],
+    pattern_len: usize,
+    rev_bits: &mut B,
+    mut matches: Vec<Match>,
+    preference: MatchLengthPreference,
+) -> Vec<Match> {
+    let mut scratch = StartScratch::new();
+    for m in matches.iter_mut() {
+        m.start = find_match_start(text, pattern_len, rev_bits, m, preference, &mut scratch);
+    }
+    matches
 }
 
 /// Block calculation step of the algorithm.
@@ -118,17 +547,19 @@ fn find_match_starts(text: &[u16], pattern: &[u16], matches: Vec<Match>) -> Vec<
 /// h_in - Horizontal input delta (1, 0 or -1)
 ///
 /// Returns horizontal output delta (1, 0 or -1)
-fn advance_block(block: &mut Block, pattern_match_bits: BlockWord, h_in: i32) -> i32 {
+#[must_use]
+fn advance_block<W: Word>(block: &mut Block<W>, pattern_match_bits: W, h_in: isize) -> isize {
     let p_v = block.plus_v;
     let m_v = block.minus_v;
 
-    let h_in_negative = if h_in < 0 { 1 } else { 0 };
+    debug_assert!((-1..=1).contains(&h_in));
+    let h_in_negative = if h_in < 0 { W::ONE } else { W::ZERO };
 
     let eq = pattern_match_bits | h_in_negative;
 
     // Step 1: Compute horizontal deltas.
     let x_v = eq | m_v;
-    let x_h = (((eq & p_v).overflowing_add(p_v).0) ^ p_v) | eq;
+    let x_h = ((eq & p_v).wrapping_add(p_v) ^ p_v) | eq;
 
     let mut p_h = m_v | !(x_h | p_v);
     let mut m_h = p_v & x_h;
@@ -138,11 +569,14 @@ fn advance_block(block: &mut Block, pattern_match_bits: BlockWord, h_in: i32) ->
         one_if_not_zero(p_h & block.last_row_mask) - one_if_not_zero(m_h & block.last_row_mask);
 
     // Step 3: Update vertical deltas for use when processing next char.
-    p_h <<= 1;
-    m_h <<= 1;
-
-    m_h |= h_in_negative;
-    p_h |= one_if_not_zero(h_in) as BlockWord - h_in_negative; // Set p_h[0] if h_in > 0
+    //
+    // Shifting aligns the horizontal delta of each row with the row below.
+    // Bit 0 is then the delta of the row above the block, which is `h_in`: it
+    // is set in `p_h` if `h_in` is 1, in `m_h` if it is -1, and in neither if
+    // it is 0.
+    let h_in_positive = if h_in > 0 { W::ONE } else { W::ZERO };
+    p_h = (p_h << 1) | h_in_positive;
+    m_h = (m_h << 1) | h_in_negative;
 
     let p_v = m_h | !(x_v | p_h);
     let m_v = p_h & x_v;
@@ -153,235 +587,4006 @@ fn advance_block(block: &mut Block, pattern_match_bits: BlockWord, h_in: i32) ->
     h_out
 }
 
-fn find_match_ends(text: &[u16], pattern: &[u16], max_errors: usize) -> Vec<Match> {
-    if pattern.is_empty() {
-        return Vec::new();
+/// Bit vectors for each distinct character in a pattern, indicating the
+/// positions in the pattern where that character occurs.
+#[derive(Clone)]
+struct PatternBits<C, W> {
+    block_count: usize,
+
+    // Bit vectors for each distinct character in the pattern, `block_count`
+    // words each. The first is for characters which do not occur in the
+    // pattern. These are shared between clones.
+    bits: Arc<Vec<W>>,
+
+    // Index of the bit vector in `bits` for each character code below 256.
+    ascii_index: [u32; 256],
+
+    // Index of the bit vectors for other character codes.
+    nonascii_index: NonAsciiIndex,
+
+    _char: PhantomData<C>,
+}
+
+/// Maximum size of the range of character codes covered by
+/// `NonAsciiIndex::Dense`.
+const MAX_DENSE_RANGE: u32 = 4096;
+
+/// Index of the bit vectors in `PatternBits::bits` for character codes of 256
+/// and above which occur in the pattern.
+#[derive(Clone)]
+enum NonAsciiIndex {
+    /// Table of indices for every code in `base..base + index.len()`, with 0
+    /// for codes which don't occur in the pattern.
+    ///
+    /// This is used when the codes lie within a small range, as is typical for
+    /// text in a single script such as Greek or Cyrillic.
+    Dense { base: u32, index: Vec<u32> },
+
+    /// Codes and their indices, sorted by code.
+    ///
+    /// Patterns usually contain few distinct characters, so a binary search of
+    /// this is faster than a hash map lookup.
+    Sparse(Vec<(u32, u32)>),
+}
+
+impl NonAsciiIndex {
+    /// Create an index from a list of codes and indices sorted by code.
+    fn new(sorted: Vec<(u32, u32)>) -> NonAsciiIndex {
+        let (Some(&(min, _)), Some(&(max, _))) = (sorted.first(), sorted.last()) else {
+            return NonAsciiIndex::Sparse(sorted);
+        };
+        if max - min >= MAX_DENSE_RANGE {
+            return NonAsciiIndex::Sparse(sorted);
+        }
+
+        let mut index = vec![0; (max - min + 1) as usize];
+        for (code, idx) in sorted {
+            index[(code - min) as usize] = idx;
+        }
+        NonAsciiIndex::Dense { base: min, index }
+    }
+
+    /// Return the codes and indices in the index, sorted by code.
+    fn entries(&self) -> Vec<(u32, u32)> {
+        match self {
+            NonAsciiIndex::Dense { base, index } => index
+                .iter()
+                .enumerate()
+                .filter(|(_, &idx)| idx != 0)
+                .map(|(offset, &idx)| (base + offset as u32, idx))
+                .collect(),
+            NonAsciiIndex::Sparse(sorted) => sorted.clone(),
+        }
     }
 
-    // Clamp error count so we can reply on `max_errors` and `pattern.len()`
-    // rows being in the same block below.
-    let mut max_errors = max_errors.min(pattern.len()) as i32;
+    /// Return the index of the bit vector for `code`, or 0 if it does not
+    /// occur in the pattern.
+    fn get(&self, code: u32) -> u32 {
+        match self {
+            NonAsciiIndex::Dense { base, index } => code
+                .checked_sub(*base)
+                .and_then(|offset| index.get(offset as usize))
+                .copied()
+                .unwrap_or(0),
+            NonAsciiIndex::Sparse(sorted) => {
+                match sorted.binary_search_by_key(&code, |(c, _)| *c) {
+                    Ok(pos) => sorted[pos].1,
+                    Err(_) => 0,
+                }
+            }
+        }
+    }
+}
 
-    let mut matches = Vec::new();
+impl<C: Char, W: Word> PatternBits<C, W> {
+    fn new(pattern: &[C], opts: MatchOptions) -> PatternBits<C, W> {
+        PatternBits::with_mask(pattern, opts, None)
+    }
 
-    // Number of blocks required by this pattern.
-    let block_count = (pattern.len() + BLOCK_LEN - 1) / BLOCK_LEN;
+    /// Variant of `new` where positions of the pattern which are set in
+    /// `wildcard_mask` match any character, in addition to occurrences of
+    /// `opts.wildcard`.
+    fn with_mask(
+        pattern: &[C],
+        opts: MatchOptions,
+        wildcard_mask: Option<&[bool]>,
+    ) -> PatternBits<C, W> {
+        let block_count = pattern.len().div_ceil(W::BITS);
+        let is_wildcard = |idx: usize, ch: C| {
+            opts.wildcard == Some(ch.into()) || wildcard_mask.is_some_and(|mask| mask[idx])
+        };
 
-    // Dummy match bit vector for chars in the text which do not occur in the pattern.
-    let zero_bits = Rc::new(vec![0; block_count]);
+        // Positions in the pattern which match any character. Text characters
+        // which do not occur in the pattern match only these positions.
+        let mut wildcard_bits = vec![W::ZERO; block_count];
+        for (idx, ch) in pattern.iter().enumerate() {
+            if is_wildcard(idx, *ch) {
+                let bits = &mut wildcard_bits[idx / W::BITS];
+                *bits = *bits | (W::ONE << (idx % W::BITS));
+            }
+        }
 
-    // Map of non-ASCII UTF-16 character code to bit vector indicating positions in the
-    // pattern that equal that character.
-    let mut nonascii_match_bits: HashMap<u16, Rc<Vec<BlockWord>>> = HashMap::new();
+        let mut bits = wildcard_bits.clone();
+        let mut ascii_index = [0u32; 256];
+        let mut nonascii_index: Vec<(u32, u32)> = Vec::new();
 
-    // Map of ASCII character code to bit vector indicating positions in the
-    // pattern that equal that character.
-    let mut ascii_match_bits = vec![zero_bits.clone(); 256];
+        // Map characters to the form used for comparisons.
+        let fold = |ch: C| -> u32 {
+            let code = ch.into();
+            if opts.ignore_ascii_case && code < 128 {
+                (code as u8).to_ascii_lowercase() as u32
+            } else {
+                code
+            }
+        };
 
-    // For each unique character in the pattern generate a bit vector indicating
-    // the positions where it occurs in the pattern.
-    for ch in pattern.iter() {
-        // Check if we've already seen this char.
-        if let Some(entry) = ascii_match_bits.get(*ch as usize) {
-            if *entry != zero_bits {
+        // For each position in the pattern, set the corresponding bit in the
+        // bit vector for the character there, adding a bit vector when the
+        // character is first seen. New bit vectors start as a copy of the
+        // wildcard bits, so that wildcard positions match every character.
+        for (idx, ch) in pattern.iter().enumerate() {
+            if is_wildcard(idx, *ch) {
                 continue;
             }
-        } else if nonascii_match_bits.get(ch).is_some() {
-            continue;
-        }
 
-        let mut match_bits: Vec<BlockWord> = vec![0; block_count];
+            let code = fold(*ch);
+            let next_index = (bits.len() / block_count) as u32;
+            let index = if code < 256 {
+                let entry = &mut ascii_index[code as usize];
+                if *entry == 0 {
+                    *entry = next_index;
 
-        for (b, bits) in match_bits.iter_mut().enumerate() {
-            // Set all the bits where the pattern matches the current char (ch).
-            // For indexes beyond the end of the pattern, always set the bit as
-            // if the pattern contained a wildcard char in that position.
-            for r in 0..BLOCK_LEN {
-                let idx = b * BLOCK_LEN + r;
-                if idx >= pattern.len() {
-                    continue;
+                    // When ignoring case, share the bit vector with the other
+                    // case of the letter so that lookups of text characters
+                    // need no extra work.
+                    if opts.ignore_ascii_case && (code as u8).is_ascii_lowercase() {
+                        ascii_index[(code ^ 0x20) as usize] = next_index;
+                    }
                 }
-
-                if pattern[idx] == *ch {
-                    *bits |= 1 << r;
+                ascii_index[code as usize]
+            } else {
+                match nonascii_index.binary_search_by_key(&code, |(c, _)| *c) {
+                    Ok(pos) => nonascii_index[pos].1,
+                    Err(pos) => {
+                        nonascii_index.insert(pos, (code, next_index));
+                        next_index
+                    }
                 }
+            };
+            if index == next_index {
+                bits.extend_from_slice(&wildcard_bits);
             }
+
+            let word = &mut bits[index as usize * block_count + idx / W::BITS];
+            *word = *word | (W::ONE << (idx % W::BITS));
+        }
+
+        PatternBits {
+            block_count,
+            bits: Arc::new(bits),
+            ascii_index,
+            nonascii_index: NonAsciiIndex::new(nonascii_index),
+            _char: PhantomData,
+        }
+    }
+
+    /// Return the index of the bit vector for `code`, adding one with no bits
+    /// set if the code does not have one yet.
+    ///
+    /// This is used when extending a pattern, which must have been created
+    /// with the default `MatchOptions`.
+    fn vector_index(&mut self, code: u32) -> usize {
+        let existing = if code < 256 {
+            self.ascii_index[code as usize]
+        } else {
+            self.nonascii_index.get(code)
+        };
+        if existing != 0 {
+            return existing as usize;
         }
 
-        let match_bits = Rc::new(match_bits);
-        if let Some(entry) = ascii_match_bits.get_mut(*ch as usize) {
-            *entry = match_bits.clone();
+        let bits = Arc::make_mut(&mut self.bits);
+        let index = bits.len() / self.block_count;
+        bits.resize(bits.len() + self.block_count, W::ZERO);
+
+        if code < 256 {
+            self.ascii_index[code as usize] = index as u32;
         } else {
-            nonascii_match_bits.insert(*ch, match_bits.clone());
+            let mut entries = self.nonascii_index.entries();
+            let pos = entries
+                .binary_search_by_key(&code, |(c, _)| *c)
+                .unwrap_err();
+            entries.insert(pos, (code, index as u32));
+            self.nonascii_index = NonAsciiIndex::new(entries);
         }
+        index
     }
 
-    // Index of last-active block level in the column.
-    let mut y = 0.max((max_errors as f32 / (BLOCK_LEN as f32)).ceil() as i32 - 1) as usize;
+    /// Resize the bit vectors to hold `pattern_len` characters. New blocks have
+    /// no bits set.
+    fn reserve_positions(&mut self, pattern_len: usize) {
+        let old_count = self.block_count;
+        let block_count = pattern_len.div_ceil(W::BITS);
+        if block_count <= old_count {
+            return;
+        }
+
+        // An empty pattern has no blocks, but still has the vector for
+        // characters not in the pattern.
+        let vectors = self.bits.len().checked_div(old_count).unwrap_or(1);
+        let mut bits = vec![W::ZERO; vectors * block_count];
+        for v in 0..vectors {
+            bits[v * block_count..v * block_count + old_count]
+                .copy_from_slice(&self.bits[v * old_count..(v + 1) * old_count]);
+        }
+        self.bits = Arc::new(bits);
+        self.block_count = block_count;
+    }
+
+    /// Update the bit vectors for a pattern of length `pattern_len` to add `ch`
+    /// to the end of the pattern.
+    fn push_back(&mut self, ch: C, pattern_len: usize) {
+        self.reserve_positions(pattern_len + 1);
+        let index = self.vector_index(ch.into());
+        let block_count = self.block_count;
+        let bits = Arc::make_mut(&mut self.bits);
+        let word = &mut bits[index * block_count + pattern_len / W::BITS];
+        *word = *word | (W::ONE << (pattern_len % W::BITS));
+    }
+
+    /// Update the bit vectors for a pattern of length `pattern_len` to add `ch`
+    /// to the start of the pattern, moving every other character along by
+    /// one position.
+    fn push_front(&mut self, ch: C, pattern_len: usize) {
+        self.reserve_positions(pattern_len + 1);
+        let block_count = self.block_count;
+        let top_bit = W::ONE << (W::BITS - 1);
+        for vector in Arc::make_mut(&mut self.bits).chunks_mut(block_count) {
+            let mut carry = W::ZERO;
+            for word in vector {
+                let next_carry = if *word & top_bit != W::ZERO {
+                    W::ONE
+                } else {
+                    W::ZERO
+                };
+                *word = (*word << 1) | carry;
+                carry = next_carry;
+            }
+        }
+
+        let index = self.vector_index(ch.into());
+        let bits = Arc::make_mut(&mut self.bits);
+        bits[index * block_count] = bits[index * block_count] | W::ONE;
+    }
+
+    /// Return the number of distinct characters in the pattern, each of which
+    /// has its own bit vector.
+    fn distinct_chars(&self) -> usize {
+        if self.block_count == 0 {
+            return 0;
+        }
+        // Exclude the bit vector for characters not in the pattern.
+        self.bits.len() / self.block_count - 1
+    }
+
+    /// Return the bit vector indicating which positions in the pattern match `ch`.
+    fn get(&self, ch: C) -> &[W] {
+        let code = ch.into();
+        let index = if code < 256 {
+            self.ascii_index[code as usize]
+        } else {
+            self.nonascii_index.get(code)
+        };
+        let offset = index as usize * self.block_count;
+        &self.bits[offset..offset + self.block_count]
+    }
+}
+
+impl<C: Char, W: Word> MatchBits<C, W> for PatternBits<C, W> {
+    fn match_bits(&mut self, ch: C) -> &[W] {
+        self.get(ch)
+    }
+}
+
+// Allow sharing the bit vectors between searches.
+impl<C: Char, W: Word> MatchBits<C, W> for &PatternBits<C, W> {
+    fn match_bits(&mut self, ch: C) -> &[W] {
+        self.get(ch)
+    }
+}
+
+/// Bit vectors computed by comparing each text character against every
+/// position in the pattern using a caller-supplied equality predicate.
+///
+/// The bit vector for each distinct text character is computed on first use and
+/// then cached, so `eq` must give the same answer each time for the same inputs.
+struct PredicateBits<'a, W, F> {
+    pattern: &'a [u16],
+    eq: &'a F,
+    cache: HashMap<u16, Vec<W>>,
+}
+
+impl<'a, W: Word, F: Fn(u16, u16) -> bool> PredicateBits<'a, W, F> {
+    fn new(pattern: &'a [u16], eq: &'a F) -> PredicateBits<'a, W, F> {
+        PredicateBits {
+            pattern,
+            eq,
+            cache: HashMap::new(),
+        }
+    }
+}
+
+impl<W: Word, F: Fn(u16, u16) -> bool> MatchBits<u16, W> for PredicateBits<'_, W, F> {
+    fn match_bits(&mut self, ch: u16) -> &[W] {
+        let pattern = self.pattern;
+        let eq = self.eq;
+        self.cache.entry(ch).or_insert_with(|| {
+            let mut match_bits = vec![W::ZERO; pattern.len().div_ceil(W::BITS)];
+            for (idx, pattern_ch) in pattern.iter().enumerate() {
+                if eq(*pattern_ch, ch) {
+                    let bits = &mut match_bits[idx / W::BITS];
+                    *bits = *bits | (W::ONE << (idx % W::BITS));
+                }
+            }
+            match_bits
+        })
+    }
+}
+
+/// State of the column-by-column scan over the text which finds the end
+/// positions of matches.
+///
+/// This depends only on the length of the pattern. The pattern's characters are
+/// supplied via the bit vectors passed to each call to `advance`, which
+/// processes one character of the text, so the scan can be stopped and resumed
+/// at any point.
+#[derive(Clone)]
+struct ColumnState<W> {
+    pattern_len: usize,
+
+    // Number of blocks required by the pattern.
+    block_count: usize,
 
     // Data for the current column of the error count table.
-    let mut blocks: Vec<Block> = Vec::with_capacity(block_count);
-    for b in 0..block_count {
-        blocks.push(Block {
-            plus_v: !0,
-            minus_v: 0,
-            last_row_mask: if b == block_count - 1 {
-                1 << ((pattern.len() - 1) % BLOCK_LEN)
-            } else {
-                1 << (BLOCK_LEN - 1)
-            },
-            score: if b == block_count - 1 {
-                pattern.len()
-            } else {
-                (b + 1) * BLOCK_LEN
-            } as i32,
-        });
+    blocks: Vec<Block<W>>,
+
+    // Index of last-active block level in the column.
+    y: usize,
+
+    // Current error threshold. When `ratchet` is set this is lowered each time
+    // a match is found.
+    max_errors: isize,
+    ratchet: bool,
+
+    // If set, text characters before the start of a match are not free, so
+    // only matches which start at the beginning of the text are found.
+    anchored: bool,
+}
+
+impl<W: Word> ColumnState<W> {
+    /// Prepare to scan a text for a pattern of length `pattern_len`, which must
+    /// be non-zero.
+    ///
+    /// If `ratchet` is set, the error threshold is lowered to the error count of
+    /// each match found, so only matches that are at least as good as earlier
+    /// ones are reported.
+    fn new(pattern_len: usize, max_errors: usize, ratchet: bool) -> ColumnState<W> {
+        let mut state = ColumnState::empty();
+        state.reset(pattern_len, max_errors, ratchet);
+        state
     }
 
-    // Process each char of the text, computing the error count for `w` chars
-    // of the pattern at a time.
-    for (j, char_code) in text.iter().enumerate() {
-        let match_bits = ascii_match_bits
-            .get(*char_code as usize)
-            .unwrap_or_else(|| nonascii_match_bits.get(&char_code).unwrap_or(&zero_bits));
+    /// Create a state which must be initialized with `reset` before use.
+    fn empty() -> ColumnState<W> {
+        ColumnState {
+            pattern_len: 0,
+            block_count: 0,
+            blocks: Vec::new(),
+            y: 0,
+            max_errors: 0,
+            ratchet: false,
+            anchored: false,
+        }
+    }
+
+    /// Re-initialize the state as if it had been created with `new`, reusing
+    /// the existing allocation for the blocks.
+    fn reset(&mut self, pattern_len: usize, max_errors: usize, ratchet: bool) {
+        // Clamp error count so we can reply on `max_errors` and `pattern.len()`
+        // rows being in the same block below.
+        let max_errors = max_errors.min(pattern_len) as isize;
+
+        // The threshold for dropping a block is `max_errors + W::BITS`, which
+        // must be representable.
+        debug_assert!(pattern_len <= isize::MAX as usize - W::BITS);
+
+        let block_count = pattern_len.div_ceil(W::BITS);
+
+        let y = (max_errors as usize).div_ceil(W::BITS).saturating_sub(1);
+
+        let blocks = &mut self.blocks;
+        blocks.clear();
+        for b in 0..block_count {
+            blocks.push(Block {
+                plus_v: !W::ZERO,
+                minus_v: W::ZERO,
+                last_row_mask: if b == block_count - 1 {
+                    W::ONE << ((pattern_len - 1) % W::BITS)
+                } else {
+                    W::ONE << (W::BITS - 1)
+                },
+                score: if b == block_count - 1 {
+                    pattern_len
+                } else {
+                    (b + 1) * W::BITS
+                } as isize,
+            });
+        }
+
+        self.pattern_len = pattern_len;
+        self.block_count = block_count;
+        self.y = y;
+        self.max_errors = max_errors;
+        self.ratchet = ratchet;
+        self.anchored = false;
+    }
+
+    /// Process the next character of the text, computing the error count for `w`
+    /// chars of the pattern at a time.
+    ///
+    /// `match_bits` indicates which positions in the pattern match the character.
+    /// Returns the error count if a match ends at this character.
+    fn advance<S: StatsRecorder>(&mut self, match_bits: &[W], stats: &mut S) -> Option<usize> {
+        if self.block_count == 1 {
+            self.advance_one_block(match_bits[0], stats)
+        } else {
+            self.advance_blocks(match_bits, stats)
+        }
+    }
+
+    /// Variant of `advance` for patterns which fit in a single block.
+    ///
+    /// This is the common case for short patterns, such as search queries
+    /// typed by a user. The only block is always active, so there is no need
+    /// to track which blocks to process.
+    #[inline]
+    fn advance_one_block<S: StatsRecorder>(
+        &mut self,
+        match_bits: W,
+        stats: &mut S,
+    ) -> Option<usize> {
+        stats.record_char();
+        let block = &mut self.blocks[0];
+        let carry = if self.anchored { 1 } else { 0 };
+        block.score += advance_block(block, match_bits, carry);
+        stats.record_block_advance();
+        stats.record_active_block(0);
+
+        if block.score <= self.max_errors {
+            if self.ratchet {
+                self.max_errors = block.score;
+            }
+            return Some(block.score as usize);
+        }
+        None
+    }
+
+    /// Variant of `advance` for patterns of any length.
+    fn advance_blocks<S: StatsRecorder>(
+        &mut self,
+        match_bits: &[W],
+        stats: &mut S,
+    ) -> Option<usize> {
+        let block_count = self.block_count;
+        let blocks = &mut self.blocks;
+        let mut y = self.y;
+        stats.record_char();
 
         // Calculate error count for blocks that we definitely have to process
-        // for this column.
-        let mut carry = 0;
+        // for this column. The first row of the table is zero everywhere, so
+        // that a match can start anywhere, unless the search is anchored.
+        let mut carry = if self.anchored { 1 } else { 0 };
         for b in 0..=y {
             carry = advance_block(&mut blocks[b], match_bits[b], carry);
             blocks[b].score += carry;
+            stats.record_block_advance();
         }
 
         // Check if we also need to compute an additional block, or if we can
         // reduce the number of blocks processed for the next column.
-        if blocks[y].score - carry <= max_errors
-            && y < (block_count - 1)
-            && ((match_bits[y + 1] & 1 != 0) || carry < 0)
+        if y < (block_count - 1)
+            && next_block_may_match(
+                blocks[y].score,
+                carry,
+                match_bits[y + 1] & W::ONE != W::ZERO,
+                self.max_errors,
+            )
         {
             // Error count for bottom block is under threshold. Increase the number
             // of blocks processed for this column and the next by one.
             y += 1;
 
-            blocks[y].plus_v = !0;
-            blocks[y].minus_v = 0;
+            blocks[y].plus_v = !W::ZERO;
+            blocks[y].minus_v = W::ZERO;
 
+            // Number of pattern rows in the block. The last block may be only
+            // partially filled.
             let max_block_score = if y == (block_count - 1) {
-                pattern.len() % BLOCK_LEN
+                self.pattern_len - y * W::BITS
             } else {
-                BLOCK_LEN
+                W::BITS
             };
-            blocks[y].score = blocks[y - 1].score + max_block_score as i32 - carry
+            blocks[y].score = blocks[y - 1].score + max_block_score as isize - carry
                 + advance_block(&mut blocks[y], match_bits[y], carry);
+            stats.record_block_advance();
         } else {
             // Error count for bottom block exceeds threshold. Reduce the number
             // of blocks processed for the next column.
-            while y > 0 && blocks[y].score >= max_errors + BLOCK_LEN as i32 {
+            while y > 0 && block_exceeds_threshold::<W>(blocks[y].score, self.max_errors) {
                 y -= 1;
             }
         }
+        stats.record_active_block(y);
+        self.y = y;
 
         // If error count is under threshold, report a match.
-        if y == (block_count - 1) && blocks[y].score <= max_errors {
-            if blocks[y].score < max_errors {
-                // Discard any earlier, worse matches.
-                matches.clear();
-            }
-
-            matches.push(Match {
-                start: 0,
-                end: j + 1,
-                errors: blocks[y].score as usize,
-            });
-
+        if y == (block_count - 1) && blocks[y].score <= self.max_errors {
             // Because `search` only reports the matches with the lowest error
             // count, we can "ratchet down" the max error threshold whenever a
             // match is encountered and thereby save a small amount of work for
             // the remainder of the text.
-            max_errors = blocks[y].score;
+            if self.ratchet {
+                self.max_errors = blocks[y].score;
+            }
+            return Some(blocks[y].score as usize);
         }
+
+        None
     }
+}
 
-    matches
+/// Return true if the block after the last active block may contain a cell of
+/// the current column which is within the error threshold, so it must be
+/// computed.
+///
+/// `score` is the error count at the last row of the last active block in the
+/// current column, and `carry` is the horizontal delta out of that row, so
+/// `score - carry` is the error count at the same row in the previous column.
+/// `first_row_matches` is true if the text character matches the pattern at
+/// the first row of the next block.
+///
+/// The first cell of the next block is at most the previous column's score
+/// plus the cost of a diagonal step, which is free if the characters match,
+/// or at most the current score plus one, which is lower than the previous
+/// column's score if `carry` is negative. This is the block-growing condition
+/// from section 4.2 of [1], in the form used by [2].
+fn next_block_may_match(
+    score: isize,
+    carry: isize,
+    first_row_matches: bool,
+    max_errors: isize,
+) -> bool {
+    score - carry <= max_errors && (first_row_matches || carry < 0)
 }
 
-fn search_impl(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
-    let matches = find_match_ends(text, pattern, max_errors as usize);
-    find_match_starts(text, pattern, matches)
+/// Return true if no cell of a block with error count `score` at its last row
+/// is within the error threshold, so the block need not be computed for the
+/// next column.
+///
+/// Adjacent rows of a column differ by at most one, so every cell in the
+/// block is at least `score - (W::BITS - 1)`, which is above `max_errors`. A
+/// cell can only come within the threshold in a later column if a cell above
+/// it or to its left is, so the block will be computed again via
+/// `next_block_may_match` once the block above it has such a cell. This is
+/// Ukkonen's cutoff, as used in section 4.2 of [1].
+fn block_exceeds_threshold<W: Word>(score: isize, max_errors: isize) -> bool {
+    score >= max_errors + W::BITS as isize
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::search_impl;
+/// Which matches are reported by `find_match_ends`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Report {
+    /// Only the matches with the lowest error count.
+    Best,
 
-    fn utf16_str(s: &str) -> Vec<u16> {
-        s.encode_utf16().collect()
+    /// A match at every position where the error count is within the threshold.
+    All,
+}
+
+/// Buffers used by `scan_match_ends`, which can be reused between scans.
+struct ScanBuffers<W> {
+    state: ColumnState<W>,
+
+    // Matches found by the scan, with `start` set to zero.
+    ends: Vec<Match>,
+}
+
+impl<W: Word> ScanBuffers<W> {
+    fn new() -> ScanBuffers<W> {
+        ScanBuffers {
+            state: ColumnState::empty(),
+            ends: Vec::new(),
+        }
+    }
+}
+
+/// Find the end positions of matches in `text`, storing them in `bufs.ends`.
+fn scan_match_ends<W: Word, C: Char, B: MatchBits<C, W>, S: StatsRecorder>(
+    text: &[C],
+    pattern_len: usize,
+    bits: &mut B,
+    max_errors: usize,
+    report: Report,
+    bufs: &mut ScanBuffers<W>,
+    stats: &mut S,
+) {
+    let matches = &mut bufs.ends;
+    matches.clear();
+
+    // An empty pattern never matches. See `search`.
+    if pattern_len == 0 {
+        return;
+    }
+
+    let state = &mut bufs.state;
+    state.reset(pattern_len, max_errors, report == Report::Best);
+    scan_with_state(text, bits, state, report, matches, stats);
+}
+
+/// Scan `text` starting from a state prepared by `ColumnState::reset`,
+/// appending the end positions of matches to `matches`.
+fn scan_with_state<W: Word, C: Char, B: MatchBits<C, W>, S: StatsRecorder>(
+    text: &[C],
+    bits: &mut B,
+    state: &mut ColumnState<W>,
+    report: Report,
+    matches: &mut Vec<Match>,
+    stats: &mut S,
+) {
+    for (j, char_code) in text.iter().enumerate() {
+        if let Some(errors) = state.advance(bits.match_bits(*char_code), stats) {
+            if report == Report::Best && matches.last().is_some_and(|m| errors < m.errors) {
+                // Discard any earlier, worse matches.
+                matches.clear();
+            }
+
+            matches.push(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+        }
+    }
+}
+
+/// Find the end positions of matches of a pattern of length `pattern_len` in
+/// `text`, replacing the contents of `out`.
+///
+/// This uses the SIMD scan for long patterns with high error thresholds if the
+/// `simd` feature is enabled, and the bit-vector scan otherwise.
+pub(crate) fn scan_ends_bit_parallel(
+    text: &[u16],
+    pattern_len: usize,
+    bits: &mut PatternBits<u16, BlockWord>,
+    max_errors: usize,
+    report: Report,
+    out: &mut Vec<Match>,
+) {
+    out.clear();
+    if pattern_len == 0 {
+        return;
+    }
+
+    #[cfg(feature = "simd")]
+    if simd::is_preferred(pattern_len, max_errors) {
+        *out = simd::find_match_ends(text, pattern_len, bits, max_errors, report);
+        return;
+    }
+
+    let mut state = ColumnState::<BlockWord>::new(pattern_len, max_errors, report == Report::Best);
+    scan_with_state(text, bits, &mut state, report, out, &mut ());
+}
+
+fn find_match_ends<W: Word, C: Char, B: MatchBits<C, W>, S: StatsRecorder>(
+    text: &[C],
+    pattern_len: usize,
+    bits: &mut B,
+    max_errors: usize,
+    report: Report,
+    stats: &mut S,
+) -> Vec<Match> {
+    let mut bufs = ScanBuffers::new();
+    scan_match_ends(
+        text,
+        pattern_len,
+        bits,
+        max_errors,
+        report,
+        &mut bufs,
+        stats,
+    );
+    bufs.ends
+}
+
+/// Iterator returned by `search_iter`.
+struct SearchIter<'a> {
+    text: &'a [u16],
+    pattern_len: usize,
+    bits: PatternBits<u16, BlockWord>,
+    rev_bits: PatternBits<u16, BlockWord>,
+    state: Option<ColumnState<BlockWord>>,
+    scratch: StartScratch<BlockWord>,
+    pos: usize,
+}
+
+impl<'a> SearchIter<'a> {
+    fn new(text: &'a [u16], pattern: &[u16], max_errors: u32) -> SearchIter<'a> {
+        let opts = MatchOptions::default();
+        SearchIter {
+            text,
+            pattern_len: pattern.len(),
+            bits: PatternBits::new(pattern, opts),
+            rev_bits: PatternBits::new(&reverse(pattern), opts),
+            state: if pattern.is_empty() {
+                None
+            } else {
+                Some(ColumnState::new(pattern.len(), max_errors as usize, true))
+            },
+            scratch: StartScratch::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl Iterator for SearchIter<'_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        let state = self.state.as_mut()?;
+        while self.pos < self.text.len() {
+            let char_code = self.text[self.pos];
+            self.pos += 1;
+
+            if let Some(errors) = state.advance(self.bits.match_bits(char_code), &mut ()) {
+                let mut m = Match {
+                    start: 0,
+                    end: self.pos,
+                    errors,
+                };
+                m.start = find_match_start(
+                    self.text,
+                    self.pattern_len,
+                    &mut self.rev_bits,
+                    &m,
+                    MatchLengthPreference::Longest,
+                    &mut self.scratch,
+                );
+                return Some(m);
+            }
+        }
+        None
+    }
+}
+
+fn search_with_options<W: Word, C: Char>(
+    text: &[C],
+    pattern: &[C],
+    max_errors: u32,
+    opts: MatchOptions,
+    report: Report,
+) -> Vec<Match> {
+    let mut bits = PatternBits::<C, W>::new(pattern, opts);
+    let matches = find_match_ends(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors as usize,
+        report,
+        &mut (),
+    );
+    let mut rev_bits = PatternBits::<C, W>::new(&reverse(pattern), opts);
+    find_match_starts(text, pattern.len(), &mut rev_bits, matches)
+}
+
+// Used by the WASM API, which is not available with `no_std`.
+#[cfg_attr(feature = "no_std", allow(dead_code))]
+fn search_impl(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    search_with_word::<BlockWord>(text, pattern, max_errors)
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, using blocks of type `W`.
+///
+/// This behaves the same as `search`, which uses `u64` blocks, but allows the
+/// block width to be chosen to suit the length of the pattern.
+pub fn search_with_word<W: Word>(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    search_with_options::<W, _>(
+        text,
+        pattern,
+        max_errors,
+        MatchOptions::default(),
+        Report::Best,
+    )
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors.
+///
+/// This is the same as `search` except that `text` and `pattern` are sequences
+/// of Unicode scalar values rather than UTF-16 code units. Characters outside
+/// the Basic Multilingual Plane count as one character, rather than two, and
+/// match offsets are indices into `text`.
+pub fn search_chars(text: &[char], pattern: &[char], max_errors: u32) -> Vec<Match> {
+    search_with_options::<BlockWord, _>(
+        text,
+        pattern,
+        max_errors,
+        MatchOptions::default(),
+        Report::Best,
+    )
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors.
+///
+/// This is the same as `search` except that `text` and `pattern` are byte
+/// strings, such as ASCII or Latin-1 text. Every byte value is treated as a
+/// distinct character and match offsets are byte indices.
+pub fn search_bytes(text: &[u8], pattern: &[u8], max_errors: u32) -> Vec<Match> {
+    search_with_options::<BlockWord, _>(
+        text,
+        pattern,
+        max_errors,
+        MatchOptions::default(),
+        Report::Best,
+    )
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, treating ASCII letters which differ only in case as
+/// equal.
+///
+/// Non-ASCII characters are still compared exactly. Case folding is applied
+/// when the pattern's bit vectors are built, so the scan over the text costs the
+/// same as a case-sensitive search.
+pub fn search_case_insensitive(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    let opts = MatchOptions {
+        ignore_ascii_case: true,
+        ..MatchOptions::default()
+    };
+    search_with_options::<BlockWord, _>(text, pattern, max_errors, opts, Report::Best)
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, where `wildcard` in the pattern matches any single text
+/// character.
+///
+/// For example with a wildcard of `?`, "w?rld" matches "world" and "wurld"
+/// without errors. There is no way to escape the wildcard, so to search for a
+/// pattern containing a literal `?`, choose a different wildcard which does not
+/// occur in the pattern, such as a character from the Private Use Area.
+pub fn search_wildcard(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    wildcard: u16,
+) -> Vec<Match> {
+    let opts = MatchOptions {
+        wildcard: Some(wildcard as u32),
+        ..MatchOptions::default()
+    };
+    search_with_options::<BlockWord, _>(text, pattern, max_errors, opts, Report::Best)
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, where the positions of the pattern for which
+/// `wildcard_mask` is true match any single text character.
+///
+/// This is like `search_wildcard`, but since wildcards are given by position
+/// rather than by a special character, any character can be used in the rest
+/// of the pattern. The character at a wildcard position is ignored.
+///
+/// # Panics
+///
+/// Panics if `wildcard_mask` and `pattern` have different lengths.
+pub fn search_with_mask(
+    text: &[u16],
+    pattern: &[u16],
+    wildcard_mask: &[bool],
+    max_errors: u32,
+) -> Vec<Match> {
+    assert_eq!(
+        wildcard_mask.len(),
+        pattern.len(),
+        "wildcard mask length must match pattern length"
+    );
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::with_mask(pattern, opts, Some(wildcard_mask));
+    let matches = find_match_ends(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors as usize,
+        Report::Best,
+        &mut (),
+    );
+    let rev_mask: Vec<bool> = wildcard_mask.iter().rev().copied().collect();
+    let mut rev_bits =
+        PatternBits::<u16, BlockWord>::with_mask(&reverse(pattern), opts, Some(&rev_mask));
+    find_match_starts(text, pattern.len(), &mut rev_bits, matches)
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, using `eq` to decide whether a pattern character (the
+/// first argument) matches a text character (the second argument).
+///
+/// This allows eg. treating groups of characters as equivalent. Since any text
+/// character may match the pattern, the bit vectors for the pattern cannot be
+/// built up front. Instead the bit vector for each distinct character in the
+/// text is built when it is first encountered by calling `eq` for every
+/// position in the pattern. This adds O(m) work for each distinct text character,
+/// for a pattern of length `m`, on top of the usual cost of the search.
+pub fn search_with_eq<F: Fn(u16, u16) -> bool>(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    eq: F,
+) -> Vec<Match> {
+    let mut bits = PredicateBits::<BlockWord, _>::new(pattern, &eq);
+    let matches = find_match_ends(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors as usize,
+        Report::Best,
+        &mut (),
+    );
+    let pat_rev = reverse(pattern);
+    let mut rev_bits = PredicateBits::<BlockWord, _>::new(&pat_rev, &eq);
+    find_match_starts(text, pattern.len(), &mut rev_bits, matches)
+}
+
+/// Search for all approximate matches of `pattern` in `text` with up to
+/// `max_errors` errors.
+///
+/// Unlike `search`, which only returns the matches with the lowest error count,
+/// this reports a match ending at every position in the text where the error
+/// count is within `max_errors`. Matches ending at nearby positions will often
+/// overlap. As with `search`, `max_errors` is clamped to the length of the
+/// pattern.
+pub fn search_all(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    search_with_options::<BlockWord, _>(
+        text,
+        pattern,
+        max_errors,
+        MatchOptions::default(),
+        Report::All,
+    )
+}
+
+/// Search for all approximate matches of `pattern` in `text` with up to
+/// `max_errors` errors, grouped by error count.
+///
+/// This returns the matches found by `search_all`, so the error threshold is
+/// not lowered as better matches are found, and the whole text is scanned with
+/// the full threshold. Each key of the map is an error count, and its value is
+/// the matches with that error count in order of position in the text. Error
+/// counts with no matches are absent.
+pub fn search_grouped(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> BTreeMap<usize, Vec<Match>> {
+    let mut groups: BTreeMap<usize, Vec<Match>> = BTreeMap::new();
+    for m in search_all(text, pattern, max_errors) {
+        groups.entry(m.errors).or_default().push(m);
+    }
+    groups
+}
+
+/// Search for regions of `text` where `prefix` matches, followed by up to
+/// `max_gap` characters of any text, followed by a match of `suffix`.
+///
+/// This is useful for matching a template with a variable field in the middle,
+/// eg. "hello " and " world" around a name. `max_errors` is shared between the
+/// two parts, so the error count of each result is the sum of the error counts
+/// of the prefix and suffix matches, and the characters in the gap are not
+/// errors. Matches are found as in `search_all`. For each end of a suffix match
+/// there is at most one result, which combines it with the prefix match that
+/// gives the lowest total error count, preferring the earliest start if there
+/// are several. Results are in order of their end position. If either part is
+/// empty there are no results.
+pub fn search_bridged(
+    text: &[u16],
+    prefix: &[u16],
+    suffix: &[u16],
+    max_errors: u32,
+    max_gap: usize,
+) -> Vec<Match> {
+    let max_errors = max_errors as usize;
+    let prefix_matches = search_all(text, prefix, max_errors as u32);
+    if prefix_matches.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches = Vec::new();
+    for s in search_all(text, suffix, max_errors as u32) {
+        // Prefix matches are in order of their end position, so those which
+        // end within `max_gap` characters before the suffix are contiguous.
+        let lo = prefix_matches.partition_point(|p| p.end + max_gap < s.start);
+        let hi = prefix_matches.partition_point(|p| p.end <= s.start);
+        let best = prefix_matches[lo..hi]
+            .iter()
+            .filter(|p| p.errors + s.errors <= max_errors)
+            .min_by_key(|p| (p.errors, p.start));
+        if let Some(p) = best {
+            matches.push(Match {
+                start: p.start,
+                end: s.end,
+                errors: p.errors + s.errors,
+            });
+        }
+    }
+    matches
+}
+
+/// Call `f` with each approximate match of `pattern` in `text` with up to
+/// `max_errors` errors, as they are found.
+///
+/// This reports the same matches as `search_all`, in the same order, without
+/// collecting them into a `Vec`. The start of each match is computed before `f`
+/// is called. If `f` returns `ControlFlow::Break`, the scan stops and the rest
+/// of the text is not processed.
+pub fn search_each<F: FnMut(Match) -> ControlFlow<()>>(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    mut f: F,
+) {
+    if pattern.is_empty() {
+        return;
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut state = ColumnState::new(pattern.len(), max_errors as usize, false);
+    let mut scratch = StartScratch::new();
+
+    for (j, &ch) in text.iter().enumerate() {
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            let mut m = Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            };
+            m.start = find_match_start(
+                text,
+                pattern.len(),
+                &mut rev_bits,
+                &m,
+                MatchLengthPreference::Longest,
+                &mut scratch,
+            );
+            if f(m).is_break() {
+                return;
+            }
+        }
+    }
+}
+
+/// Count the approximate matches of `pattern` in `text` with up to `max_errors`
+/// errors.
+///
+/// This counts every end position where the error count is within the
+/// threshold, so it returns `search_all(text, pattern, max_errors).len()`
+/// without collecting the matches or computing their start positions.
+pub fn count_matches(text: &[u16], pattern: &[u16], max_errors: u32) -> usize {
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+    text.iter()
+        .filter(|&&ch| state.advance(bits.get(ch), &mut ()).is_some())
+        .count()
+}
+
+/// Count the separate regions of `text` where `pattern` approximately occurs
+/// with up to `max_errors` errors.
+///
+/// A region is a maximal run of consecutive end positions at which a match
+/// with up to `max_errors` errors ends, ie. consecutive offsets in the `end`
+/// of the matches returned by `search_all`. One occurrence of the pattern in
+/// the text usually produces several matches ending at adjacent positions,
+/// which are counted as a single region. Two regions are separated by at least
+/// one position where the error count exceeds the threshold, although the
+/// matches in them may still overlap.
+pub fn count_regions(text: &[u16], pattern: &[u16], max_errors: u32) -> usize {
+    if pattern.is_empty() {
+        return 0;
+    }
+
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+    let mut regions = 0;
+    let mut in_region = false;
+    for &ch in text {
+        let is_match = state.advance(bits.get(ch), &mut ()).is_some();
+        if is_match && !in_region {
+            regions += 1;
+        }
+        in_region = is_match;
+    }
+    regions
+}
+
+/// Count the approximate matches of `pattern` in `text` at each error count up
+/// to `max_errors`.
+///
+/// Entry `e` of the result is the number of matches with exactly `e` errors,
+/// counting matches as `count_matches` does, so the entries sum to
+/// `count_matches(text, pattern, max_errors)`. This is useful for choosing a
+/// threshold for a corpus, by scanning with a generous `max_errors` and looking
+/// at how quickly the number of matches grows with the error count. The result
+/// has one entry for each error count from zero to `max_errors`, clamped to the
+/// pattern length, and is empty if the pattern is empty.
+pub fn error_histogram(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<usize> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors, false);
+    let mut counts = vec![0; max_errors + 1];
+    for &ch in text {
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            counts[errors] += 1;
+        }
+    }
+    counts
+}
+
+/// Find the `k` best matches of `pattern` in `text` with up to `max_errors`
+/// errors.
+///
+/// Matches are considered at every end position in the text, as with
+/// `search_all`, but only the best `k` are kept, using memory proportional to
+/// `k`. Where matches beyond the first `k` tie on error count, earlier matches
+/// are preferred. The result is sorted best-first (see `Match`).
+pub fn search_top_k(text: &[u16], pattern: &[u16], max_errors: u32, k: usize) -> Vec<Match> {
+    if pattern.is_empty() || k == 0 {
+        return Vec::new();
+    }
+
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+
+    // Max-heap of the best matches found so far, with the worst at the top.
+    let mut best: BinaryHeap<Match> = BinaryHeap::with_capacity(k + 1);
+
+    for (j, char_code) in text.iter().enumerate() {
+        if let Some(errors) = state.advance(bits.match_bits(*char_code), &mut ()) {
+            if best.len() == k {
+                if best.peek().is_some_and(|worst| errors >= worst.errors) {
+                    continue;
+                }
+                best.pop();
+            }
+            best.push(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+
+            // Once `k` matches have been found, only better matches than the
+            // worst of them are of interest.
+            if best.len() == k {
+                state.max_errors = best.peek().map(|m| m.errors as isize).unwrap_or(0);
+            }
+        }
+    }
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut matches = find_match_starts(text, pattern.len(), &mut rev_bits, best.into_vec());
+    matches.sort();
+    matches
+}
+
+/// Matches returned by `search_limited` and `search_all_limited`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LimitedMatches {
+    /// The matches which were found, in order of position in the text.
+    pub matches: Vec<Match>,
+
+    /// True if there were more matches than the limit, which were discarded.
+    pub truncated: bool,
+}
+
+/// Counts the blocks advanced during a scan, for `SearchLimits::max_work_units`.
+struct WorkCounter(usize);
+
+impl StatsRecorder for WorkCounter {
+    fn record_block_advance(&mut self) {
+        self.0 += 1;
+    }
+}
+
+/// Find matches as `find_match_ends` does, keeping at most `max_results`.
+///
+/// The scan stops once more than `max_work_units` blocks have been advanced.
+/// Returns the matches and the number of text characters scanned.
+fn find_limited_match_ends(
+    text: &[u16],
+    pattern_len: usize,
+    bits: &PatternBits<u16, BlockWord>,
+    max_errors: usize,
+    report: Report,
+    max_results: usize,
+    max_work_units: usize,
+) -> (LimitedMatches, usize) {
+    let mut result = LimitedMatches::default();
+    let matches = &mut result.matches;
+    let mut state = ColumnState::<BlockWord>::new(pattern_len, max_errors, report == Report::Best);
+    let mut work = WorkCounter(0);
+
+    for (j, &ch) in text.iter().enumerate() {
+        if work.0 > max_work_units {
+            return (result, j);
+        }
+        if let Some(errors) = state.advance(bits.get(ch), &mut work) {
+            if report == Report::Best && matches.last().is_some_and(|m| errors < m.errors) {
+                // Discard any earlier, worse matches. Discarded matches beyond
+                // the limit were worse too.
+                matches.clear();
+                result.truncated = false;
+            }
+
+            if matches.len() == max_results {
+                result.truncated = true;
+                if report == Report::All {
+                    // No later match can replace the ones already found.
+                    break;
+                }
+                continue;
+            }
+
+            matches.push(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+        }
+    }
+
+    (result, text.len())
+}
+
+fn search_limited_impl(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    max_results: usize,
+    report: Report,
+) -> LimitedMatches {
+    if pattern.is_empty() {
+        return LimitedMatches::default();
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let (mut result, _) = find_limited_match_ends(
+        text,
+        pattern.len(),
+        &bits,
+        max_errors as usize,
+        report,
+        max_results,
+        usize::MAX,
+    );
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    result.matches = find_match_starts(text, pattern.len(), &mut rev_bits, result.matches);
+    result
+}
+
+/// Variant of `search` which returns at most `max_results` matches.
+///
+/// If there are more matches with the lowest error count, the earliest ones in
+/// the text are returned and `truncated` is set. At most `max_results` matches
+/// are held in memory at once, regardless of how many the text contains. The
+/// whole text is still scanned, since a later match may have fewer errors.
+pub fn search_limited(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    max_results: usize,
+) -> LimitedMatches {
+    search_limited_impl(text, pattern, max_errors, max_results, Report::Best)
+}
+
+/// Variant of `search_all` which returns at most `max_results` matches.
+///
+/// The scan stops once `max_results` matches have been found and another match
+/// is found, in which case `truncated` is set. The earliest matches in the text
+/// are returned.
+pub fn search_all_limited(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    max_results: usize,
+) -> LimitedMatches {
+    search_limited_impl(text, pattern, max_errors, max_results, Report::All)
+}
+
+/// Limits on the resources used by `search_with_limits`.
+///
+/// These protect against inputs which make a search expensive, such as a long
+/// pattern with a high error threshold and a long, repetitive text. The
+/// default is no limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Maximum number of matches to return, as with `search_limited`.
+    pub max_results: Option<usize>,
+
+    /// Maximum number of blocks of the pattern which may be processed while
+    /// scanning the text.
+    ///
+    /// Each text character costs between one and `ceil(m / 64)` units, for a
+    /// pattern of length `m`, depending on how many blocks of the pattern
+    /// could be within the error threshold. Finding the start of each match
+    /// is not counted, since there are at most `max_results` of them.
+    pub max_work_units: Option<usize>,
+}
+
+/// Variant of `search_limited` which also limits how much work the search may
+/// do.
+///
+/// If the scan of the text exceeds `limits.max_work_units`, it is abandoned
+/// and `SearchLimitExceeded` is returned. This holds the matches found in the
+/// part of the text which was scanned, which are the matches `search_limited`
+/// would return for that part, but may not be the best matches in the whole
+/// text.
+pub fn search_with_limits(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    limits: &SearchLimits,
+) -> Result<LimitedMatches, SearchLimitExceeded> {
+    if pattern.is_empty() {
+        return Ok(LimitedMatches::default());
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let (mut result, scanned) = find_limited_match_ends(
+        text,
+        pattern.len(),
+        &bits,
+        max_errors as usize,
+        Report::Best,
+        limits.max_results.unwrap_or(usize::MAX),
+        limits.max_work_units.unwrap_or(usize::MAX),
+    );
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    result.matches = find_match_starts(text, pattern.len(), &mut rev_bits, result.matches);
+
+    if scanned < text.len() {
+        Err(SearchLimitExceeded {
+            partial: result,
+            scanned,
+        })
+    } else {
+        Ok(result)
+    }
+}
+
+fn find_first_impl<S: StatsRecorder>(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    stats: &mut S,
+) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+
+    for (j, char_code) in text.iter().enumerate() {
+        if let Some(errors) = state.advance(bits.match_bits(*char_code), stats) {
+            let mut m = Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            };
+            let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+            m.start = find_match_start(
+                text,
+                pattern.len(),
+                &mut rev_bits,
+                &m,
+                MatchLengthPreference::Longest,
+                &mut StartScratch::new(),
+            );
+            return Some(m);
+        }
+    }
+
+    None
+}
+
+/// Find the first approximate match of `pattern` in `text` with up to
+/// `max_errors` errors.
+///
+/// This returns the match which ends earliest in the text, which is not
+/// necessarily the best match. The scan stops as soon as the match is found, so
+/// the remainder of the text is not processed.
+pub fn find_first(text: &[u16], pattern: &[u16], max_errors: u32) -> Option<Match> {
+    find_first_impl(text, pattern, max_errors, &mut ())
+}
+
+/// Find the last approximate match of `pattern` in `text` with up to
+/// `max_errors` errors.
+///
+/// This is the counterpart of `find_first`. It returns the match which starts
+/// latest in the text, which is not necessarily the best match. The text is
+/// scanned from the end, stopping as soon as the match is found.
+pub fn find_last(text: &[u16], pattern: &[u16], max_errors: u32) -> Option<Match> {
+    // A match of the reversed pattern in the reversed text corresponds to a
+    // match of the pattern in the text, with the same error count. The
+    // earliest end in the reversed text is the latest start in the text.
+    let m = find_first_impl(&reverse(text), &reverse(pattern), max_errors, &mut ())?;
+    Some(Match {
+        start: text.len() - m.end,
+        end: text.len() - m.start,
+        errors: m.errors,
+    })
+}
+
+fn contains_impl<S: StatsRecorder>(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    stats: &mut S,
+) -> bool {
+    if pattern.is_empty() {
+        return false;
+    }
+
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+    text.iter()
+        .any(|&ch| state.advance(bits.get(ch), stats).is_some())
+}
+
+/// Return true if `text` contains an approximate match of `pattern` with up to
+/// `max_errors` errors.
+///
+/// This is equivalent to `!search(text, pattern, max_errors).is_empty()`, but
+/// the scan stops at the first match, and no start positions are computed.
+pub fn contains(text: &[u16], pattern: &[u16], max_errors: u32) -> bool {
+    contains_impl(text, pattern, max_errors, &mut ())
+}
+
+/// Result of `search_summary`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchSummary {
+    /// The first match, in order of end position, with the lowest error count,
+    /// or `None` if there is no match within the threshold.
+    pub best: Option<Match>,
+
+    /// Number of text characters which were scanned. This is less than the
+    /// length of the text if the scan stopped early at an exact match.
+    pub examined_positions: usize,
+}
+
+impl MatchSummary {
+    /// Return true if the best match has no errors, ie. the error threshold
+    /// was lowered all the way to zero.
+    pub fn is_exact(&self) -> bool {
+        self.best.as_ref().is_some_and(Match::is_exact)
+    }
+}
+
+/// Find the best approximate match of `pattern` in `text` allowing up to
+/// `max_errors` errors, and report how much of the text was scanned.
+///
+/// As the text is scanned, the error threshold is lowered to the best error
+/// count found so far, as in `search`. Once an exact match is found no better
+/// match is possible, so the scan stops there. Only the start of the best
+/// match is computed.
+pub fn search_summary(text: &[u16], pattern: &[u16], max_errors: u32) -> MatchSummary {
+    let mut summary = MatchSummary::default();
+    if pattern.is_empty() {
+        return summary;
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, true);
+
+    for (j, &ch) in text.iter().enumerate() {
+        summary.examined_positions = j + 1;
+        let Some(errors) = state.advance(bits.get(ch), &mut ()) else {
+            continue;
+        };
+        if summary.best.as_ref().is_none_or(|m| errors < m.errors) {
+            summary.best = Some(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+        }
+        if errors == 0 {
+            break;
+        }
+    }
+
+    if let Some(m) = summary.best.as_mut() {
+        let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+        m.start = find_match_start(
+            text,
+            pattern.len(),
+            &mut rev_bits,
+            m,
+            MatchLengthPreference::Longest,
+            &mut StartScratch::new(),
+        );
+    }
+    summary
+}
+
+/// Find the single best approximate match of `pattern` in `text`, with no limit
+/// on the number of errors.
+///
+/// This returns the first match in "best first" order (see `Match`), ie. the
+/// match with the fewest errors, and the earliest among those. It is the same
+/// as the first of the matches returned by `search` with `max_errors` set to
+/// the length of the pattern, but only the current best match is kept during
+/// the scan. Returns `None` if the pattern is empty.
+pub fn best_match(text: &[u16], pattern: &[u16]) -> Option<Match> {
+    best_match_within(text, pattern, pattern.len())
+}
+
+/// Variant of `best_match` which starts with an error threshold of
+/// `initial_max_errors`, rather than the length of the pattern.
+///
+/// The threshold is lowered as better matches are found, as with `best_match`,
+/// but with a low starting threshold less of the pattern needs to be
+/// processed for each text character from the start of the scan. This is
+/// useful when re-searching after a small edit to the text, when the error
+/// count of the previous best match is a good guess. If there is no match
+/// within the hint, the search is repeated with no limit, so the result is
+/// always the same as that of `best_match`.
+pub fn best_match_hinted(text: &[u16], pattern: &[u16], initial_max_errors: u32) -> Option<Match> {
+    let hint = (initial_max_errors as usize).min(pattern.len());
+    best_match_within(text, pattern, hint).or_else(|| {
+        if hint < pattern.len() {
+            best_match_within(text, pattern, pattern.len())
+        } else {
+            None
+        }
+    })
+}
+
+/// Find the best approximate match of `pattern` in `text` and the best match
+/// which is distinct from it, with no limit on the number of errors.
+///
+/// The first match is the one returned by `best_match`. The second is the
+/// first in "best first" order (see `Match`) of the matches which do not
+/// overlap the first, ie. which lie entirely before its start or entirely
+/// after its end. The difference between their error counts indicates how
+/// clearly the best match stands out, when deciding whether to accept it.
+///
+/// The second match is `None` if there is no text before or after the first,
+/// and both are `None` if the pattern is empty or there is no match at all.
+/// This runs `best_match` on the whole text and then on the parts before and
+/// after the first match, so it takes about twice as long, and like
+/// `best_match` it only keeps the current best match during each scan.
+pub fn best_two(text: &[u16], pattern: &[u16]) -> (Option<Match>, Option<Match>) {
+    let Some(first) = best_match(text, pattern) else {
+        return (None, None);
+    };
+
+    // A match in the text after the first must have fewer errors than the
+    // best one before it in order to be preferred, since it starts later.
+    let before = best_match(&text[..first.start], pattern);
+    let after_max_errors = match &before {
+        Some(m) if m.errors == 0 => None,
+        Some(m) => Some(m.errors - 1),
+        None => Some(pattern.len()),
+    };
+    let after = after_max_errors.and_then(|max_errors| {
+        let mut m = best_match_within(&text[first.end..], pattern, max_errors)?;
+        m.start += first.end;
+        m.end += first.end;
+        Some(m)
+    });
+
+    (Some(first), after.or(before))
+}
+
+/// Find the best approximate match of `query` in each of `fields`, allowing up
+/// to `max_errors` errors.
+///
+/// This is for searching records with several text fields, such as a title
+/// and body. Entry `i` of the result is the match in `fields[i]` with the
+/// lowest error count, preferring the earliest if there are several, or `None`
+/// if there is no match in that field. Offsets are relative to the field. The
+/// query is compiled once and reused for every field (see `CompiledPattern`),
+/// so this is cheaper than calling `search` for each.
+pub fn search_fields(query: &[u16], fields: &[&[u16]], max_errors: u32) -> Vec<Option<Match>> {
+    let search = CompiledPattern::new(query).with_max_errors(max_errors);
+    fields
+        .iter()
+        .map(|field| search.search(field).into_iter().next())
+        .collect()
+}
+
+/// Find the best match as `best_match` does, considering only matches with up
+/// to `max_errors` errors.
+fn best_match_within(text: &[u16], pattern: &[u16], max_errors: usize) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors, true);
+    let mut scratch = StartScratch::new();
+
+    let mut best: Option<Match> = None;
+
+    for (j, &ch) in text.iter().enumerate() {
+        let Some(errors) = state.advance(bits.get(ch), &mut ()) else {
+            continue;
+        };
+        let end = j + 1;
+
+        // Because of the ratchet, `errors` is never worse than `best`. A match
+        // with the same error count can only be better if it could start
+        // earlier.
+        if let Some(best) = &best {
+            let min_start = end.saturating_sub(pattern.len() + errors);
+            if errors == best.errors && best.start <= min_start {
+                continue;
+            }
+        }
+
+        let mut m = Match {
+            start: 0,
+            end,
+            errors,
+        };
+        m.start = find_match_start(
+            text,
+            pattern.len(),
+            &mut rev_bits,
+            &m,
+            MatchLengthPreference::Longest,
+            &mut scratch,
+        );
+        if best.as_ref().is_none_or(|best| m < *best) {
+            best = Some(m);
+        }
+    }
+
+    best
+}
+
+/// Result of `search_or_closest`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClosestMatches {
+    /// The matches with the lowest error count, which is within the threshold.
+    WithinThreshold(Vec<Match>),
+
+    /// There were no matches within the threshold. This is the closest match,
+    /// as returned by `best_match`, which has more errors than the threshold.
+    OverThreshold(Match),
+
+    /// There were no matches at all, because the pattern or text is empty.
+    NoMatch,
+}
+
+/// Search for approximate matches of `pattern` in `text` with up to
+/// `preferred_max_errors` errors, falling back to the closest match if there
+/// are none.
+///
+/// This returns the same matches as `search` if there are any. Otherwise it
+/// returns the result of `best_match`, marked as being over the threshold.
+pub fn search_or_closest(
+    text: &[u16],
+    pattern: &[u16],
+    preferred_max_errors: u32,
+) -> ClosestMatches {
+    let matches = search(text, pattern, preferred_max_errors);
+    if !matches.is_empty() {
+        return ClosestMatches::WithinThreshold(matches);
+    }
+    match best_match(text, pattern) {
+        Some(m) => ClosestMatches::OverThreshold(m),
+        None => ClosestMatches::NoMatch,
+    }
+}
+
+/// Return the lowest error count of a match of `pattern` which ends at each
+/// position in `text`.
+///
+/// Entry `j` of the result is the lowest error count of any match whose last
+/// character is `text[j]`, or `max_errors + 1` if there is no match with at
+/// most `max_errors` errors there. The exact count is not known in that case,
+/// because rows of the table which are already over the threshold are not
+/// computed. This is useful for understanding why a region of the text did or
+/// did not match. An empty pattern never matches, so every entry is
+/// `max_errors + 1`.
+pub fn score_profile(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<usize> {
+    let over_threshold = max_errors as usize + 1;
+    if pattern.is_empty() {
+        return vec![over_threshold; text.len()];
+    }
+
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, false);
+    text.iter()
+        .map(|&ch| {
+            state
+                .advance(bits.get(ch), &mut ())
+                .unwrap_or(over_threshold)
+        })
+        .collect()
+}
+
+/// Find the best match of `pattern` with up to `max_errors` errors which ends
+/// at offset `end` in `text`, ie. whose last character is `text[end - 1]`.
+///
+/// This returns the match with the lowest error count ending there, with the
+/// earliest start among those, or `None` if every match ending there has more
+/// than `max_errors` errors. This still needs to scan `text[..end]`, since the
+/// error counts of each column depend on the columns before it, but only the
+/// start of the one match is computed.
+///
+/// # Panics
+///
+/// Panics if `end` is greater than `text.len()`.
+pub fn matches_at(text: &[u16], pattern: &[u16], max_errors: u32, end: usize) -> Option<Match> {
+    assert!(end <= text.len(), "end is beyond the end of the text");
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let max_errors = (max_errors as usize).min(pattern.len());
+    if end == 0 {
+        // Only the empty match, which deletes every pattern character, ends at
+        // the start of the text.
+        return (pattern.len() <= max_errors).then_some(Match {
+            start: 0,
+            end: 0,
+            errors: pattern.len(),
+        });
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors, false);
+    for &ch in &text[..end - 1] {
+        state.advance(bits.get(ch), &mut ());
+    }
+    let errors = state.advance(bits.get(text[end - 1]), &mut ())?;
+
+    let mut m = Match {
+        start: 0,
+        end,
+        errors,
+    };
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    m.start = find_match_start(
+        text,
+        pattern.len(),
+        &mut rev_bits,
+        &m,
+        MatchLengthPreference::Longest,
+        &mut StartScratch::new(),
+    );
+    Some(m)
+}
+
+/// Return every start position from which `pattern` matches `text[start..end]`
+/// with exactly `errors` errors, in ascending order.
+///
+/// The search functions pick one of these for each match, the earliest by
+/// default (see `MatchLengthPreference`). This returns all of them, so callers
+/// can apply their own policy, eg. `match_starts_for_end(text, pattern,
+/// m.end(), m.errors())` for a match `m`. If `errors` is lower than the error
+/// count of every match ending at `end`, the result is empty. The result
+/// includes `end` itself if deleting the whole pattern, which costs
+/// `pattern.len()` errors, gives a match with `errors` errors.
+///
+/// # Panics
+///
+/// Panics if `end` is greater than `text.len()`.
+pub fn match_starts_for_end(
+    text: &[u16],
+    pattern: &[u16],
+    end: usize,
+    errors: usize,
+) -> Vec<usize> {
+    assert!(end <= text.len(), "end is beyond the end of the text");
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    // As in `find_match_start`, scan the reversed pattern backwards from
+    // `end`, anchored there, so the error count of each column is that of the
+    // match which starts there.
+    let rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), MatchOptions::default());
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), pattern.len(), false);
+    state.anchored = true;
+    state.max_errors = errors as isize;
+
+    let min_start = end.saturating_sub(pattern.len() + errors);
+    let mut starts: Vec<usize> = text[min_start..end]
+        .iter()
+        .rev()
+        .enumerate()
+        .filter_map(|(j, &ch)| {
+            let column_errors = state.advance(rev_bits.get(ch), &mut ())?;
+            (column_errors == errors).then_some(end - (j + 1))
+        })
+        .collect();
+    starts.reverse();
+    if pattern.len() == errors {
+        starts.push(end);
+    }
+    starts
+}
+
+/// Return the error threshold which is actually used when searching for a
+/// pattern of `pattern_len` code units with up to `max_errors` errors.
+///
+/// A match can never need more errors than the length of the pattern, since
+/// every pattern character can be deleted, so the search functions clamp
+/// `max_errors` to the pattern length. A result lower than `max_errors` means
+/// that the threshold was higher than it needs to be, and every position in the
+/// text will match. `try_search` reports this case as an error instead.
+pub fn effective_max_errors(pattern_len: usize, max_errors: u32) -> u32 {
+    (max_errors as usize).min(pattern_len) as u32
+}
+
+/// Return true if a search for a pattern of `pattern_len` code units in a text
+/// of `text_len` code units with up to `max_errors` errors could find a match.
+///
+/// A match covers at most `text_len` characters of the text, so at least
+/// `pattern_len - text_len` pattern characters must be deleted when the text
+/// is shorter than the pattern. This returns true exactly when
+/// `max_errors >= pattern_len.saturating_sub(text_len)`, `pattern_len > 0` and
+/// `text_len > 0`. An empty pattern never matches, and nor does an empty text,
+/// even if every pattern character could be deleted, since each match ends
+/// after a character of the text.
+///
+/// This depends only on the lengths, so it is a necessary condition rather
+/// than a sufficient one: when it returns false, `search` and `search_all`
+/// are certain to find no matches, and the search can be skipped.
+pub fn can_match_within(pattern_len: usize, text_len: usize, max_errors: u32) -> bool {
+    pattern_len > 0 && text_len > 0 && max_errors as usize >= pattern_len.saturating_sub(text_len)
+}
+
+/// Convert an error threshold expressed as a fraction of the pattern length to
+/// an error count.
+///
+/// This is `floor(ratio * pattern_len)`, clamped to `0..=pattern_len`. Products
+/// which are within floating point rounding error of the next integer are
+/// rounded up, so that eg. `0.29 * 100` gives 29 rather than 28. A ratio which
+/// is negative or NaN gives 0.
+fn max_errors_for_ratio(pattern_len: usize, ratio: f64) -> u32 {
+    let errors = ratio * pattern_len as f64;
+
+    // Casting truncates towards zero and saturates, mapping negative values
+    // and NaN to 0.
+    let mut floor = errors as usize;
+    if floor as f64 + 1.0 - errors < 1e-9 {
+        floor = floor.saturating_add(1);
+    }
+    floor.min(pattern_len) as u32
+}
+
+/// Search for approximate matches of `pattern` in `text`, allowing a number of
+/// errors proportional to the length of the pattern.
+///
+/// For example a `max_error_ratio` of 0.25 allows up to 25% of the pattern's
+/// characters to be errors. The threshold is rounded down to a whole number of
+/// errors, so a ratio of 0.25 allows 2 errors for a pattern of length 8 but
+/// only 1 error for a pattern of length 7. Otherwise this behaves like `search`.
+pub fn search_ratio(text: &[u16], pattern: &[u16], max_error_ratio: f64) -> Vec<Match> {
+    search(
+        text,
+        pattern,
+        max_errors_for_ratio(pattern.len(), max_error_ratio),
+    )
+}
+
+/// Return the similarity of `pattern` to its best match in `text`, from 0.0
+/// (no similarity) to 1.0 (an exact match).
+///
+/// This is `1.0 - errors / pattern.len()` for the match with the fewest errors,
+/// which makes it comparable across patterns of different lengths. Returns 0.0
+/// if the pattern is empty.
+pub fn best_similarity(text: &[u16], pattern: &[u16]) -> f64 {
+    if pattern.is_empty() {
+        return 0.0;
+    }
+
+    // Only the error count is needed, so the reverse pass to find the start of
+    // the match is skipped.
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, MatchOptions::default());
+    let ends = find_match_ends(
+        text,
+        pattern.len(),
+        &mut bits,
+        pattern.len(),
+        Report::Best,
+        &mut (),
+    );
+    let errors = ends.first().map(|m| m.errors).unwrap_or(pattern.len());
+    (1.0 - errors as f64 / pattern.len() as f64).clamp(0.0, 1.0)
+}
+
+/// A pattern which has been preprocessed for searching.
+///
+/// Each search needs to build tables of the positions where each character
+/// occurs in the pattern, and in the reversed pattern. When the same pattern is
+/// searched for in many texts, a `CompiledPattern` lets this work be done once.
+///
+/// A `CompiledPattern` owns its tables and does not borrow the pattern it was
+/// created from, so it is `'static`. It is also `Send` and `Sync`, and searches
+/// only need `&self`, so one pattern can be shared between threads or tasks,
+/// eg. in an `Arc` or held across `.await` points. Cloning is cheap, since the
+/// tables are shared between clones. The same applies to `CompiledSearch`.
+#[derive(Clone)]
+pub struct CompiledPattern {
+    len: usize,
+    bits: PatternBits<u16, BlockWord>,
+    rev_bits: PatternBits<u16, BlockWord>,
+}
+
+impl CompiledPattern {
+    /// Preprocess `pattern`, a sequence of UTF-16 code units, for searching.
+    pub fn new(pattern: &[u16]) -> CompiledPattern {
+        let opts = MatchOptions::default();
+        CompiledPattern {
+            len: pattern.len(),
+            bits: PatternBits::new(pattern, opts),
+            rev_bits: PatternBits::new(&reverse(pattern), opts),
+        }
+    }
+
+    /// Return the length of the pattern in UTF-16 code units.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the pattern is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append `ch` to the pattern.
+    ///
+    /// This updates the preprocessed tables in place, which is cheaper than
+    /// creating a new `CompiledPattern` when a pattern is built up one
+    /// character at a time, eg. as a user types a query. The result is the same
+    /// as compiling the extended pattern from scratch.
+    pub fn push_char(&mut self, ch: u16) {
+        self.bits.push_back(ch, self.len);
+        self.rev_bits.push_front(ch, self.len);
+        self.len += 1;
+    }
+
+    /// Return the number of distinct code units in the pattern.
+    ///
+    /// Preprocessing creates a bit vector of `block_count` words for each of
+    /// these, so patterns with many distinct characters take more memory.
+    pub fn distinct_chars(&self) -> usize {
+        self.bits.distinct_chars()
+    }
+
+    /// Return the number of 64-bit words needed to represent the pattern's
+    /// positions.
+    ///
+    /// The cost of a search grows with the number of blocks which must be
+    /// processed for each text character, which is at most this many.
+    pub fn block_count(&self) -> usize {
+        self.bits.block_count
+    }
+
+    /// Search for approximate matches of the pattern in `text` allowing up to
+    /// `max_errors` errors.
+    ///
+    /// This returns the same result as the `search` function.
+    pub fn search(&self, text: &[u16], max_errors: u32) -> Vec<Match> {
+        let matches = find_match_ends(
+            text,
+            self.len,
+            &mut &self.bits,
+            max_errors as usize,
+            Report::Best,
+            &mut (),
+        );
+        find_match_starts(text, self.len, &mut &self.rev_bits, matches)
+    }
+
+    /// Fix the error threshold for searches with this pattern.
+    ///
+    /// The returned `CompiledSearch` also caches the initial state of the scan,
+    /// which depends on `max_errors`.
+    pub fn with_max_errors(self, max_errors: u32) -> CompiledSearch {
+        let initial_state = if self.len == 0 {
+            ColumnState::empty()
+        } else {
+            ColumnState::new(self.len, max_errors as usize, true)
+        };
+        CompiledSearch {
+            pattern: self,
+            max_errors,
+            initial_state,
+        }
+    }
+}
+
+/// A `CompiledPattern` with a fixed error threshold, created by
+/// `CompiledPattern::with_max_errors`.
+///
+/// In addition to the pattern's tables, this caches the initial state of the
+/// scan: the score, last row mask and bit vectors of each block, and the number
+/// of blocks which are initially active. Each search starts from a copy of this
+/// state, so only the work which depends on the text is done for each call.
+#[derive(Clone)]
+pub struct CompiledSearch {
+    pattern: CompiledPattern,
+    max_errors: u32,
+    initial_state: ColumnState<BlockWord>,
+}
+
+impl CompiledSearch {
+    /// Return the pattern which this searches for.
+    pub fn pattern(&self) -> &CompiledPattern {
+        &self.pattern
+    }
+
+    /// Return the error threshold which was passed to
+    /// `CompiledPattern::with_max_errors`.
+    pub fn max_errors(&self) -> u32 {
+        self.max_errors
+    }
+
+    /// Search for approximate matches of the pattern in `text`.
+    ///
+    /// This returns the same result as `CompiledPattern::search` with the fixed
+    /// error threshold.
+    pub fn search(&self, text: &[u16]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        if self.pattern.is_empty() {
+            return matches;
+        }
+
+        let mut state = self.initial_state.clone();
+        scan_with_state(
+            text,
+            &mut &self.pattern.bits,
+            &mut state,
+            Report::Best,
+            &mut matches,
+            &mut (),
+        );
+        find_match_starts(text, self.pattern.len, &mut &self.pattern.rev_bits, matches)
+    }
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors.
+///
+/// `text` is the text to search in and `pattern` is the text to search for.
+/// `search_needle` takes the same arguments with distinct types, so that they
+/// can't be passed in the wrong order.
+///
+/// `text` and `pattern` are sequences of UTF-16 code units. They can be of any
+/// type which implements `AsRef<[u16]>`, such as `&[u16]`, `Vec<u16>`,
+/// `&Vec<u16>` or `Box<[u16]>`, and are only borrowed as slices, never copied.
+///
+/// Only the matches with the lowest error count are returned. If several
+/// regions of the text tie for the lowest error count, a match is returned for
+/// each of them. Use `search_all` to also get the matches with higher error
+/// counts.
+///
+/// In particular, if the pattern occurs exactly anywhere in the text, only
+/// exact matches are returned, and approximate matches are returned only when
+/// there is no exact one. This is determined in a single scan of the text.
+///
+/// `max_errors` is clamped to the length of the pattern (see
+/// `effective_max_errors`). The text may be shorter than the pattern, in which
+/// case a match needs at least `pattern.len() - text.len()` errors.
+///
+/// An empty pattern never matches, so the result is empty. The empty string
+/// could be said to occur at every position of the text, but a zero-length
+/// match at each offset is never useful to callers, and is what a search for
+/// a pattern computed from empty input would usually produce by accident. All
+/// search functions in this crate follow this rule, and `try_search` reports
+/// an empty pattern as `SearchError::EmptyPattern`.
+///
+/// Matches are returned in ascending order of `start`. Matches with the same
+/// start are ordered by `end`.
+///
+/// A pattern which is a single character repeated, such as a run of padding,
+/// is searched for by counting copies of the character in a sliding window
+/// rather than with the bit-vector scan, and when `max_errors` is zero an exact
+/// substring search is used. The results are the same.
+pub fn search(text: impl AsRef<[u16]>, pattern: impl AsRef<[u16]>, max_errors: u32) -> Vec<Match> {
+    let mut matches = Vec::new();
+    search_into(text.as_ref(), pattern.as_ref(), max_errors, &mut matches);
+    matches
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, replacing the contents of `out` with the result.
+///
+/// The matches are the same as those returned by `search`, but are written to
+/// the caller's vector so that its allocation can be reused across searches.
+/// `Searcher::search_into` also reuses the buffers used during the search.
+pub fn search_into(text: &[u16], pattern: &[u16], max_errors: u32, out: &mut Vec<Match>) {
+    out.clear();
+    if pattern.is_empty() {
+        return;
+    }
+    if max_errors == 0 {
+        // Without errors this is an exact substring search, which is much
+        // cheaper than the bit-vector scan.
+        *out = exact::find_exact(text, pattern, false);
+        return;
+    }
+    if let Some(ch) = repeated::repeated_char(pattern) {
+        repeated::find_repeated(text, ch, pattern.len(), max_errors as usize, out);
+        return;
+    }
+
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    scan_ends_bit_parallel(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors as usize,
+        Report::Best,
+        out,
+    );
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut scratch = StartScratch::new();
+    for m in out.iter_mut() {
+        m.start = find_match_start(
+            text,
+            pattern.len(),
+            &mut rev_bits,
+            m,
+            MatchLengthPreference::Longest,
+            &mut scratch,
+        );
+    }
+}
+
+/// Variant of `search` which reports invalid inputs instead of ignoring them.
+///
+/// Returns an error if `pattern` is empty or `max_errors` exceeds its length.
+pub fn try_search(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> Result<Vec<Match>, SearchError> {
+    SearchError::check(pattern, max_errors)?;
+    Ok(search(text, pattern, max_errors))
+}
+
+/// Search for approximate matches of `pattern` in the part of `text` given by
+/// `range`, allowing up to `max_errors` errors.
+///
+/// The returned offsets are relative to the whole of `text`. Since a match can
+/// be up to `max_errors` characters longer than the pattern, the search covers
+/// the length of the pattern plus `max_errors` characters on either side of
+/// `range`, so that a match which extends outside it is found in full. Matches
+/// which lie entirely outside `range` are discarded, so every returned match
+/// overlaps `range` but may extend beyond it. Of the remaining matches, only
+/// those with the lowest error count are returned, as with `search`.
+///
+/// # Panics
+///
+/// Panics if `range` is out of bounds for `text`.
+pub fn search_in_range(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    range: core::ops::Range<usize>,
+) -> Vec<Match> {
+    assert!(
+        range.start <= range.end && range.end <= text.len(),
+        "range {:?} out of bounds for text of length {}",
+        range,
+        text.len()
+    );
+    let margin = pattern.len().saturating_add(max_errors as usize);
+    let window_start = range.start.saturating_sub(margin);
+    let window_end = range.end.saturating_add(margin).min(text.len());
+
+    // Discard matches outside the range before choosing the best ones, so that
+    // a better match just outside it does not hide the ones inside it.
+    let mut matches = search_all(&text[window_start..window_end], pattern, max_errors);
+    for m in matches.iter_mut() {
+        m.start += window_start;
+        m.end += window_start;
+    }
+    matches.retain(|m| m.start < range.end && m.end > range.start);
+    if let Some(best) = matches.iter().map(|m| m.errors).min() {
+        matches.retain(|m| m.errors == best);
+    }
+    matches
+}
+
+/// Lazily search for approximate matches of `pattern` in `text`, allowing up to
+/// `max_errors` errors.
+///
+/// Matches are produced as the text is scanned, so iteration can be stopped
+/// early without processing the rest of the text. Unlike `search`, earlier
+/// matches are not discarded when a better match is found later on. Instead the
+/// error threshold is lowered to the best error count seen so far, so each match
+/// has no more errors than the matches before it.
+pub fn search_iter<'a>(
+    text: &'a [u16],
+    pattern: &'a [u16],
+    max_errors: u32,
+) -> impl Iterator<Item = Match> + 'a {
+    SearchIter::new(text, pattern, max_errors)
+}
+
+/// Lazily search for approximate matches of `pattern` in `text`, as
+/// `search_iter` does, yielding each match together with the matched part of
+/// `text`.
+///
+/// The slice is `&text[m.range()]`, so like the match offsets it may begin or
+/// end in the middle of a surrogate pair. Use `snap_to_char_boundaries` on the
+/// match if the slice needs to be decoded.
+pub fn search_slices<'a>(
+    text: &'a [u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> impl Iterator<Item = (Match, &'a [u16])> + 'a {
+    SearchIter::new(text, pattern, max_errors).map(move |m| {
+        let slice = &text[m.range()];
+        (m, slice)
+    })
+}
+
+/// Search for approximate matches of `pattern` in `text`, also returning
+/// counters describing how much work the search did.
+#[cfg(feature = "stats")]
+pub fn search_stats(text: &[u16], pattern: &[u16], max_errors: u32) -> (Vec<Match>, SearchStats) {
+    let opts = MatchOptions::default();
+    let mut stats = SearchStats::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let matches = find_match_ends(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors as usize,
+        Report::Best,
+        &mut stats,
+    );
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    (
+        find_match_starts(text, pattern.len(), &mut rev_bits, matches),
+        stats,
+    )
+}
+
+/// Search for approximate matches of `pattern` in `text`, also returning the
+/// error count computed for each column of the table.
+///
+/// For each position `j` in the text, entry `j` of the trace is the error count
+/// at the last row of the last block of the pattern which was computed after
+/// processing `text[j]`. When every block was computed, this is the lowest
+/// error count of a match ending at `text[j]`, and a match is reported there
+/// if it is within the threshold. Otherwise it is the error count partway
+/// through the pattern, which shows how close the scan came to a match. The
+/// threshold is lowered as matches are found, as in `search`, which can reduce
+/// the number of blocks computed. An empty pattern gives an empty trace.
+#[cfg(feature = "trace")]
+pub fn search_traced(text: &[u16], pattern: &[u16], max_errors: u32) -> (Vec<Match>, Vec<isize>) {
+    if pattern.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let opts = MatchOptions::default();
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, true);
+    let mut matches = Vec::new();
+    let mut trace = Vec::with_capacity(text.len());
+    for (j, &ch) in text.iter().enumerate() {
+        if let Some(errors) = state.advance(bits.match_bits(ch), &mut ()) {
+            if matches.last().is_some_and(|m: &Match| errors < m.errors) {
+                matches.clear();
+            }
+            matches.push(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+        }
+        trace.push(state.blocks[state.y].score);
+    }
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    (
+        find_match_starts(text, pattern.len(), &mut rev_bits, matches),
+        trace,
+    )
+}
+
+/// Estimate the work done by `search` for a pattern of `pattern_len` code
+/// units and a text of `text_len` code units, allowing up to `max_errors`
+/// errors.
+///
+/// The result is measured in block steps, each of which processes one
+/// character of the text against 64 characters of the pattern (see
+/// `SearchStats::block_advances`). The scan usually only processes the blocks
+/// of the pattern needed to cover `max_errors` errors, so it costs about
+/// `max_errors.div_ceil(64) * text_len` steps. Finding where the best match
+/// starts adds a scan over `pattern_len + max_errors` characters with every
+/// block. The cost of an actual search can be higher, up to
+/// `pattern_len.div_ceil(64) * text_len` steps, if the text is similar enough
+/// to the pattern that extra blocks are needed for many characters.
+pub fn estimate_cost(pattern_len: usize, text_len: usize, max_errors: usize) -> usize {
+    if pattern_len == 0 {
+        return 0;
+    }
+
+    let block_len = <BlockWord as Word>::BITS;
+    let block_count = pattern_len.div_ceil(block_len);
+    let max_errors = max_errors.min(pattern_len);
+
+    let active_blocks = max_errors.div_ceil(block_len).clamp(1, block_count);
+    let scan_cost = active_blocks.saturating_mul(text_len);
+    let start_cost = (pattern_len + max_errors)
+        .min(text_len)
+        .saturating_mul(block_count);
+    scan_cost.saturating_add(start_cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::ops::ControlFlow;
+
+    use crate::test_util::Random;
+    use crate::{
+        advance_block, best_match, best_match_hinted, best_similarity, best_two,
+        block_exceeds_threshold, can_match_within, contains, count_matches, count_regions,
+        edit_distance, effective_max_errors, error_histogram, estimate_cost, find_first, find_last,
+        match_starts_for_end, matched_slice, matches_at, max_errors_for_ratio,
+        next_block_may_match, score_profile, search, search_all, search_all_limited,
+        search_bridged, search_bytes, search_case_insensitive, search_chars, search_each,
+        search_fields, search_grouped, search_impl, search_in_range, search_into, search_iter,
+        search_limited, search_or_closest, search_ratio, search_slices, search_summary,
+        search_top_k, search_wildcard, search_with, search_with_eq, search_with_limits,
+        search_with_mask, search_with_word, try_search, try_search_with, Block, BlockWord,
+        ClosestMatches, ColumnState, CompiledPattern, Match, MatchOptions, NonAsciiIndex,
+        PatternBits, SearchError, SearchLimits, SearchOptions,
+    };
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_finds_short_pattern_in_short_text() {
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("wrld");
+
+        let matches = search_impl(&text, &pattern, 1);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].errors, 1);
+    }
+
+    #[test]
+    fn it_finds_match_for_pattern_with_repeated_chars() {
+        let text = utf16_str("some cases");
+        let pattern = utf16_str("some cas");
+
+        let matches = search_impl(&text, &pattern, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start, 0);
+        assert_eq!(matches[0].end, pattern.len());
+        assert_eq!(matches[0].errors, 0);
+    }
+
+    #[test]
+    fn it_finds_match_for_pattern_in_longer_string() {
+        let text = utf16_str("Escaping double-quotes can be cumbersome in some cases such as writing regular expressions or defining a JSON object as a string literal");
+        let pattern = utf16_str("reglar expressions");
+
+        let matches = search_impl(&text, &pattern, 1);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start, 71);
+        assert_eq!(matches[0].end, matches[0].start + pattern.len() + 1);
+        assert_eq!(matches[0].errors, 1);
+    }
+
+    #[test]
+    fn it_finds_no_match_for_long_pattern_in_long_text() {
+        let text = utf16_str("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
+        let pattern =
+            utf16_str("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
+        let matches = search_impl(&text, &pattern, pattern.len() as u32);
+        assert_eq!(matches.len(), 67);
+        assert_eq!(matches[0].errors, 65);
+    }
+
+    #[test]
+    fn it_limits_number_of_matches() {
+        let text = utf16_str(&"a".repeat(67));
+        let pattern = utf16_str(&"b".repeat(65));
+        let max_errors = pattern.len() as u32;
+
+        let all = search_all(&text, &pattern, max_errors);
+        assert_eq!(all.len(), 67);
+
+        let limited = search_all_limited(&text, &pattern, max_errors, 10);
+        assert!(limited.truncated);
+        assert_eq!(limited.matches, all[..10]);
+
+        let limited = search_all_limited(&text, &pattern, max_errors, 67);
+        assert!(!limited.truncated);
+        assert_eq!(limited.matches, all);
+
+        let best = search(&text, &pattern, max_errors);
+        assert_eq!(best.len(), 67);
+        let limited = search_limited(&text, &pattern, max_errors, 2);
+        assert!(limited.truncated);
+        assert_eq!(limited.matches, all[..2]);
+
+        // Worse matches which `search` discards don't count towards the limit.
+        let text = utf16_str("cbt cbt cat");
+        let limited = search_limited(&text, &utf16_str("cat"), 1, 1);
+        assert!(!limited.truncated);
+        assert_eq!(
+            limited.matches,
+            vec![Match {
+                start: 8,
+                end: 11,
+                errors: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn it_returns_matches_in_order_of_position() {
+        let mut random = Random::new(1);
+
+        for _ in 0..500 {
+            let text_len = random.below(40);
+            let text = random.chars(text_len, b"abc");
+            let pattern_len = 1 + random.below(8);
+            let pattern = random.chars(pattern_len, b"abc");
+            let max_errors = random.below(pattern.len() + 1) as u32;
+
+            let matches = search(&text, &pattern, max_errors);
+            assert!(matches
+                .windows(2)
+                .all(|w| (w[0].start, w[0].end) < (w[1].start, w[1].end)));
+        }
+    }
+
+    #[test]
+    fn it_finds_match_for_non_ascii_pattern() {
+        let text = utf16_str("hello world 🙂");
+        let pattern = utf16_str("world 🙂");
+        let matches = search_impl(&text, &pattern, 0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start, text.len() - pattern.len());
+    }
+
+    #[test]
+    fn it_indexes_non_ascii_characters() {
+        let text = utf16_str("Съешь же ещё этих мягких французских булок, да выпей же чаю");
+
+        for (pattern, dense) in [
+            // Cyrillic characters lie in a small range.
+            ("мягкех французскех", true),
+            // These are far apart.
+            ("мягких булок ア", false),
+        ] {
+            let pattern = utf16_str(pattern);
+            let bits = PatternBits::<u16, BlockWord>::new(&pattern, MatchOptions::default());
+            assert_eq!(
+                matches!(bits.nonascii_index, NonAsciiIndex::Dense { .. }),
+                dense
+            );
+
+            // Compare against a search which doesn't use the index.
+            for max_errors in 0..4 {
+                assert_eq!(
+                    search(&text, &pattern, max_errors),
+                    search_with_eq(&text, &pattern, max_errors, |p, t| p == t)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_match_for_long_pattern() {
+        let text = utf16_str("Many years later, as he faced the firing squad, Colonel Aureliano Buendía was to remember that distant afternoon when his father took him to discover ice.");
+        let pattern = text.clone();
+        let matches = search_impl(&text, &pattern, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].errors, 0);
+        assert_eq!(matches[0].start, 0);
+    }
+
+    #[test]
+    fn it_exposes_match_fields() {
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("world");
+
+        let matches = search(&text, &pattern, 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].start(), 6);
+        assert_eq!(matches[0].end(), 11);
+        assert_eq!(matches[0].errors(), 0);
+        assert_eq!(&text[matches[0].range()], &pattern[..]);
+        assert_eq!(matches[0].len(), 5);
+        assert!(!matches[0].is_empty());
+        assert!(Match::from((3, 3, 2)).is_empty());
+    }
+
+    #[test]
+    fn it_constructs_match() {
+        let text = utf16_str("hello world");
+        assert_eq!(search(&text, utf16_str("wrld"), 1), [Match::new(6, 11, 1)]);
+        assert!(Match::new(3, 3, 2).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "match start 4 is after end 3")]
+    fn it_rejects_match_with_start_after_end() {
+        Match::new(4, 3, 0);
+    }
+
+    #[test]
+    fn it_converts_match_to_and_from_tuple() {
+        let text = utf16_str("hello world");
+        let m = search(&text, utf16_str("wrld"), 1).remove(0);
+
+        let tuple: (usize, usize, usize) = m.clone().into();
+        assert_eq!(tuple, (6, 11, 1));
+        assert_eq!(Match::from(tuple), m);
+    }
+
+    #[test]
+    fn it_returns_matched_slice() {
+        let text = utf16_str("hello world");
+        let matches = search(&text, utf16_str("wrld"), 1);
+        assert_eq!(
+            matched_slice(&text, &matches[0]),
+            utf16_str("world").as_slice()
+        );
+    }
+
+    #[test]
+    fn it_displays_match() {
+        let m = Match {
+            start: 2,
+            end: 5,
+            errors: 1,
+        };
+        assert_eq!(m.to_string(), "[2..5] errors=1");
+    }
+
+    #[test]
+    fn it_orders_matches_by_errors_then_position() {
+        let a = Match {
+            start: 5,
+            end: 10,
+            errors: 0,
+        };
+        let b = Match {
+            start: 0,
+            end: 4,
+            errors: 1,
+        };
+        let c = Match {
+            start: 2,
+            end: 6,
+            errors: 1,
+        };
+        let mut matches = vec![c.clone(), b.clone(), a.clone()];
+        matches.sort();
+        assert_eq!(matches, vec![a, b, c]);
+    }
+
+    #[test]
+    fn it_lazily_yields_matches() {
+        let text = utf16_str("wrld hello world");
+        let pattern = utf16_str("world");
+
+        let matches: Vec<Match> = search_iter(&text, &pattern, 1).collect();
+        assert_eq!(matches.len(), 3);
+        assert_eq!(matches[0].errors, 1);
+        assert_eq!(matches.last().unwrap().errors, 0);
+        assert_eq!(matches.last().unwrap().range(), 11..16);
+
+        let first = search_iter(&text, &pattern, 1).next().unwrap();
+        assert_eq!(first.range(), 0..4);
+    }
+
+    #[test]
+    fn it_yields_matched_slices() {
+        let text = utf16_str("the quick brown fox");
+
+        // The pattern only needs to live as long as the call.
+        let slices: Vec<_> = search_slices(&text, &utf16_str("quick"), 1)
+            .map(|(m, slice)| (m.range(), String::from_utf16(slice).unwrap()))
+            .collect();
+        assert_eq!(
+            slices,
+            [(4..8, "quic".to_string()), (4..9, "quick".to_string())]
+        );
+    }
+
+    #[test]
+    fn it_finds_match_in_byte_string() {
+        let text = b"caf\xe9 au lait";
+        let matches = search_bytes(text, b"cafe au", 1);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 0..7);
+        assert_eq!(matches[0].errors, 1);
+
+        let matches = search_bytes(text, b"caf\xe9", 0);
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn it_finds_match_in_char_string() {
+        let text: Vec<char> = "hello 🙂 world 🙃".chars().collect();
+        let pattern: Vec<char> = "🙂 wrld".chars().collect();
+
+        let matches = search_chars(&text, &pattern, 1);
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].range(), matches[0].errors()), (6..13, 1));
+
+        // An emoji is a single error, rather than two as with UTF-16.
+        let matches = search_chars(&text, &['🙃'], 1);
+        assert_eq!((matches[0].range(), matches[0].errors()), (14..15, 0));
+        let matches = search_chars(&text, &['x', '🙃'], 1);
+        assert_eq!((matches[0].range(), matches[0].errors()), (13..15, 1));
+    }
+
+    #[test]
+    fn it_finds_same_matches_with_different_word_sizes() {
+        let text = utf16_str("Many years later, as he faced the firing squad, Colonel Aureliano Buendía was to remember that distant afternoon when his father took him to discover ice.");
+        let pattern =
+            utf16_str("Colonel Aureliano Buendia was to remember that distant afternoon w");
+
+        let expected = search(&text, &pattern, 3);
+        assert_eq!(expected.len(), 1);
+        assert_eq!(search_with_word::<u32>(&text, &pattern, 3), expected);
+        assert_eq!(search_with_word::<u128>(&text, &pattern, 3), expected);
+    }
+
+    #[test]
+    fn it_finds_known_match_for_patterns_at_block_boundaries() {
+        for pattern_len in [63, 64, 65, 127, 128, 129] {
+            // Cycling through an alphabet which excludes the padding and
+            // substituted characters means that the match is unique.
+            let pattern: Vec<u16> = (0..pattern_len)
+                .map(|i| b'a' as u16 + (i * 7 % 26) as u16)
+                .collect();
+            let start = 10;
+            let end = start + pattern_len;
+            let mut text = vec![b'-' as u16; start];
+            text.extend_from_slice(&pattern);
+            text.extend(vec![b'+' as u16; 10]);
+
+            // A substitution of the last character would tie with deleting
+            // it, giving a second match, so it is not tested here.
+            for error_pos in [None, Some(0), Some(pattern_len / 2), Some(pattern_len - 2)] {
+                let mut text = text.clone();
+                if let Some(pos) = error_pos {
+                    text[start + pos] = b'#' as u16;
+                }
+                let errors = error_pos.map_or(0, |_| 1);
+                let context = alloc::format!("len={} error at {:?}", pattern_len, error_pos);
+
+                let expected = vec![Match::new(start, end, errors)];
+                assert_eq!(search(&text, &pattern, 3), expected, "{}", context);
+                assert_eq!(
+                    search_with_word::<u32>(&text, &pattern, 3),
+                    expected,
+                    "{}",
+                    context
+                );
+                assert_eq!(
+                    edit_distance(&text[start..end], &pattern),
+                    errors,
+                    "{}",
+                    context
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_correct_error_count_for_pattern_filling_last_block() {
+        // Generate texts and patterns from a small alphabet so that they are
+        // similar enough for the last block of the pattern to be activated
+        // part way through the text.
+        let mut random = Random::new(1);
+
+        for &pattern_len in &[64, 96, 128] {
+            let pattern = random.chars(pattern_len, b"abcd");
+            let mut text = random.chars(500, b"abcd");
+            text[100..100 + pattern_len].copy_from_slice(&pattern);
+            text[100 + pattern_len / 2] = b'x' as u16;
+
+            for max_errors in [1, 5, 20] {
+                let expected = crate::dp::dp_search(
+                    &text,
+                    &pattern,
+                    max_errors,
+                    crate::dp::DpCosts::default(),
+                    crate::Report::Best,
+                );
+                let actual = search_with_word::<u32>(&text, &pattern, max_errors as u32);
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn it_ignores_ascii_case() {
+        let text = utf16_str("Say HELLO to Åsa");
+        let pattern = utf16_str("hello TO åsa");
+
+        assert!(search(&text, &pattern, 0).is_empty());
+
+        let matches = search_case_insensitive(&text, &pattern, 1);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 4..16);
+        assert_eq!(matches[0].errors, 1);
+    }
+
+    #[test]
+    fn it_matches_masked_positions() {
+        let text = utf16_str("file-2021.txt file_2022.txt");
+        let pattern = utf16_str("file?2022");
+        let mask: Vec<bool> = (0..pattern.len()).map(|i| i == 4).collect();
+
+        let matches = search_with_mask(&text, &pattern, &mask, 0);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 14..23);
+
+        // The character at a masked position is ignored, and other positions
+        // are compared literally, even if they hold the same character.
+        let pattern = utf16_str("?ile?2021");
+        let mask: Vec<bool> = (0..pattern.len()).map(|i| i == 4).collect();
+        assert!(search_with_mask(&text, &pattern, &mask, 0).is_empty());
+        let matches = search_with_mask(&text, &pattern, &mask, 1);
+        assert_eq!(matches[0].range(), 0..9);
+        assert_eq!(matches[0].errors(), 1);
+    }
+
+    #[test]
+    fn it_matches_wildcards() {
+        let text = utf16_str("hello wurld, hello world");
+        let pattern = utf16_str("w?rld");
+        let wildcard = '?' as u16;
+
+        let ranges: Vec<_> = search_wildcard(&text, &pattern, 0, wildcard)
+            .iter()
+            .map(|m| m.range())
+            .collect();
+        assert_eq!(ranges, vec![6..11, 19..24]);
+
+        // Without the wildcard, the `?` is an ordinary character.
+        let matches = search(&text, &pattern, 1);
+        assert!(matches.iter().all(|m| m.errors() == 1));
+
+        // A nonexistent wildcard behaves as an ordinary search.
+        assert!(search_wildcard(&text, &pattern, 0, '*' as u16).is_empty());
+    }
+
+    #[test]
+    fn it_uses_custom_equality_predicate() {
+        let text = utf16_str("Call 555-1234 today");
+        let pattern = utf16_str("000-0000");
+        let is_digit = |c: u16| (b'0' as u16..=b'9' as u16).contains(&c);
+
+        let matches = search_with_eq(&text, &pattern, 0, |p, t| {
+            p == t || (is_digit(p) && is_digit(t))
+        });
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 5..13);
+    }
+
+    #[test]
+    fn it_finds_all_matches_within_threshold() {
+        let text = utf16_str("a cat, a cot and a coat");
+        let pattern = utf16_str("cat");
+
+        assert_eq!(search(&text, &pattern, 1).len(), 1);
+
+        let matches = search_all(&text, &pattern, 1);
+        assert!(matches.iter().any(|m| m.range() == (2..5) && m.errors == 0));
+        assert!(matches
+            .iter()
+            .any(|m| m.range() == (9..12) && m.errors == 1));
+        assert!(matches
+            .iter()
+            .any(|m| m.range() == (19..23) && m.errors == 1));
+        assert!(matches.iter().all(|m| m.errors <= 1));
+    }
+
+    #[test]
+    fn it_calls_back_for_each_match() {
+        let text = utf16_str("a cat, a cot and a coat");
+        let pattern = utf16_str("cat");
+
+        let mut matches = Vec::new();
+        search_each(&text, &pattern, 1, |m| {
+            matches.push(m);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(matches, search_all(&text, &pattern, 1));
+
+        let mut first = None;
+        search_each(&text, &pattern, 1, |m| {
+            first = Some(m);
+            ControlFlow::Break(())
+        });
+        assert_eq!(first.as_ref(), matches.first());
+    }
+
+    #[test]
+    fn it_counts_matches() {
+        let text = utf16_str("a cat, a cot and a coat");
+        let pattern = utf16_str("cat");
+
+        for max_errors in 0..4 {
+            assert_eq!(
+                count_matches(&text, &pattern, max_errors),
+                search_all(&text, &pattern, max_errors).len()
+            );
+        }
+        assert_eq!(count_matches(&text, &[], 1), 0);
+    }
+
+    #[test]
+    fn it_counts_regions() {
+        let text = utf16_str("a cat, a cot and a coat");
+        let pattern = utf16_str("cat");
+
+        assert_eq!(count_regions(&text, &pattern, 0), 1);
+        // Each occurrence matches at several adjacent end positions.
+        assert!(count_matches(&text, &pattern, 1) > 3);
+        assert_eq!(count_regions(&text, &pattern, 1), 3);
+        assert_eq!(count_regions(&text, &pattern, 3), 1);
+        assert_eq!(count_regions(&text, &[], 1), 0);
+    }
+
+    #[test]
+    fn it_finds_all_equally_good_occurrences() {
+        let text = utf16_str("abcXXXabc");
+        let pattern = utf16_str("abc");
+        let matches = search(&text, &pattern, 0);
+        let ranges: Vec<_> = matches.iter().map(|m| m.range()).collect();
+        assert_eq!(ranges, vec![0..3, 6..9]);
+
+        let text = utf16_str("abdXabcXXabcXXXXabc");
+        let matches = search(&text, &pattern, 1);
+        let ranges: Vec<_> = matches.iter().map(|m| m.range()).collect();
+        assert_eq!(ranges, vec![4..7, 9..12, 16..19]);
+    }
+
+    #[test]
+    fn it_finds_top_k_matches() {
+        let text = utf16_str("cot cat cut cart cat");
+        let pattern = utf16_str("cat");
+
+        let matches = search_top_k(&text, &pattern, 1, 3);
+        let ranges: Vec<_> = matches.iter().map(|m| (m.range(), m.errors)).collect();
+        assert_eq!(ranges, vec![(4..7, 0), (17..20, 0), (0..3, 1)]);
+    }
+
+    #[test]
+    fn it_reports_effective_max_errors() {
+        assert_eq!(effective_max_errors(5, 2), 2);
+        assert_eq!(effective_max_errors(5, 5), 5);
+        assert_eq!(effective_max_errors(5, u32::MAX), 5);
+        assert_eq!(effective_max_errors(0, 3), 0);
+
+        // Searching with the effective threshold gives the same results.
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("wrld");
+        let clamped = effective_max_errors(pattern.len(), u32::MAX);
+        assert_eq!(
+            search_all(&text, &pattern, u32::MAX),
+            search_all(&text, &pattern, clamped)
+        );
+    }
+
+    #[test]
+    fn it_checks_whether_a_match_is_possible() {
+        assert!(can_match_within(3, 3, 0));
+        assert!(can_match_within(3, 2, 1));
+        assert!(!can_match_within(3, 2, 0));
+        assert!(can_match_within(3, 1, 2));
+        assert!(!can_match_within(3, 0, 3));
+        assert!(!can_match_within(0, 5, 1));
+
+        // For a text which is a prefix of the pattern, or contains it, there
+        // is a match exactly when the condition holds.
+        let pattern = utf16_str("abc");
+        let text = utf16_str("abcab");
+        for text_len in 0..=text.len() {
+            for max_errors in 0..4 {
+                assert_eq!(
+                    !search(&text[..text_len], &pattern, max_errors).is_empty(),
+                    can_match_within(pattern.len(), text_len, max_errors),
+                    "text_len={} max_errors={}",
+                    text_len,
+                    max_errors
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_finds_best_two_distinct_matches() {
+        let text = utf16_str("the cat sat on a mat, a cut");
+        let (best, second) = best_two(&text, &utf16_str("cat"));
+        assert_eq!(best, best_match(&text, &utf16_str("cat")));
+        let best = best.unwrap();
+        let second = second.unwrap();
+        assert_eq!((best.range(), best.errors()), (4..7, 0));
+        assert_eq!((second.range(), second.errors()), (8..11, 1));
+
+        // Every other candidate overlaps the only good match.
+        let (best, second) = best_two(&utf16_str("cat"), &utf16_str("cat"));
+        assert_eq!(best.map(|m| m.range()), Some(0..3));
+        assert_eq!(second, None);
+
+        assert_eq!(best_two(&text, &[]), (None, None));
+
+        // The second match is the best one lying entirely before or after the
+        // first.
+        let mut random = Random::new(4);
+        for _ in 0..300 {
+            let text_len = random.below(16);
+            let text = random.chars(text_len, b"abc");
+            let pattern_len = 1 + random.below(4);
+            let pattern = random.chars(pattern_len, b"abc");
+
+            let best_within = |range: core::ops::Range<usize>| {
+                let mut candidates = Vec::new();
+                for start in range.clone() {
+                    for end in start..=range.end {
+                        let errors = crate::reference::edit_distance(&text[start..end], &pattern);
+                        candidates.push(Match { start, end, errors });
+                    }
+                }
+                candidates.into_iter().filter(|m| !m.is_empty()).min()
+            };
+
+            let (best, second) = best_two(&text, &pattern);
+            assert_eq!(best, best_match(&text, &pattern));
+            let expected = best.and_then(|best| {
+                let before = best_within(0..best.start);
+                let after = best_within(best.end..text.len());
+                before.into_iter().chain(after).min()
+            });
+            assert_eq!(
+                second,
+                expected,
+                "text={:?} pattern={:?}",
+                String::from_utf16_lossy(&text),
+                String::from_utf16_lossy(&pattern)
+            );
+        }
+    }
+
+    #[test]
+    fn it_converts_error_ratio_to_count() {
+        assert_eq!(max_errors_for_ratio(8, 0.25), 2);
+        assert_eq!(max_errors_for_ratio(7, 0.25), 1);
+        assert_eq!(max_errors_for_ratio(100, 0.29), 29);
+        assert_eq!(max_errors_for_ratio(10, 0.0), 0);
+        assert_eq!(max_errors_for_ratio(10, -1.0), 0);
+        assert_eq!(max_errors_for_ratio(10, f64::NAN), 0);
+        assert_eq!(max_errors_for_ratio(10, 5.0), 10);
+        assert_eq!(max_errors_for_ratio(10, f64::INFINITY), 10);
+
+        let text = utf16_str("the quick brown fox");
+        assert_eq!(
+            search_ratio(&text, &utf16_str("qvick"), 0.2)[0].range(),
+            4..9
+        );
+        assert!(search_ratio(&text, &utf16_str("qvick"), 0.19).is_empty());
+    }
+
+    #[test]
+    fn it_computes_best_similarity() {
+        let text = utf16_str("the quick brown fox");
+
+        assert_eq!(best_similarity(&text, &utf16_str("quick")), 1.0);
+        assert_eq!(best_similarity(&text, &utf16_str("quack")), 0.8);
+        assert_eq!(best_similarity(&text, &utf16_str("zzzz")), 0.0);
+        assert_eq!(best_similarity(&text, &[]), 0.0);
+        assert_eq!(best_similarity(&[], &utf16_str("quick")), 0.0);
+    }
+
+    #[test]
+    fn it_grows_and_shrinks_active_blocks() {
+        assert!(next_block_may_match(3, 0, true, 3));
+        assert!(!next_block_may_match(3, 0, false, 3));
+        assert!(next_block_may_match(2, -1, false, 3));
+        assert!(!next_block_may_match(4, 0, true, 3));
+        assert!(!block_exceeds_threshold::<u64>(66, 3));
+        assert!(block_exceeds_threshold::<u64>(67, 3));
+
+        // A pattern of two blocks, which starts with only the first active.
+        let pattern = utf16_str(&"a".repeat(128));
+        let bits = PatternBits::<u16, BlockWord>::new(&pattern, MatchOptions::default());
+        let mut state = ColumnState::<BlockWord>::new(pattern.len(), 2, false);
+        assert_eq!(state.y, 0);
+
+        // The second block becomes active once the text matches the first
+        // block closely enough that the second may be within the threshold.
+        let mut ys = Vec::new();
+        for _ in 0..70 {
+            state.advance(bits.get('a' as u16), &mut ());
+            ys.push(state.y);
+        }
+        let grown_at = ys.iter().position(|&y| y == 1).unwrap();
+        assert!((60..66).contains(&grown_at));
+
+        // Once the text stops matching, the error count of the second block
+        // rises until it is deactivated again.
+        for _ in 0..80 {
+            state.advance(bits.get('b' as u16), &mut ());
+            ys.push(state.y);
+        }
+        assert_eq!(ys.last(), Some(&0));
+    }
+
+    #[test]
+    fn it_matches_general_path_with_single_block() {
+        let mut random = Random::new(1);
+
+        for case in 0..200 {
+            let pattern_len = 1 + random.below(64);
+            let pattern = random.chars(pattern_len, b"abc");
+            let text_len = random.below(200);
+            let text = random.chars(text_len, b"abc");
+            let max_errors = random.below(pattern.len() + 1);
+            let bits = PatternBits::<u16, BlockWord>::new(&pattern, MatchOptions::default());
+
+            let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors, case % 2 == 0);
+            state.anchored = case % 3 == 0;
+            let mut general = state.clone();
+            for &ch in &text {
+                let match_bits = bits.get(ch);
+                assert_eq!(
+                    state.advance_one_block(match_bits[0], &mut ()),
+                    general.advance_blocks(match_bits, &mut ())
+                );
+            }
+        }
     }
 
     #[test]
-    fn it_finds_short_pattern_in_short_text() {
-        let text = utf16_str("hello world");
-        let pattern = utf16_str("wrld");
+    fn it_extends_compiled_pattern() {
+        let pattern: Vec<u16> = "the quick brown fox jumps over the lazy dog, "
+            .repeat(4)
+            .encode_utf16()
+            .chain("and ἀλώπηξ ".encode_utf16())
+            .collect();
+        let text = utf16_str("a quick brown fox jumped over a lazy dog");
+        let alphabet: Vec<u16> = pattern
+            .iter()
+            .copied()
+            .chain([b'!' as u16, 0x3b1])
+            .collect();
 
-        let matches = search_impl(&text, &pattern, 1);
+        let mut compiled = CompiledPattern::new(&[]);
+        for (i, &ch) in pattern.iter().enumerate() {
+            compiled.push_char(ch);
+            let expected = CompiledPattern::new(&pattern[..=i]);
 
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].errors, 1);
+            assert_eq!(compiled.len(), expected.len());
+            assert_eq!(compiled.block_count(), expected.block_count());
+            assert_eq!(compiled.distinct_chars(), expected.distinct_chars());
+            for &ch in &alphabet {
+                assert_eq!(compiled.bits.get(ch), expected.bits.get(ch));
+                assert_eq!(compiled.rev_bits.get(ch), expected.rev_bits.get(ch));
+            }
+            assert_eq!(compiled.search(&text, 5), expected.search(&text, 5));
+        }
     }
 
     #[test]
-    fn it_finds_match_for_pattern_with_repeated_chars() {
-        let text = utf16_str("some cases");
-        let pattern = utf16_str("some cas");
+    fn it_reports_compiled_pattern_sizes() {
+        let compiled = CompiledPattern::new(&utf16_str("banana"));
+        assert_eq!(compiled.len(), 6);
+        assert_eq!(compiled.distinct_chars(), 3);
+        assert_eq!(compiled.block_count(), 1);
 
-        let matches = search_impl(&text, &pattern, 0);
+        let compiled = CompiledPattern::new(&utf16_str(&"αβγδ".repeat(20)));
+        assert_eq!(compiled.distinct_chars(), 4);
+        assert_eq!(compiled.block_count(), 2);
 
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].start, 0);
-        assert_eq!(matches[0].end, pattern.len());
-        assert_eq!(matches[0].errors, 0);
+        let compiled = CompiledPattern::new(&[]);
+        assert_eq!(compiled.distinct_chars(), 0);
+        assert_eq!(compiled.block_count(), 0);
     }
 
     #[test]
-    fn it_finds_match_for_pattern_in_longer_string() {
-        let text = utf16_str("Escaping double-quotes can be cumbersome in some cases such as writing regular expressions or defining a JSON object as a string literal");
+    fn it_searches_with_compiled_pattern() {
         let pattern = utf16_str("reglar expressions");
+        let compiled = CompiledPattern::new(&pattern);
 
-        let matches = search_impl(&text, &pattern, 1);
+        for text in [
+            "Escaping double-quotes can be cumbersome in some cases such as writing regular expressions",
+            "regular expressions are powerful",
+            "no match here",
+            // Several matches, whose starts are all found with the cached
+            // tables for the reversed pattern.
+            "regular expressions, reglar expresions and regulr expresssions",
+        ] {
+            let text = utf16_str(text);
+            assert_eq!(compiled.search(&text, 2), search(&text, &pattern, 2));
+        }
+    }
+
+    #[test]
+    fn it_searches_with_fixed_max_errors() {
+        let pattern = utf16_str("reglar expressions");
+        let compiled = CompiledPattern::new(&pattern).with_max_errors(2);
+        assert_eq!(compiled.max_errors(), 2);
+        assert_eq!(compiled.pattern().len(), pattern.len());
+
+        for text in [
+            "Escaping double-quotes can be cumbersome in some cases such as writing regular expressions",
+            "regular expressions are powerful",
+            "no match here",
+        ] {
+            let text = utf16_str(text);
+            // Repeated searches start from the same state.
+            for _ in 0..2 {
+                assert_eq!(compiled.search(&text), search(&text, &pattern, 2));
+            }
+        }
+        assert!(CompiledPattern::new(&[])
+            .with_max_errors(1)
+            .search(&utf16_str("abc"))
+            .is_empty());
+    }
+
+    #[test]
+    fn it_finds_match_in_text_shorter_than_pattern() {
+        let pattern = utf16_str("hello");
+        let ranges = |text: &str, max_errors| -> Vec<_> {
+            search(utf16_str(text), &pattern, max_errors)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        assert_eq!(ranges("helo", 0), vec![]);
+        assert_eq!(ranges("helo", 1), vec![(0..4, 1)]);
+        assert_eq!(ranges("helo", 3), vec![(0..4, 1)]);
+        assert_eq!(ranges("hel", 1), vec![]);
+        assert_eq!(ranges("hel", 2), vec![(0..3, 2)]);
+        assert_eq!(ranges("", 4), vec![]);
 
+        // Long patterns span several blocks.
+        let long_pattern = utf16_str(&"abcdefgh".repeat(20));
+        let text = &long_pattern[..150];
+        assert!(search(text, &long_pattern, 9).is_empty());
+        let matches = search(text, &long_pattern, 10);
         assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].start, 71);
-        assert_eq!(matches[0].end, matches[0].start + pattern.len() + 1);
-        assert_eq!(matches[0].errors, 1);
+        assert_eq!((matches[0].range(), matches[0].errors()), (0..150, 10));
+        assert_eq!(
+            search_with_word::<u32>(text, &long_pattern, 12),
+            search(text, &long_pattern, 12)
+        );
     }
 
     #[test]
-    fn it_finds_no_match_for_long_pattern_in_long_text() {
-        let text = utf16_str("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa");
-        let pattern =
-            utf16_str("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb");
-        let matches = search_impl(&text, &pattern, pattern.len() as u32);
-        assert_eq!(matches.len(), 67);
-        assert_eq!(matches[0].errors, 65);
+    fn it_returns_no_matches_for_empty_pattern() {
+        let text = utf16_str("hello world");
+        let matches = search(&text, [], 5);
+        assert!(matches.is_empty());
     }
 
     #[test]
-    fn it_finds_match_for_non_ascii_pattern() {
-        let text = utf16_str("hello world 🙂");
-        let pattern = utf16_str("world 🙂");
-        let matches = search_impl(&text, &pattern, 0);
+    fn it_matches_search_with_default_options() {
+        let mut random = Random::new(2);
+        for max_errors in [0, 1, 3, 40] {
+            for pattern_len in [2, 5, 70] {
+                let pattern = random.chars(pattern_len, b"ab");
+                let mut text = random.chars(2000, b"abc");
+                // Plant several exact copies of the pattern.
+                for pos in [10, 500, 501, 1900] {
+                    text[pos..pos + pattern_len].copy_from_slice(&pattern);
+                }
+
+                assert_eq!(
+                    search(&text, &pattern, max_errors),
+                    search_with(
+                        &text,
+                        &pattern,
+                        &SearchOptions::new().max_errors(max_errors)
+                    ),
+                    "pattern_len={} max_errors={}",
+                    pattern_len,
+                    max_errors
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_searches_in_range() {
+        let text = utf16_str("cat one, cat two, cat three");
+        let pattern = utf16_str("cat");
+
+        let ranges =
+            |matches: Vec<Match>| -> Vec<_> { matches.iter().map(|m| m.range()).collect() };
+        assert_eq!(
+            ranges(search_in_range(&text, &pattern, 0, 5..20)),
+            vec![9..12, 18..21]
+        );
+
+        // A match which extends just outside the range is found in full.
+        assert_eq!(
+            ranges(search_in_range(&text, &pattern, 1, 10..17)),
+            vec![9..12]
+        );
+
+        // Matches entirely outside the range are discarded.
+        assert!(search_in_range(&text, &pattern, 1, 13..17).is_empty());
+
+        // A match which starts in the range is found in full even when it
+        // extends further than `max_errors` beyond it.
+        assert_eq!(
+            ranges(search_in_range(&text, &pattern, 0, 5..10)),
+            vec![9..12]
+        );
+
+        // A better match outside the range does not hide one inside it.
+        let text = utf16_str("cot.cat");
+        assert_eq!(
+            ranges(search_in_range(&text, &pattern, 1, 0..3)),
+            vec![0..3]
+        );
+    }
+
+    #[test]
+    fn it_finds_last_match() {
+        let text = utf16_str("abcd abxd zz");
+        let pattern = utf16_str("abcd");
+
+        assert_eq!(
+            find_last(&text, &pattern, 1),
+            Some(Match {
+                start: 5,
+                end: 9,
+                errors: 1
+            })
+        );
+        assert_eq!(
+            find_last(&text, &pattern, 0),
+            Some(Match {
+                start: 0,
+                end: 4,
+                errors: 0
+            })
+        );
+        assert_eq!(find_last(&text, &utf16_str("wxyz"), 1), None);
+    }
+
+    #[test]
+    fn it_summarizes_search() {
+        let text = utf16_str("a cot, a cat, a cut");
+        let pattern = utf16_str("cat");
+
+        let summary = search_summary(&text, &pattern, 2);
+        assert_eq!(summary.best.as_ref().map(|m| m.range()), Some(9..12));
+        assert!(summary.is_exact());
+        assert_eq!(summary.examined_positions, 12);
+
+        let summary = search_summary(&text, &utf16_str("cbt"), 2);
+        assert_eq!(
+            summary.best.as_ref().map(|m| (m.range(), m.errors())),
+            Some((2..5, 1))
+        );
+        assert!(!summary.is_exact());
+        assert_eq!(summary.examined_positions, text.len());
+
+        let summary = search_summary(&text, &utf16_str("dog"), 1);
+        assert_eq!(summary.best, None);
+        assert_eq!(summary.examined_positions, text.len());
+    }
+
+    #[test]
+    fn it_prefers_exact_matches() {
+        let pattern = utf16_str("cat");
+
+        let matches = search(utf16_str("a cot, a cat, a cut"), &pattern, 1);
         assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].start, text.len() - pattern.len());
+        assert!(matches[0].is_exact());
+
+        let matches = search(utf16_str("a cot, a cut"), &pattern, 1);
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| !m.is_exact()));
     }
 
     #[test]
-    fn it_finds_match_for_long_pattern() {
-        let text = utf16_str("Many years later, as he faced the firing squad, Colonel Aureliano Buendía was to remember that distant afternoon when his father took him to discover ice.");
-        let pattern = text.clone();
-        let matches = search_impl(&text, &pattern, 0);
+    fn it_finds_best_match_with_hint() {
+        let text = utf16_str("the quack brown fox, the quick brwn fox");
+        for pattern in ["quick brown", "slow red", "fox"] {
+            let pattern = utf16_str(pattern);
+            let expected = best_match(&text, &pattern);
+            assert!(expected.is_some());
+            for hint in 0..=pattern.len() as u32 + 1 {
+                assert_eq!(best_match_hinted(&text, &pattern, hint), expected);
+            }
+        }
+        assert_eq!(best_match_hinted(&text, &[], 1), None);
+    }
+
+    #[test]
+    fn it_finds_best_match() {
+        let text = utf16_str("a cot, a cat, a cat");
+        let pattern = utf16_str("cat");
+
+        assert_eq!(
+            best_match(&text, &pattern),
+            Some(Match {
+                start: 9,
+                end: 12,
+                errors: 0
+            })
+        );
+        assert_eq!(
+            best_match(&text, &utf16_str("xyz")),
+            search(&text, utf16_str("xyz"), 3).into_iter().min()
+        );
+        assert_eq!(best_match(&text, &[]), None);
+    }
+
+    #[test]
+    fn it_falls_back_to_closest_match() {
+        let text = utf16_str("a cot, a cat, a cut");
+        let m = |start, end, errors| Match { start, end, errors };
+
+        assert_eq!(
+            search_or_closest(&text, &utf16_str("cat"), 1),
+            ClosestMatches::WithinThreshold(vec![m(9, 12, 0)])
+        );
+        assert_eq!(
+            search_or_closest(&text, &utf16_str("cap"), 0),
+            ClosestMatches::OverThreshold(m(9, 11, 1))
+        );
+        assert_eq!(
+            search_or_closest(&[], &utf16_str("cat"), 1),
+            ClosestMatches::NoMatch
+        );
+        assert_eq!(search_or_closest(&text, &[], 1), ClosestMatches::NoMatch);
+    }
+
+    #[test]
+    fn it_computes_score_profile() {
+        let text = utf16_str("a cat");
+        let pattern = utf16_str("cat");
+
+        assert_eq!(score_profile(&text, &pattern, 1), vec![2, 2, 2, 1, 0]);
+        assert_eq!(score_profile(&text, &pattern, 0), vec![1, 1, 1, 1, 0]);
+        assert_eq!(score_profile(&text, &[], 1), vec![2; 5]);
+
+        // The profile agrees with `search_all` wherever there is a match.
+        let matches = search_all(&text, &pattern, 1);
+        for m in &matches {
+            assert_eq!(score_profile(&text, &pattern, 1)[m.end() - 1], m.errors());
+        }
+    }
+
+    #[test]
+    fn it_never_matches_empty_pattern() {
+        let text = utf16_str("hello world");
+        for max_errors in [0, 1] {
+            assert!(search(&text, [], max_errors).is_empty());
+            assert!(search_all(&text, &[], max_errors).is_empty());
+            assert!(search_iter(&text, &[], max_errors).next().is_none());
+            assert!(CompiledPattern::new(&[])
+                .search(&text, max_errors)
+                .is_empty());
+            assert_eq!(count_matches(&text, &[], max_errors), 0);
+            assert!(!contains(&text, &[], max_errors));
+        }
+        assert!(search([], [], 0).is_empty());
+    }
+
+    #[test]
+    fn it_searches_any_slice_of_code_units() {
+        let text = utf16_str("a cat and a cot");
+        let pattern = utf16_str("cat");
+        let expected = search(&text[..], &pattern[..], 1);
+        assert_eq!(expected.len(), 1);
+
+        assert_eq!(search(&text, &pattern, 1), expected);
+        assert_eq!(search(text.clone(), pattern.clone(), 1), expected);
+        assert_eq!(
+            search(
+                text.clone().into_boxed_slice(),
+                pattern.into_boxed_slice(),
+                1
+            ),
+            expected
+        );
+        assert_eq!(
+            search(&text, [b'c' as u16, b'a' as u16, b't' as u16], 1),
+            expected
+        );
+        assert!(search(&text, [], 1).is_empty());
+    }
+
+    #[test]
+    fn it_converts_between_exclusive_and_inclusive_ends() {
+        let m = Match::new(2, 5, 0);
+        assert_eq!(m.end(), 5);
+        assert_eq!(m.end_inclusive(), 4);
+        assert_eq!(m.len(), m.end_inclusive() + 1 - m.start());
+    }
+
+    #[test]
+    #[should_panic(expected = "empty match has no last character")]
+    fn it_panics_on_inclusive_end_of_empty_match() {
+        Match::new(3, 3, 2).end_inclusive();
+    }
+
+    #[test]
+    fn it_translates_reverse_scan_offsets_to_match_start() {
+        // Exact matches at the start, middle and end of the text, so that the
+        // first and last characters of each match are known.
+        let pattern = utf16_str("abc");
+        for (text, start) in [("abcxx", 0), ("xabcx", 1), ("xxabc", 2), ("abc", 0)] {
+            let text = utf16_str(text);
+            let matches = search(&text, &pattern, 1);
+            assert_eq!(matches, [Match::new(start, start + 3, 0)]);
+            assert_eq!(text[matches[0].start()], b'a' as u16);
+            assert_eq!(text[matches[0].end_inclusive()], b'c' as u16);
+        }
+
+        // With an error, the start is the earliest character which gives the
+        // lowest error count, including the first character of the text.
+        let text = utf16_str("xbc");
+        let end = Match {
+            start: 0,
+            end: 3,
+            errors: 1,
+        };
+        let mut rev_bits =
+            PatternBits::<u16, BlockWord>::new(&crate::reverse(&pattern), MatchOptions::default());
+        let mut scratch = crate::StartScratch::new();
+        for (preference, start) in [
+            (crate::MatchLengthPreference::Longest, 0),
+            (crate::MatchLengthPreference::Shortest, 1),
+        ] {
+            assert_eq!(
+                crate::find_match_start(
+                    &text,
+                    pattern.len(),
+                    &mut rev_bits,
+                    &end,
+                    preference,
+                    &mut scratch
+                ),
+                start
+            );
+        }
+    }
+
+    #[test]
+    fn it_reports_invalid_inputs() {
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("wrld");
+
+        assert_eq!(try_search(&text, &[], 0), Err(SearchError::EmptyPattern));
+        assert_eq!(
+            try_search(&text, &pattern, 5),
+            Err(SearchError::MaxErrorsExceedsPatternLength {
+                max_errors: 5,
+                pattern_len: 4
+            })
+        );
+        assert_eq!(
+            try_search(&text, &pattern, 1),
+            Ok(search(&text, &pattern, 1))
+        );
+    }
+
+    #[test]
+    fn it_optionally_rejects_trivial_threshold() {
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("wrld");
+
+        let options = SearchOptions::new().max_errors(4).report_all(true);
+        assert_eq!(
+            try_search_with(&text, &pattern, &options).map(|m| m.len()),
+            Ok(text.len())
+        );
+        assert_eq!(
+            try_search_with(
+                &text,
+                &pattern,
+                &options.clone().reject_trivial_threshold(true)
+            ),
+            Err(SearchError::TrivialThreshold { pattern_len: 4 })
+        );
+
+        let options = options.max_errors(3).reject_trivial_threshold(true);
+        assert_eq!(
+            try_search_with(&text, &pattern, &options),
+            Ok(search_all(&text, &pattern, 3))
+        );
+    }
+
+    #[test]
+    fn it_stops_search_at_work_limit() {
+        let text: Vec<u16> = "ab".repeat(1000).encode_utf16().collect();
+        let pattern: Vec<u16> = "ba".repeat(100).encode_utf16().collect();
+
+        let unlimited = search_with_limits(&text, &pattern, 150, &SearchLimits::default()).unwrap();
+        assert_eq!(unlimited.matches, search(&text, &pattern, 150));
+
+        let limits = SearchLimits {
+            max_results: Some(2),
+            max_work_units: None,
+        };
+        let limited = search_with_limits(&text, &pattern, 150, &limits).unwrap();
+        assert_eq!(limited.matches, unlimited.matches[..2]);
+        assert!(limited.truncated);
+
+        let limits = SearchLimits {
+            max_results: None,
+            max_work_units: Some(1000),
+        };
+        let err = search_with_limits(&text, &pattern, 150, &limits).unwrap_err();
+        assert!(err.scanned > 0 && err.scanned < text.len());
+        assert_eq!(
+            err.partial.matches,
+            search(&text[..err.scanned], &pattern, 150)
+        );
+    }
+
+    #[test]
+    fn it_groups_matches_by_error_count() {
+        let text = utf16_str("a cat, a cot, a coat");
+        let pattern = utf16_str("cat");
+
+        let groups = search_grouped(&text, &pattern, 1);
+        assert_eq!(groups.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(groups[&0].len(), 1);
+        assert_eq!(groups[&0][0].range(), 2..5);
+        assert!(groups[&1].iter().any(|m| m.range() == (9..12)));
+
+        let total: usize = groups.values().map(Vec::len).sum();
+        assert_eq!(total, search_all(&text, &pattern, 1).len());
+    }
+
+    #[test]
+    fn it_finds_match_at_position() {
+        let text = utf16_str("a cot, a cat");
+        let pattern = utf16_str("cat");
+
+        let m = matches_at(&text, &pattern, 1, 5).unwrap();
+        assert_eq!((m.range(), m.errors()), (2..5, 1));
+        assert_eq!(matches_at(&text, &pattern, 1, 12).unwrap().range(), 9..12);
+        assert_eq!(matches_at(&text, &pattern, 1, 7), None);
+
+        // The result agrees with `search_all` at every position.
+        let all = search_all(&text, &pattern, 2);
+        for end in 0..=text.len() {
+            let expected = all.iter().find(|m| m.end() == end).cloned();
+            assert_eq!(matches_at(&text, &pattern, 2, end), expected);
+        }
+    }
+
+    #[test]
+    fn it_finds_first_match() {
+        let text = utf16_str("a cot, a cat");
+        let pattern = utf16_str("cat");
+
+        let m = find_first(&text, &pattern, 1).unwrap();
+        assert_eq!(m.range(), 2..5);
+        assert_eq!(m.errors, 1);
+
+        assert!(find_first(&text, &pattern, 0).is_some());
+        assert!(find_first(&text, &utf16_str("dog"), 1).is_none());
+    }
+
+    #[test]
+    fn it_checks_for_match() {
+        let text = utf16_str("a cot, a cat");
+        assert!(contains(&text, &utf16_str("cat"), 0));
+        assert!(contains(&text, &utf16_str("cut"), 1));
+        assert!(!contains(&text, &utf16_str("dog"), 1));
+        assert!(!contains(&text, &[], 1));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn it_stops_checking_at_first_match() {
+        let mut text = utf16_str("a cat");
+        text.extend(core::iter::repeat_n(b'x' as u16, 10_000));
+
+        let mut stats = crate::SearchStats::default();
+        assert!(crate::contains_impl(
+            &text,
+            &utf16_str("cat"),
+            0,
+            &mut stats
+        ));
+        assert_eq!(stats.chars_scanned, 5);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn it_stops_scanning_at_first_match() {
+        let text = utf16_str("a cot, a cat and a lot more text");
+        let pattern = utf16_str("cat");
+
+        let mut stats = crate::SearchStats::default();
+        let m = crate::find_first_impl(&text, &pattern, 1, &mut stats).unwrap();
+
+        assert_eq!(m.end, 5);
+        assert_eq!(stats.chars_scanned, 5);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn it_traces_column_scores() {
+        let text = utf16_str("a cot, a cat");
+        let pattern = utf16_str("cat");
+
+        let (matches, trace) = crate::search_traced(&text, &pattern, 1);
+
+        assert_eq!(matches, search(&text, &pattern, 1));
+        assert_eq!(trace, [2, 2, 2, 2, 1, 2, 3, 2, 2, 2, 1, 0]);
+    }
+
+    #[test]
+    fn it_finds_start_when_reverse_scan_is_empty() {
+        let pattern = utf16_str("ab");
+        let mut rev_bits =
+            PatternBits::<u16, BlockWord>::new(&crate::reverse(&pattern), MatchOptions::default());
+        let mut scratch = crate::StartScratch::new();
+
+        // A match ending at the start of the text can only be empty, so there
+        // is nothing for the reverse scan to find.
+        let m = Match {
+            start: 0,
+            end: 0,
+            errors: 2,
+        };
+        for preference in [
+            crate::MatchLengthPreference::Longest,
+            crate::MatchLengthPreference::Shortest,
+        ] {
+            let start = crate::find_match_start(
+                &utf16_str("ab"),
+                pattern.len(),
+                &mut rev_bits,
+                &m,
+                preference,
+                &mut scratch,
+            );
+            assert_eq!(start, 0);
+        }
+    }
+
+    #[test]
+    fn it_searches_across_a_gap() {
+        let text = utf16_str("helo there, wrld. hello world");
+        let prefix = utf16_str("hello ");
+        let suffix = utf16_str("world");
+
+        let ranges = |max_errors, max_gap| -> Vec<(core::ops::Range<usize>, usize)> {
+            search_bridged(&text, &prefix, &suffix, max_errors, max_gap)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        // The second "hello world" has an empty gap.
+        assert_eq!(ranges(0, 0), [(18..29, 0)]);
+
+        // The first needs a gap of "there, " and two errors, one in each part.
+        // As with `search_all`, there are also worse matches ending near the
+        // best one.
+        let near_second = [(18..27, 2), (18..28, 1), (18..29, 0)];
+        assert_eq!(ranges(2, 8), [&[(0..16, 2)], &near_second[..]].concat());
+        assert_eq!(ranges(2, 6), near_second);
+        assert!(search_bridged(&text, &[], &suffix, 2, 8).is_empty());
+    }
+
+    #[test]
+    fn it_searches_into_existing_vec() {
+        let text = utf16_str("a cat, a hat and a bat");
+        let mut out = vec![Match {
+            start: 0,
+            end: 1,
+            errors: 0,
+        }];
+
+        search_into(&text, &utf16_str("cat"), 1, &mut out);
+        assert_eq!(out, search(&text, utf16_str("cat"), 1));
+
+        search_into(&text, &utf16_str("dog"), 0, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn it_searches_each_field() {
+        let title = utf16_str("Rust in Action");
+        let body = utf16_str("a book about rusty and rust-like tools");
+        let tags = utf16_str("programming");
+        let query = utf16_str("rust");
+
+        let matches = search_fields(&query, &[&title, &body, &tags], 1);
+
+        let ranges: Vec<_> = matches
+            .iter()
+            .map(|m| m.as_ref().map(|m| m.range()))
+            .collect();
+        assert_eq!(ranges, [Some(0..4), Some(13..17), None]);
+        assert!(search_fields(&query, &[], 1).is_empty());
+    }
+
+    #[test]
+    fn it_counts_matches_by_error_count() {
+        let text = utf16_str("a cat, a cot and a dog");
+        let pattern = utf16_str("cat");
+
+        let histogram = error_histogram(&text, &pattern, 2);
+
+        let grouped = search_grouped(&text, &pattern, 2);
+        let expected: Vec<usize> = (0..=2)
+            .map(|e| grouped.get(&e).map_or(0, |m| m.len()))
+            .collect();
+        assert_eq!(histogram, expected);
+        assert_eq!(histogram[0], 1);
+        assert_eq!(error_histogram(&text, &pattern, 10).len(), 4);
+        assert!(error_histogram(&text, &[], 2).is_empty());
+    }
+
+    #[test]
+    fn it_finds_all_starts_for_match_end() {
+        // "axbc" (one insertion), "xbc" (one substitution) and "bc" (one
+        // deletion) all end at the same position with one error.
+        let text = utf16_str("axbc");
+        let pattern = utf16_str("abc");
+
+        assert_eq!(match_starts_for_end(&text, &pattern, 4, 1), [0, 1, 2]);
+        assert!(match_starts_for_end(&text, &pattern, 4, 0).is_empty());
+        assert_eq!(match_starts_for_end(&text, &pattern, 0, 3), [0]);
+        assert_eq!(match_starts_for_end(&text, &pattern, 1, 3), [1]);
+    }
+
+    #[test]
+    fn it_matches_naive_recurrence_in_advance_block() {
+        const ROWS: usize = 4;
+
+        // Try every column of `ROWS` rows, described by the difference of each
+        // row from the one above, with every set of matching rows and every
+        // horizontal delta of the row above the block.
+        for deltas in 0..3usize.pow(ROWS as u32) {
+            let v_delta = |i: usize| (deltas / 3usize.pow(i as u32) % 3) as isize - 1;
+            let mut column = [0isize; ROWS + 1];
+            for i in 1..=ROWS {
+                column[i] = column[i - 1] + v_delta(i - 1);
+            }
+
+            for eq in 0..1u32 << ROWS {
+                for h_in in [-1, 0, 1] {
+                    let mut block = Block {
+                        plus_v: (0..ROWS).filter(|&i| v_delta(i) > 0).map(|i| 1 << i).sum(),
+                        minus_v: (0..ROWS).filter(|&i| v_delta(i) < 0).map(|i| 1 << i).sum(),
+                        last_row_mask: 1u32 << (ROWS - 1),
+                        score: column[ROWS],
+                    };
+                    let h_out = advance_block(&mut block, eq, h_in);
+
+                    let mut next = [column[0] + h_in; ROWS + 1];
+                    for i in 1..=ROWS {
+                        let cost = ((eq >> (i - 1)) & 1 == 0) as isize;
+                        next[i] = (column[i - 1] + cost)
+                            .min(column[i] + 1)
+                            .min(next[i - 1] + 1);
+                    }
+
+                    assert_eq!(h_out, next[ROWS] - column[ROWS]);
+                    for i in 0..ROWS {
+                        let delta = next[i + 1] - next[i];
+                        assert_eq!((block.plus_v >> i) & 1 == 1, delta > 0);
+                        assert_eq!((block.minus_v >> i) & 1 == 1, delta < 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_allows_sharing_compiled_patterns() {
+        fn assert_shareable<T: Send + Sync + 'static>(_: &T) {}
+
+        let pattern = CompiledPattern::new(&utf16_str("cat"));
+        assert_shareable(&pattern);
+        assert_shareable(&pattern.with_max_errors(1));
+    }
+
+    #[test]
+    fn it_prefers_longest_match_when_start_is_ambiguous() {
+        // "axbc" (one insertion), "xbc" (one substitution) and "bc" (one
+        // deletion) all end at the same position with one error.
+        let text = utf16_str("axbc");
+        let pattern = utf16_str("abc");
+
+        let matches = search(&text, &pattern, 1);
+
+        assert_eq!(
+            matches,
+            vec![Match {
+                start: 0,
+                end: 4,
+                errors: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn it_finds_start_of_match_with_given_end() {
+        // "bbac" contains "ba", which matches with fewer errors than "bac", but
+        // does not end at the end of the match.
+        let text = utf16_str("acbbbacaccab");
+        let pattern = utf16_str("baa");
+
+        let matches = search(&text, &pattern, 3);
+
+        assert_eq!(
+            matches,
+            vec![
+                Match {
+                    start: 3,
+                    end: 6,
+                    errors: 1
+                },
+                Match {
+                    start: 4,
+                    end: 7,
+                    errors: 1
+                },
+                Match {
+                    start: 4,
+                    end: 8,
+                    errors: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_has_send_and_sync_public_types() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Match>();
+        assert_send_sync::<CompiledPattern>();
+        assert_send_sync::<crate::CompiledSearch>();
+        assert_send_sync::<crate::SearchOptions>();
+        assert_send_sync::<SearchError>();
+        assert_send_sync::<crate::Searcher>();
+        assert_send_sync::<crate::StreamingSearcher>();
+        assert_send_sync::<crate::StrMatch>();
+        assert_send_sync::<crate::AlignedMatch>();
+        assert_send_sync::<crate::EditCosts>();
+    }
+
+    #[test]
+    fn it_estimates_cost_of_search() {
+        // Short patterns need a single block.
+        assert_eq!(estimate_cost(10, 1000, 2), 1000 + 12);
+        assert_eq!(estimate_cost(10, 1000, 0), 1000 + 10);
+
+        // Long patterns only need enough blocks to cover `max_errors`, except
+        // when finding the start of a match.
+        assert_eq!(estimate_cost(200, 10_000, 100), 2 * 10_000 + 300 * 4);
+        assert_eq!(estimate_cost(200, 10_000, 500), 4 * 10_000 + 400 * 4);
+
+        // The start search can't cover more than the whole text.
+        assert_eq!(estimate_cost(200, 100, 10), 100 + 100 * 4);
+
+        assert_eq!(estimate_cost(0, 1000, 2), 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn it_reports_search_stats() {
+        let text = utf16_str("hello world");
+        let pattern = utf16_str("wrld");
+
+        let (matches, stats) = crate::search_stats(&text, &pattern, 1);
 
         assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].errors, 0);
-        assert_eq!(matches[0].start, 0);
+        assert_eq!(stats.chars_scanned, text.len());
+        assert_eq!(stats.block_advances, text.len());
+        assert_eq!(stats.peak_y, 0);
+        assert_eq!(stats.max_active_blocks(), 1);
+        assert_eq!(stats.avg_active_blocks(), 1.0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn it_reports_active_block_counts() {
+        // A 3-block pattern, which only needs all three blocks to be active
+        // near the end of the text.
+        let pattern: Vec<u16> = (0..150).map(|i| b"abcde"[i % 5] as u16).collect();
+        let mut text = vec![b'x' as u16; 100];
+        text.extend_from_slice(&pattern);
+
+        let (matches, stats) = crate::search_stats(&text, &pattern, 2);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(stats.max_active_blocks(), 3);
+        let avg = stats.avg_active_blocks();
+        assert!(avg > 1.0 && avg < 3.0, "avg {}", avg);
+
+        let (_, stats) = crate::search_stats(&[], &pattern, 2);
+        assert_eq!(stats.max_active_blocks(), 0);
+        assert_eq!(stats.avg_active_blocks(), 0.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_serializes_matches() {
+        let m = Match {
+            start: 2,
+            end: 5,
+            errors: 1,
+        };
+        let json = serde_json::to_string(&m).unwrap();
+        assert_eq!(json, r#"{"start":2,"end":5,"errors":1}"#);
+
+        let parsed: Match = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, m);
     }
 }
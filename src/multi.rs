@@ -0,0 +1,215 @@
+// This module implements searching for several patterns in a single pass over
+// the text.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{
+    find_match_starts, max_errors_for_ratio, reverse, search_all, select_non_overlapping,
+    BlockWord, ColumnState, Match, MatchOptions, PatternBits,
+};
+
+/// State of the scan for one of the patterns passed to `search_multi`.
+struct PatternScan {
+    bits: PatternBits<u16, BlockWord>,
+    state: ColumnState<BlockWord>,
+
+    // Best matches found so far, with `start` set to zero.
+    ends: Vec<Match>,
+}
+
+/// Search for approximate matches of each of `patterns` in `text` allowing up
+/// to `max_errors` errors.
+///
+/// This is equivalent to calling `search` for each pattern, but the text is
+/// only traversed once. Each result is a pair of the index of the pattern in
+/// `patterns` and the match. Results are ordered by position in the text, then
+/// by pattern index. Empty patterns never match.
+pub fn search_multi(text: &[u16], patterns: &[&[u16]], max_errors: u32) -> Vec<(usize, Match)> {
+    search_multi_impl(text, patterns, &vec![max_errors; patterns.len()])
+}
+
+/// Search for approximate matches of each of `patterns` in `text`, allowing a
+/// number of errors proportional to the length of each pattern.
+///
+/// `max_error_ratios[i]` is the error ratio for `patterns[i]`, which is
+/// converted to a number of errors as `search_ratio` does. Otherwise this
+/// behaves like `search_multi`.
+///
+/// # Panics
+///
+/// Panics if `patterns` and `max_error_ratios` have different lengths.
+pub fn search_multi_ratio(
+    text: &[u16],
+    patterns: &[&[u16]],
+    max_error_ratios: &[f64],
+) -> Vec<(usize, Match)> {
+    assert_eq!(
+        patterns.len(),
+        max_error_ratios.len(),
+        "each pattern must have an error ratio"
+    );
+    let max_errors: Vec<u32> = patterns
+        .iter()
+        .zip(max_error_ratios)
+        .map(|(pattern, &ratio)| max_errors_for_ratio(pattern.len(), ratio))
+        .collect();
+    search_multi_impl(text, patterns, &max_errors)
+}
+
+/// Search for approximate matches of a term with several spellings, given by
+/// `alternatives`, allowing up to `max_errors` errors.
+///
+/// Unlike `search_multi`, the alternatives are treated as one term, so where
+/// matches of different alternatives overlap, only one is reported. Matches of
+/// every alternative are found as with `search_all`, and then chosen as
+/// `select_non_overlapping` does, preferring the match with the lowest error
+/// count, then the earliest start and then the earliest end. Where several
+/// alternatives give the same match, the first of them is reported. Each
+/// result is a pair of the index of the alternative in `alternatives` and the
+/// match, in order of position in the text.
+pub fn search_alternatives(
+    text: &[u16],
+    alternatives: &[&[u16]],
+    max_errors: u32,
+) -> Vec<(usize, Match)> {
+    // The first alternative which produced each match. Alternatives are
+    // searched in order, so `or_insert` keeps the lowest index.
+    let mut sources: BTreeMap<Match, usize> = BTreeMap::new();
+    for (i, alternative) in alternatives.iter().enumerate() {
+        for m in search_all(text, alternative, max_errors) {
+            sources.entry(m).or_insert(i);
+        }
+    }
+
+    let candidates = sources.keys().cloned().collect();
+    select_non_overlapping(candidates)
+        .into_iter()
+        .map(|m| (sources[&m], m))
+        .collect()
+}
+
+/// Search for each of `patterns` in `text`, allowing up to `max_errors[i]`
+/// errors for `patterns[i]`.
+fn search_multi_impl(text: &[u16], patterns: &[&[u16]], max_errors: &[u32]) -> Vec<(usize, Match)> {
+    let opts = MatchOptions::default();
+    let mut scans: Vec<Option<PatternScan>> = patterns
+        .iter()
+        .zip(max_errors)
+        .map(|(pattern, &max_errors)| {
+            if pattern.is_empty() {
+                return None;
+            }
+            Some(PatternScan {
+                bits: PatternBits::new(pattern, opts),
+                state: ColumnState::new(pattern.len(), max_errors as usize, true),
+                ends: Vec::new(),
+            })
+        })
+        .collect();
+
+    for (j, &ch) in text.iter().enumerate() {
+        for scan in scans.iter_mut().flatten() {
+            if let Some(errors) = scan.state.advance(scan.bits.get(ch), &mut ()) {
+                if scan.ends.last().is_some_and(|m| errors < m.errors) {
+                    // Discard any earlier, worse matches.
+                    scan.ends.clear();
+                }
+                scan.ends.push(Match {
+                    start: 0,
+                    end: j + 1,
+                    errors,
+                });
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+    for (i, scan) in scans.into_iter().enumerate() {
+        let Some(scan) = scan else {
+            continue;
+        };
+        let pattern = patterns[i];
+        let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+        let matches = find_match_starts(text, pattern.len(), &mut rev_bits, scan.ends);
+        results.extend(matches.into_iter().map(|m| (i, m)));
+    }
+    results.sort_by_key(|(i, m)| (m.start, m.end, *i));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{search_alternatives, search_multi, search_multi_ratio};
+    use crate::{search, search_ratio};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_matches_each_pattern_like_search() {
+        let text = utf16_str("the quick brown fox jumps over the lazy dog");
+        let patterns: Vec<Vec<u16>> = ["quack", "fox", "lazy dgo", "", "cat"]
+            .iter()
+            .map(|p| utf16_str(p))
+            .collect();
+        let pattern_refs: Vec<&[u16]> = patterns.iter().map(|p| p.as_slice()).collect();
+
+        let results = search_multi(&text, &pattern_refs, 2);
+
+        for (i, pattern) in patterns.iter().enumerate() {
+            let expected = search(&text, pattern, 2);
+            let actual: Vec<_> = results
+                .iter()
+                .filter(|(idx, _)| *idx == i)
+                .map(|(_, m)| m.clone())
+                .collect();
+            assert_eq!(actual, expected);
+        }
+        assert!(results.windows(2).all(|w| w[0].1.start() <= w[1].1.start()));
+    }
+
+    #[test]
+    fn it_uses_error_ratio_for_each_pattern() {
+        let text = utf16_str("the quick brown fox jumps over the lazy dog");
+        let patterns: Vec<Vec<u16>> = ["quack", "fxo", "lazy dgo"]
+            .iter()
+            .map(|p| utf16_str(p))
+            .collect();
+        let pattern_refs: Vec<&[u16]> = patterns.iter().map(|p| p.as_slice()).collect();
+        let ratios = [0.2, 0.5, 0.25];
+
+        let results = search_multi_ratio(&text, &pattern_refs, &ratios);
+
+        for (i, pattern) in patterns.iter().enumerate() {
+            let expected = search_ratio(&text, pattern, ratios[i]);
+            assert!(!expected.is_empty());
+            let actual: Vec<_> = results
+                .iter()
+                .filter(|(idx, _)| *idx == i)
+                .map(|(_, m)| m.clone())
+                .collect();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn it_searches_for_alternative_spellings() {
+        let text = utf16_str("the colour of the colr scheme, in color");
+        let alternatives = [utf16_str("color"), utf16_str("colour")];
+        let alternatives: Vec<&[u16]> = alternatives.iter().map(|a| a.as_slice()).collect();
+
+        let results: Vec<_> = search_alternatives(&text, &alternatives, 1)
+            .iter()
+            .map(|(i, m)| (*i, m.range(), m.errors()))
+            .collect();
+
+        // "colour" also matches "color" with one error, but the exact match
+        // of the second alternative is preferred.
+        assert_eq!(results, [(1, 4..10, 0), (0, 18..22, 1), (0, 34..39, 0)]);
+    }
+}
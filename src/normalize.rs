@@ -0,0 +1,168 @@
+// This module implements Unicode normalization of texts before matching, so that
+// composed and decomposed forms of the same characters match each other, and
+// removal of accents, so that accented letters match their base letters.
+
+use alloc::vec::Vec;
+use unicode_normalization::char::{canonical_combining_class, is_combining_mark};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::offsets::OffsetMap;
+
+/// Return true if normalization never changes text across a boundary before
+/// `ch`.
+///
+/// This holds for characters which are starters (canonical combining class 0)
+/// and never combine with a previous character when composing, since
+/// reordering of combining marks and composition both stop at such
+/// characters.
+fn is_safe_boundary(ch: char) -> bool {
+    canonical_combining_class(ch) == 0 && is_nfc_quick(core::iter::once(ch)) == IsNormalized::Yes
+}
+
+/// Text which is being normalized, along with tables mapping offsets in it
+/// back to the original text.
+struct Normalized {
+    chars: Vec<u16>,
+
+    // Offset in the original text of the start of the segment which each code
+    // unit was produced from, and of the end of the segment of the code unit
+    // before each offset.
+    floor: Vec<usize>,
+    ceil: Vec<usize>,
+}
+
+impl Normalized {
+    fn push(&mut self, unit: u16, start: usize, end: usize) {
+        self.chars.push(unit);
+        self.floor.push(start);
+        self.ceil.push(end);
+    }
+
+    /// Normalize a segment of the original text which spans `start..end`.
+    fn push_segment(&mut self, segment: &mut Vec<char>, start: usize, end: usize) {
+        let mut buf = [0; 2];
+        for ch in segment.drain(..).nfc() {
+            for unit in ch.encode_utf16(&mut buf) {
+                self.push(*unit, start, end);
+            }
+        }
+    }
+}
+
+/// Convert `text` to Normalization Form C, and map offsets in the normalized
+/// text to offsets in `text`.
+///
+/// The text is split into segments at safe boundaries, and each segment is
+/// normalized separately. Every code unit produced from a segment maps to the
+/// whole of that segment in `text`, so a match never covers only part of a
+/// sequence of characters which changed when normalized.
+pub(crate) fn nfc(text: &[u16]) -> (Vec<u16>, OffsetMap) {
+    let mut normalized = Normalized {
+        chars: Vec::with_capacity(text.len()),
+        floor: Vec::with_capacity(text.len() + 1),
+        ceil: Vec::with_capacity(text.len() + 1),
+    };
+    normalized.ceil.push(0);
+
+    let mut segment: Vec<char> = Vec::new();
+    let mut segment_start = 0;
+    let mut pos = 0;
+
+    for ch in char::decode_utf16(text.iter().cloned()) {
+        match ch {
+            Ok(ch) => {
+                if is_safe_boundary(ch) {
+                    normalized.push_segment(&mut segment, segment_start, pos);
+                    segment_start = pos;
+                }
+                segment.push(ch);
+                pos += ch.len_utf16();
+            }
+            Err(err) => {
+                // Lone surrogates are passed through unchanged, as a segment
+                // of their own.
+                normalized.push_segment(&mut segment, segment_start, pos);
+                normalized.push(err.unpaired_surrogate(), pos, pos + 1);
+                pos += 1;
+                segment_start = pos;
+            }
+        }
+    }
+    normalized.push_segment(&mut segment, segment_start, pos);
+    normalized.floor.push(text.len());
+
+    (
+        normalized.chars,
+        OffsetMap::new(normalized.floor, normalized.ceil),
+    )
+}
+
+/// Return the base letter of `ch`, with any accents removed.
+///
+/// This is the first character of the canonical decomposition of `ch`, if the
+/// rest of the decomposition consists of combining marks. Other characters,
+/// such as Hangul syllables, which decompose into several letters, are
+/// unchanged.
+fn base_letter(ch: char) -> char {
+    let mut decomposed = core::iter::once(ch).nfd();
+    match decomposed.next() {
+        Some(base) if decomposed.all(is_combining_mark) => base,
+        _ => ch,
+    }
+}
+
+/// Replace each character of `text` with its base letter and remove combining
+/// marks, and map offsets in the resulting text to offsets in `text`.
+///
+/// A removed combining mark is treated as part of the character before it, so
+/// a match never ends between a letter and its accents.
+pub(crate) fn strip_accents(text: &[u16]) -> (Vec<u16>, OffsetMap) {
+    let mut stripped = Vec::with_capacity(text.len());
+    let mut starts = Vec::with_capacity(text.len() + 1);
+
+    // End offset of the character which each code unit was produced from.
+    let mut ends = Vec::with_capacity(text.len());
+
+    // Index in `stripped` of the first code unit of the last character.
+    let mut last_char = None;
+
+    let mut pos = 0;
+    for ch in char::decode_utf16(text.iter().cloned()) {
+        let start = pos;
+        let (units, len) = match ch {
+            Ok(ch) => {
+                pos += ch.len_utf16();
+                if is_combining_mark(ch) {
+                    if let Some(first_unit) = last_char {
+                        for end in &mut ends[first_unit..] {
+                            *end = pos;
+                        }
+                        continue;
+                    }
+                }
+                let mut buf = [0; 2];
+                let len = base_letter(ch).encode_utf16(&mut buf).len();
+                (buf, len)
+            }
+            Err(err) => {
+                // Lone surrogates are passed through unchanged.
+                pos += 1;
+                ([err.unpaired_surrogate(), 0], 1)
+            }
+        };
+
+        last_char = Some(stripped.len());
+        for &unit in &units[..len] {
+            stripped.push(unit);
+            starts.push(start);
+            ends.push(pos);
+        }
+    }
+
+    starts.push(text.len());
+    let mut ceil = Vec::with_capacity(ends.len() + 1);
+    ceil.push(0);
+    ceil.extend(ends);
+
+    (stripped, OffsetMap::new(starts, ceil))
+}
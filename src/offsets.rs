@@ -0,0 +1,386 @@
+// This module defines functions which convert match offsets between the UTF-16
+// code units used by the search functions and other ways of indexing text.
+
+use crate::{Match, Utf16Error};
+use alloc::vec::Vec;
+
+/// Tables mapping UTF-16 code unit offsets in a text to offsets in another
+/// indexing scheme.
+///
+/// Matching operates on UTF-16 code units, so a match can start or end between
+/// the two halves of a surrogate pair. Such offsets are widened to the enclosing
+/// character: a start is rounded down to the start of the character and an end is
+/// rounded up to the end of the character.
+pub(crate) struct OffsetMap {
+    floor: Vec<usize>,
+    ceil: Vec<usize>,
+}
+
+impl OffsetMap {
+    /// Create a map from tables of the offsets to use for starts and ends of
+    /// matches, each with one more entry than the text.
+    #[cfg(feature = "unicode-normalization")]
+    pub(crate) fn new(floor: Vec<usize>, ceil: Vec<usize>) -> OffsetMap {
+        debug_assert_eq!(floor.len(), ceil.len());
+        OffsetMap { floor, ceil }
+    }
+
+    /// Map UTF-16 offsets in `text` to code point (`char`) indices.
+    ///
+    /// Unpaired surrogates count as one code point each, as they do when
+    /// decoded with `char::decode_utf16`.
+    fn chars(text: &[u16]) -> OffsetMap {
+        let mut floor = Vec::with_capacity(text.len() + 1);
+        let mut ceil = Vec::with_capacity(text.len() + 1);
+
+        let mut char_idx = 0;
+        let mut i = 0;
+        while i < text.len() {
+            floor.push(char_idx);
+            ceil.push(char_idx);
+            if is_surrogate_pair(text, i) {
+                floor.push(char_idx);
+                ceil.push(char_idx + 1);
+                i += 2;
+            } else {
+                i += 1;
+            }
+            char_idx += 1;
+        }
+        floor.push(char_idx);
+        ceil.push(char_idx);
+
+        OffsetMap { floor, ceil }
+    }
+
+    /// Map UTF-16 offsets in `text` to themselves, except for offsets which
+    /// split a surrogate pair.
+    fn code_units(text: &[u16]) -> OffsetMap {
+        let mut floor = Vec::with_capacity(text.len() + 1);
+        let mut ceil = Vec::with_capacity(text.len() + 1);
+
+        let mut i = 0;
+        while i < text.len() {
+            floor.push(i);
+            ceil.push(i);
+            if is_surrogate_pair(text, i) {
+                floor.push(i);
+                ceil.push(i + 2);
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        floor.push(text.len());
+        ceil.push(text.len());
+
+        OffsetMap { floor, ceil }
+    }
+
+    /// Map UTF-16 offsets in the UTF-16 encoding of `s` to byte offsets in `s`.
+    ///
+    /// The resulting byte offsets always lie on `char` boundaries.
+    pub(crate) fn utf8(s: &str) -> OffsetMap {
+        let mut floor = Vec::with_capacity(s.len() + 1);
+        let mut ceil = Vec::with_capacity(s.len() + 1);
+
+        for (byte_idx, ch) in s.char_indices() {
+            floor.push(byte_idx);
+            ceil.push(byte_idx);
+            if ch.len_utf16() == 2 {
+                floor.push(byte_idx);
+                ceil.push(byte_idx + ch.len_utf8());
+            }
+        }
+        floor.push(s.len());
+        ceil.push(s.len());
+
+        OffsetMap { floor, ceil }
+    }
+
+    /// Collapse each run of whitespace in `text` to a single space, and map
+    /// offsets in the collapsed text to offsets in `text`.
+    ///
+    /// The space which replaces a run maps to the whole of the run, so a match
+    /// which includes it covers all of the run's whitespace in `text`.
+    pub(crate) fn collapse_whitespace(text: &[u16]) -> (Vec<u16>, OffsetMap) {
+        let is_space = |ch: u16| char::from_u32(ch as u32).is_some_and(char::is_whitespace);
+
+        let mut collapsed = Vec::with_capacity(text.len());
+        let mut offsets = Vec::with_capacity(text.len() + 1);
+
+        let mut i = 0;
+        while i < text.len() {
+            offsets.push(i);
+            if is_space(text[i]) {
+                collapsed.push(b' ' as u16);
+                while i < text.len() && is_space(text[i]) {
+                    i += 1;
+                }
+            } else {
+                collapsed.push(text[i]);
+                i += 1;
+            }
+        }
+        offsets.push(text.len());
+
+        // Each collapsed character ends where the next one starts, so starts
+        // and ends map the same way.
+        let map = OffsetMap {
+            floor: offsets.clone(),
+            ceil: offsets,
+        };
+        (collapsed, map)
+    }
+
+    /// Remove the code units in `ignore` from `text`, and map offsets in the
+    /// resulting text to offsets in `text`.
+    ///
+    /// Removed code units between two others are included in a match which
+    /// spans them, but removed code units before the start or after the end of
+    /// a match are not.
+    pub(crate) fn remove_chars(text: &[u16], ignore: &[u16]) -> (Vec<u16>, OffsetMap) {
+        let mut kept = Vec::with_capacity(text.len());
+        let mut floor = Vec::with_capacity(text.len() + 1);
+        let mut ceil = Vec::with_capacity(text.len() + 1);
+        ceil.push(0);
+
+        for (i, &ch) in text.iter().enumerate() {
+            if ignore.contains(&ch) {
+                continue;
+            }
+            kept.push(ch);
+            floor.push(i);
+            ceil.push(i + 1);
+        }
+        floor.push(text.len());
+
+        (kept, OffsetMap { floor, ceil })
+    }
+
+    /// Map a UTF-16 offset which is the start of a match.
+    pub(crate) fn start(&self, offset: usize) -> usize {
+        self.floor[offset]
+    }
+
+    /// Map a UTF-16 offset which is the end of a match.
+    pub(crate) fn end(&self, offset: usize) -> usize {
+        self.ceil[offset]
+    }
+
+    /// Map the offsets of `matches`, merging adjacent matches which map to
+    /// the same range.
+    pub(crate) fn apply(&self, matches: &[Match]) -> Vec<Match> {
+        let mut mapped = matches
+            .iter()
+            .map(|m| Match {
+                start: self.start(m.start),
+                end: self.end(m.end),
+                errors: m.errors,
+            })
+            .collect();
+        dedup_ranges(&mut mapped);
+        mapped
+    }
+}
+
+/// Merge adjacent matches in `matches` which have the same range, keeping the
+/// lowest error count.
+///
+/// Mapping offsets preserves their order, so when the matches are sorted by
+/// position, as the ones returned by a search are, this removes every
+/// duplicate.
+pub(crate) fn dedup_ranges(matches: &mut Vec<Match>) {
+    matches.dedup_by(|m, prev| {
+        let same = m.range() == prev.range();
+        if same {
+            prev.errors = prev.errors.min(m.errors);
+        }
+        same
+    });
+}
+
+/// Replace each surrogate in `text` which is not part of a surrogate pair with
+/// U+FFFD REPLACEMENT CHARACTER.
+pub(crate) fn replace_lone_surrogates(text: &[u16]) -> Vec<u16> {
+    let mut replaced = text.to_vec();
+    let mut i = 0;
+    while i < text.len() {
+        if is_surrogate_pair(text, i) {
+            i += 2;
+            continue;
+        }
+        if (0xd800..0xe000).contains(&text[i]) {
+            replaced[i] = 0xfffd;
+        }
+        i += 1;
+    }
+    replaced
+}
+
+/// Check that `units` is well-formed UTF-16, ie. that every surrogate is part
+/// of a surrogate pair.
+///
+/// The search functions do not require valid UTF-16. By default they compare
+/// code units (see `SurrogatePolicy::Raw`), so a lone surrogate is just a code
+/// unit which matches itself. This is for callers who want to reject or
+/// sanitize such input before searching, since the text of a match which
+/// includes a lone surrogate cannot be converted to a `String` without loss.
+pub fn validate_utf16(units: &[u16]) -> Result<(), Utf16Error> {
+    let mut i = 0;
+    while i < units.len() {
+        if is_surrogate_pair(units, i) {
+            i += 2;
+            continue;
+        }
+        if (0xd800..0xe000).contains(&units[i]) {
+            return Err(Utf16Error { index: i });
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// Return true if `text[i]` and `text[i + 1]` form a surrogate pair.
+fn is_surrogate_pair(text: &[u16], i: usize) -> bool {
+    let is_high = |ch: u16| (0xd800..0xdc00).contains(&ch);
+    let is_low = |ch: u16| (0xdc00..0xe000).contains(&ch);
+    is_high(text[i]) && text.get(i + 1).copied().is_some_and(is_low)
+}
+
+/// Convert the offsets of matches in `text` from UTF-16 code units to Unicode
+/// code point (`char`) indices.
+///
+/// The two offset spaces differ whenever the text contains characters outside
+/// the Basic Multilingual Plane, such as most emoji, which take two UTF-16 code
+/// units but are a single `char`. The converted offsets can be used with
+/// `str::chars`. Matches which start or end in the middle of a surrogate pair
+/// are widened to include the whole character, and adjacent matches which end
+/// up with the same range are merged, keeping the lowest error count.
+pub fn to_char_offsets(text: &[u16], matches: &[Match]) -> Vec<Match> {
+    OffsetMap::chars(text).apply(matches)
+}
+
+/// Widen matches in `text` which start or end in the middle of a surrogate pair
+/// so that they include the whole character.
+///
+/// Offsets remain in UTF-16 code units, but every boundary lies between two
+/// code points, so the matched text can always be decoded or converted to a
+/// `&str` slice. Matches which do not split a surrogate pair are unchanged.
+/// Adjacent matches which are widened to the same range are merged, keeping
+/// the lowest error count.
+pub fn snap_to_char_boundaries(text: &[u16], matches: &[Match]) -> Vec<Match> {
+    OffsetMap::code_units(text).apply(matches)
+}
+
+/// Convert the offsets of matches from UTF-16 code units in the encoding of
+/// `text_utf8` to byte offsets in `text_utf8`.
+///
+/// This is for when the searched text was produced with `str::encode_utf16`, so
+/// that `&text_utf8[m.range()]` gives the matched text. Matches which start or
+/// end in the middle of a surrogate pair are widened to include the whole
+/// character, and adjacent matches which end up with the same range are
+/// merged, keeping the lowest error count.
+///
+/// # Panics
+///
+/// Panics if a match has an offset beyond the end of the UTF-16 encoding of
+/// `text_utf8`.
+pub fn utf16_to_utf8_offsets(text_utf8: &str, matches: &[Match]) -> Vec<Match> {
+    OffsetMap::utf8(text_utf8).apply(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use alloc::vec::Vec;
+
+    use super::{
+        is_surrogate_pair, snap_to_char_boundaries, to_char_offsets, utf16_to_utf8_offsets,
+        validate_utf16,
+    };
+    use crate::search;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_converts_offsets_to_chars() {
+        let text_str = "🙂 smile 🙂 please";
+        let text = utf16_str(text_str);
+        let matches = search(&text, utf16_str("please"), 0);
+        assert_eq!(matches[0].range(), 12..18);
+
+        let char_matches = to_char_offsets(&text, &matches);
+        assert_eq!(char_matches[0].range(), 10..16);
+
+        let chars: Vec<char> = text_str.chars().collect();
+        let matched: String = chars[char_matches[0].range()].iter().collect();
+        assert_eq!(matched, "please");
+    }
+
+    #[test]
+    fn it_snaps_matches_to_char_boundaries() {
+        let text = utf16_str("smile 🙂 please");
+        let splits_pair = |offset: usize| offset > 0 && is_surrogate_pair(&text, offset - 1);
+
+        // The pattern can match the high surrogate of the emoji as a
+        // substitution, which leaves the end in the middle of the pair.
+        let matches = search(&text, utf16_str("le x"), 1);
+        assert!(matches.iter().any(|m| splits_pair(m.end())));
+
+        let snapped = snap_to_char_boundaries(&text, &matches);
+        assert_eq!(snapped.len(), matches.len());
+        for m in &snapped {
+            assert!(!splits_pair(m.start()));
+            assert!(!splits_pair(m.end()));
+        }
+        assert!(snapped
+            .iter()
+            .any(|m| String::from_utf16(&text[m.range()]).unwrap() == "le 🙂"));
+    }
+
+    #[test]
+    fn it_converts_offsets_to_utf8() {
+        let text = "n🙂ño 🙂";
+        let text_utf16 = utf16_str(text);
+        let cases = [("n🙂", "n🙂"), ("ño", "ño"), (" 🙂", " 🙂"), ("🙂ñ", "🙂ñ")];
+
+        for (pattern, expected) in cases {
+            let matches = search(&text_utf16, utf16_str(pattern), 0);
+            let converted = utf16_to_utf8_offsets(text, &matches);
+            assert_eq!(converted.len(), 1);
+            assert_eq!(&text[converted[0].range()], expected);
+        }
+
+        // Empty matches at the start and end of the text.
+        let empty = [
+            crate::Match {
+                start: 0,
+                end: 0,
+                errors: 0,
+            },
+            crate::Match {
+                start: text_utf16.len(),
+                end: text_utf16.len(),
+                errors: 0,
+            },
+        ];
+        let converted = utf16_to_utf8_offsets(text, &empty);
+        assert_eq!(converted[0].range(), 0..0);
+        assert_eq!(converted[1].range(), text.len()..text.len());
+    }
+
+    #[test]
+    fn it_validates_utf16() {
+        assert_eq!(validate_utf16(&utf16_str("ab😀c")), Ok(()));
+        assert_eq!(validate_utf16(&[]), Ok(()));
+
+        // A low surrogate before a high one, and a high surrogate at the end.
+        let err = validate_utf16(&[0x61, 0xde00, 0xd83d]).unwrap_err();
+        assert_eq!(err.index(), 1);
+        let err = validate_utf16(&[0xd83d, 0xde00, 0xd83d]).unwrap_err();
+        assert_eq!(err.index(), 2);
+    }
+}
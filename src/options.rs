@@ -0,0 +1,1067 @@
+// This module defines `SearchOptions` and the `search_with` function, which
+// allow the various search features to be combined.
+
+use crate::dp::{dp_search, DpCosts};
+use crate::exact::find_exact;
+use crate::offsets::{replace_lone_surrogates, OffsetMap};
+use crate::{
+    find_match_starts_preferring, reverse, scan_ends_bit_parallel, snap_to_char_boundaries,
+    BlockWord, Match, MatchOptions, PatternBits, Report, SearchError,
+};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Which start position to report when several give a match with the same end
+/// position and error count.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchLengthPreference {
+    /// Report the earliest start, giving the longest match.
+    #[default]
+    Longest,
+
+    /// Report the latest start, giving the shortest match.
+    Shortest,
+}
+
+/// How `search_with` treats UTF-16 surrogates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurrogatePolicy {
+    /// Treat each code unit independently, as `search` does. A match may start
+    /// or end between the two halves of a surrogate pair, and an unpaired
+    /// surrogate only matches the same code unit.
+    #[default]
+    Raw,
+
+    /// Treat each surrogate pair as a single unit for the purpose of match
+    /// boundaries, and each unpaired surrogate as U+FFFD REPLACEMENT
+    /// CHARACTER.
+    ScalarAware,
+}
+
+/// Options which control the behavior of `search_with`.
+///
+/// The defaults, which are used by `SearchOptions::new`, match the behavior of
+/// `search` with `max_errors` set to zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchOptions {
+    max_errors: u32,
+    case_insensitive: bool,
+    report_all: bool,
+    transpositions: bool,
+    max_results: Option<usize>,
+    compute_starts: bool,
+    length_preference: MatchLengthPreference,
+    min_match_len: usize,
+    max_span: Option<usize>,
+    min_gap: usize,
+    collapse_whitespace: bool,
+    word_boundary: bool,
+    similarity_tiebreak: bool,
+    ignore_chars: Vec<u16>,
+    boundaries: Vec<u16>,
+    surrogate_policy: SurrogatePolicy,
+    reject_trivial_threshold: bool,
+    #[cfg(feature = "unicode-normalization")]
+    normalize: bool,
+    #[cfg(feature = "unicode-normalization")]
+    accent_insensitive: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> SearchOptions {
+        SearchOptions {
+            max_errors: 0,
+            case_insensitive: false,
+            report_all: false,
+            transpositions: false,
+            max_results: None,
+            compute_starts: true,
+            length_preference: MatchLengthPreference::Longest,
+            min_match_len: 0,
+            max_span: None,
+            min_gap: 0,
+            collapse_whitespace: false,
+            word_boundary: false,
+            similarity_tiebreak: false,
+            ignore_chars: Vec::new(),
+            boundaries: Vec::new(),
+            surrogate_policy: SurrogatePolicy::Raw,
+            reject_trivial_threshold: false,
+            #[cfg(feature = "unicode-normalization")]
+            normalize: false,
+            #[cfg(feature = "unicode-normalization")]
+            accent_insensitive: false,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Create a set of options with default values.
+    pub fn new() -> SearchOptions {
+        SearchOptions::default()
+    }
+
+    /// Set the maximum number of errors allowed in a match. Defaults to 0.
+    ///
+    /// This is clamped to the length of the pattern.
+    pub fn max_errors(mut self, max_errors: u32) -> SearchOptions {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Treat ASCII letters which differ only in case as equal, as with
+    /// `search_case_insensitive`. Defaults to false.
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> SearchOptions {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    /// Report every match within the error threshold, as with `search_all`,
+    /// rather than only the best matches. Defaults to false.
+    pub fn report_all(mut self, report_all: bool) -> SearchOptions {
+        self.report_all = report_all;
+        self
+    }
+
+    /// Lower the error threshold to the lowest error count found so far as the
+    /// text is scanned. Defaults to true.
+    ///
+    /// This is what makes a search report only the best matches: once a match
+    /// with `n` errors has been found, matches with more than `n` errors are no
+    /// longer reported, and the scan can skip more of the work for each text
+    /// character. When disabled, `max_errors` stays fixed for the whole scan,
+    /// so every match within it is reported. This is the same option as
+    /// `report_all`, with the opposite sense: `ratchet(false)` is equivalent to
+    /// `report_all(true)`.
+    pub fn ratchet(self, ratchet: bool) -> SearchOptions {
+        self.report_all(!ratchet)
+    }
+
+    /// Count swapping two adjacent characters as a single error, as with
+    /// `search_damerau`. Defaults to false.
+    ///
+    /// This uses a much slower algorithm than the default.
+    pub fn transpositions(mut self, transpositions: bool) -> SearchOptions {
+        self.transpositions = transpositions;
+        self
+    }
+
+    /// Limit the number of matches returned. When there are more matches, the
+    /// ones which occur earliest in the text are returned. Defaults to `None`
+    /// (no limit).
+    pub fn max_results(mut self, max_results: Option<usize>) -> SearchOptions {
+        self.max_results = max_results;
+        self
+    }
+
+    /// Compute where each match starts. Defaults to true.
+    ///
+    /// Finding the start of a match requires a second, reverse search over the
+    /// text before the end of the match. If only the end positions and error
+    /// counts are needed, disabling this skips that work. The `start` of the
+    /// returned matches is then unspecified.
+    pub fn compute_starts(mut self, compute_starts: bool) -> SearchOptions {
+        self.compute_starts = compute_starts;
+        self
+    }
+
+    /// Choose between start positions which give matches with the same end
+    /// position and error count. Defaults to `MatchLengthPreference::Longest`.
+    ///
+    /// For example, when searching for "abc" in "axbc" with one error, the match
+    /// could be "axbc", "xbc" or "bc". `Longest` reports "axbc" and `Shortest`
+    /// reports "bc". `Shortest` is useful when highlighting matches, to avoid
+    /// selecting more of the surrounding text than needed.
+    ///
+    /// This is ignored when `transpositions` is enabled.
+    pub fn length_preference(mut self, preference: MatchLengthPreference) -> SearchOptions {
+        self.length_preference = preference;
+        self
+    }
+
+    /// Discard matches which are shorter than `min_match_len` code units.
+    /// Defaults to 0 (no minimum).
+    ///
+    /// This is applied after the best matches have been selected, so when
+    /// `report_all` is disabled, a search whose best matches are all too short
+    /// returns no matches rather than longer matches with more errors. The
+    /// length of a match depends on where it starts, so if this is set, start
+    /// positions are computed even when `compute_starts` is disabled.
+    pub fn min_match_len(mut self, min_match_len: usize) -> SearchOptions {
+        self.min_match_len = min_match_len;
+        self
+    }
+
+    /// Discard matches which are longer than `max_span` code units. Defaults
+    /// to `None` (no maximum).
+    ///
+    /// With a high `max_errors`, a short pattern can match a long stretch of
+    /// text with many insertions. This discards such matches. Like
+    /// `min_match_len`, this is applied after the best matches have been
+    /// selected and forces start positions to be computed.
+    pub fn max_span(mut self, max_span: Option<usize>) -> SearchOptions {
+        self.max_span = max_span;
+        self
+    }
+
+    /// Drop matches which are within `min_gap` code units of a better match.
+    /// Defaults to 0 (no suppression).
+    ///
+    /// A single occurrence of the pattern often produces several overlapping
+    /// or nearby matches. When this is set, matches are considered in "best
+    /// first" order (see `Match`), like non-maximum suppression, and each is
+    /// kept only if it does not overlap a match that was already kept, and is
+    /// separated from it by more than `min_gap` code units. This keeps the
+    /// match with the fewest errors from each cluster, or the earliest if
+    /// several tie. Unlike `merge_matches`, which combines nearby matches, this
+    /// drops all but one of them.
+    ///
+    /// Suppression uses start positions, so setting this forces them to be
+    /// computed. It is applied after the `min_match_len`, `max_span` and
+    /// `word_boundary` filters, so that a match rejected by them never
+    /// suppresses one which they accept.
+    pub fn min_gap(mut self, min_gap: usize) -> SearchOptions {
+        self.min_gap = min_gap;
+        self
+    }
+
+    /// Treat each run of whitespace in the text and pattern as a single space.
+    /// Defaults to false.
+    ///
+    /// This allows phrases to match text which has been reflowed, eg. "hello
+    /// world" matches "hello \n  world" with no errors. Offsets of matches still
+    /// refer to the original text. A match which includes part of a run of
+    /// whitespace includes the whole run. Lengths used by other options, such
+    /// as `min_match_len`, are measured in the collapsed text.
+    pub fn collapse_whitespace(mut self, collapse_whitespace: bool) -> SearchOptions {
+        self.collapse_whitespace = collapse_whitespace;
+        self
+    }
+
+    /// Only report matches which start and end at word boundaries. Defaults to
+    /// false.
+    ///
+    /// A match is discarded if the characters on both sides of its start, or
+    /// on both sides of its end, are word characters, so that eg. "cat" does
+    /// not match inside "concatenate". Word characters are those which are
+    /// alphanumeric. The start and end of the text count as boundaries. As with
+    /// `min_match_len`, this is applied after the best matches have been
+    /// selected, and forces start positions to be computed.
+    pub fn word_boundary(mut self, word_boundary: bool) -> SearchOptions {
+        self.word_boundary = word_boundary;
+        self
+    }
+
+    /// Order matches with the same error count by how similar they are to the
+    /// pattern. Defaults to false.
+    ///
+    /// Similarity is measured by the length of the longest common subsequence
+    /// of the pattern and the matched text, so that eg. of two matches with
+    /// two errors for "abcdef", "abdcef" ranks above "abxyef". The matches with the
+    /// lowest error count are returned first, ordered by decreasing similarity
+    /// and then by position, followed by any other matches in their usual order.
+    /// The similarity is only computed for the matches with the lowest error
+    /// count, which takes time proportional to the pattern length times the
+    /// match length for each of them. This forces start positions to be
+    /// computed, and is applied before `max_results`.
+    pub fn similarity_tiebreak(mut self, similarity_tiebreak: bool) -> SearchOptions {
+        self.similarity_tiebreak = similarity_tiebreak;
+        self
+    }
+
+    /// Skip the given code units in the text and pattern, as if they were not
+    /// there. Defaults to an empty set.
+    ///
+    /// This is useful for characters which don't affect how text reads, such
+    /// as soft hyphens (U+00AD) or zero-width joiners (U+200D), so that eg.
+    /// "hyphen\u{ad}ation" matches "hyphenation" with no errors. Offsets of
+    /// matches refer to the original text. Skipped characters inside a match
+    /// are part of it, but a match does not start or end with them.
+    pub fn ignore_chars(mut self, chars: &[u16]) -> SearchOptions {
+        self.ignore_chars = chars.to_vec();
+        self
+    }
+
+    /// Prevent matches from including any of the given code units. Defaults to
+    /// an empty set.
+    ///
+    /// This is for searching many records packed into one text, separated by
+    /// a delimiter such as `\0`, without matches spanning two records. The text
+    /// is split at each boundary character and each part is searched
+    /// separately, so the scan starts afresh after each boundary rather than
+    /// matches which cross one being discarded afterwards. When `report_all`
+    /// is disabled, only the matches with the lowest error count across all
+    /// parts are returned. Other options which discard matches, such as
+    /// `min_match_len`, are applied within each part.
+    pub fn boundaries(mut self, chars: &[u16]) -> SearchOptions {
+        self.boundaries = chars.to_vec();
+        self
+    }
+
+    /// Choose how surrogates in the text and pattern are treated. Defaults to
+    /// `SurrogatePolicy::Raw`.
+    ///
+    /// With `SurrogatePolicy::ScalarAware`, matches which start or end in the
+    /// middle of a surrogate pair are widened to include the whole character,
+    /// as with `snap_to_char_boundaries`, and matches which become identical
+    /// are merged. Error counts are those of the match before it was widened.
+    /// Unpaired surrogates in the text and pattern are all replaced with
+    /// U+FFFD before matching, so any unpaired surrogate matches any other, and
+    /// matches U+FFFD itself.
+    pub fn surrogate_policy(mut self, policy: SurrogatePolicy) -> SearchOptions {
+        self.surrogate_policy = policy;
+        self
+    }
+
+    /// Make `try_search_with` report a `max_errors` equal to the pattern length
+    /// as `SearchError::TrivialThreshold`. Defaults to false.
+    ///
+    /// With such a threshold every position in the text matches, since the
+    /// whole pattern can be deleted, which is rarely intended and usually means
+    /// that the threshold was miscomputed. A threshold above the pattern length
+    /// is always reported, as `SearchError::MaxErrorsExceedsPatternLength`.
+    /// This has no effect on `search_with`, which never fails.
+    pub fn reject_trivial_threshold(mut self, reject: bool) -> SearchOptions {
+        self.reject_trivial_threshold = reject;
+        self
+    }
+
+    /// Convert the text and pattern to Unicode Normalization Form C before
+    /// matching. Defaults to false.
+    ///
+    /// This makes composed and decomposed forms of the same characters match,
+    /// eg. "é" as one code point matches "e" followed by a combining acute
+    /// accent with no errors. Error counts are measured in the normalized text.
+    /// Offsets of matches refer to the original text, and a match never covers
+    /// only part of a sequence of characters which changed when normalized.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize(mut self, normalize: bool) -> SearchOptions {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Treat accented letters as equal to their base letters, eg. "résumé"
+    /// matches "resume" with no errors. Defaults to false.
+    ///
+    /// Each character of the text and pattern is replaced with the first
+    /// character of its canonical decomposition, and combining marks are
+    /// removed. Offsets of matches refer to the original text, and a match
+    /// never ends between a letter and a combining mark which follows it. This
+    /// can be combined with `case_insensitive` to ignore both case and accents.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn accent_insensitive(mut self, accent_insensitive: bool) -> SearchOptions {
+        self.accent_insensitive = accent_insensitive;
+        self
+    }
+}
+
+/// Search for approximate matches of `pattern` in `text` using the given
+/// options.
+///
+/// `text` and `pattern` are sequences of UTF-16 code units. An empty pattern
+/// never matches. Matches are returned in ascending order of `start`, then of
+/// `end`, unless `SearchOptions::similarity_tiebreak` is set. There is at most
+/// one match for each end position, so this order is unique.
+pub fn search_with(text: &[u16], pattern: &[u16], options: &SearchOptions) -> Vec<Match> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+
+    if !options.boundaries.is_empty() {
+        let inner = options
+            .clone()
+            .boundaries(&[])
+            .max_results(None)
+            .similarity_tiebreak(false);
+        let mut matches = Vec::new();
+        let mut offset = 0;
+        for part in text.split(|ch| options.boundaries.contains(ch)) {
+            matches.extend(
+                search_with(part, pattern, &inner)
+                    .into_iter()
+                    .map(|m| Match {
+                        start: m.start + offset,
+                        end: m.end + offset,
+                        errors: m.errors,
+                    }),
+            );
+            offset += part.len() + 1;
+        }
+
+        if !options.report_all {
+            if let Some(min_errors) = matches.iter().map(|m| m.errors).min() {
+                matches.retain(|m| m.errors == min_errors);
+            }
+        }
+        if options.similarity_tiebreak {
+            order_ties_by_similarity(text, pattern, options.case_insensitive, &mut matches);
+        }
+        if let Some(max_results) = options.max_results {
+            matches.truncate(max_results);
+        }
+        return matches;
+    }
+
+    if options.surrogate_policy == SurrogatePolicy::ScalarAware {
+        let text = replace_lone_surrogates(text);
+        let pattern = replace_lone_surrogates(pattern);
+        let options = options.clone().surrogate_policy(SurrogatePolicy::Raw);
+        return snap_to_char_boundaries(&text, &search_with(&text, &pattern, &options));
+    }
+
+    if !options.ignore_chars.is_empty() {
+        let (text, offsets) = OffsetMap::remove_chars(text, &options.ignore_chars);
+        let (pattern, _) = OffsetMap::remove_chars(pattern, &options.ignore_chars);
+        let options = options.clone().ignore_chars(&[]);
+        let matches = search_with(&text, &pattern, &options);
+        return offsets.apply(&matches);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    if options.normalize {
+        let (text, offsets) = crate::normalize::nfc(text);
+        let (pattern, _) = crate::normalize::nfc(pattern);
+        let options = options.clone().normalize(false);
+        let matches = search_with(&text, &pattern, &options);
+        return offsets.apply(&matches);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    if options.accent_insensitive {
+        let (text, offsets) = crate::normalize::strip_accents(text);
+        let (pattern, _) = crate::normalize::strip_accents(pattern);
+        let options = options.clone().accent_insensitive(false);
+        let matches = search_with(&text, &pattern, &options);
+        return offsets.apply(&matches);
+    }
+
+    if options.collapse_whitespace {
+        let (text, offsets) = OffsetMap::collapse_whitespace(text);
+        let (pattern, _) = OffsetMap::collapse_whitespace(pattern);
+        let options = options.clone().collapse_whitespace(false);
+        let matches = search_with(&text, &pattern, &options);
+        return offsets.apply(&matches);
+    }
+
+    let report = if options.report_all {
+        Report::All
+    } else {
+        Report::Best
+    };
+
+    let mut matches = if options.transpositions {
+        let fold = |chars: &[u16]| -> Vec<u16> {
+            chars
+                .iter()
+                .map(|&ch| {
+                    if options.case_insensitive && ch < 128 {
+                        (ch as u8).to_ascii_lowercase() as u16
+                    } else {
+                        ch
+                    }
+                })
+                .collect()
+        };
+        let costs = DpCosts {
+            transpose: Some(1),
+            ..DpCosts::default()
+        };
+        let max_errors = (options.max_errors as usize).min(pattern.len());
+        dp_search(&fold(text), &fold(pattern), max_errors, costs, report)
+    } else {
+        let opts = MatchOptions {
+            ignore_ascii_case: options.case_insensitive,
+            ..MatchOptions::default()
+        };
+        search_bit_parallel(text, pattern, opts, report, options)
+    };
+
+    // The scan usually produces matches in this order already, in which case
+    // sorting is cheap.
+    matches.sort_by_key(|m| (m.start, m.end));
+
+    if options.min_match_len > 0 {
+        matches.retain(|m| m.end - m.start >= options.min_match_len);
+    }
+
+    if let Some(max_span) = options.max_span {
+        matches.retain(|m| m.end - m.start <= max_span);
+    }
+
+    if options.word_boundary {
+        matches.retain(|m| is_word_boundary(text, m.start) && is_word_boundary(text, m.end));
+    }
+
+    suppress_nearby_matches(&mut matches, options.min_gap);
+
+    if options.similarity_tiebreak {
+        order_ties_by_similarity(text, pattern, options.case_insensitive, &mut matches);
+    }
+
+    if let Some(max_results) = options.max_results {
+        matches.truncate(max_results);
+    }
+
+    matches
+}
+
+/// Move the matches with the lowest error count to the front of `matches`,
+/// ordered by decreasing length of the longest common subsequence of the
+/// pattern and the matched text.
+fn order_ties_by_similarity(
+    text: &[u16],
+    pattern: &[u16],
+    case_insensitive: bool,
+    matches: &mut [Match],
+) {
+    let Some(min_errors) = matches.iter().map(|m| m.errors).min() else {
+        return;
+    };
+
+    // Stable partition, keeping the relative order of the other matches.
+    let mut best: Vec<(usize, Match)> = Vec::new();
+    let mut rest: Vec<Match> = Vec::new();
+    for m in matches.iter() {
+        if m.errors == min_errors {
+            let similarity =
+                common_subsequence_len(&text[m.start..m.end], pattern, case_insensitive);
+            best.push((similarity, m.clone()));
+        } else {
+            rest.push(m.clone());
+        }
+    }
+    best.sort_by(|(sim_a, a), (sim_b, b)| sim_b.cmp(sim_a).then(a.cmp(b)));
+
+    let ordered = best.into_iter().map(|(_, m)| m).chain(rest);
+    for (slot, m) in matches.iter_mut().zip(ordered) {
+        *slot = m;
+    }
+}
+
+/// Return the length of the longest common subsequence of `a` and `b`.
+fn common_subsequence_len(a: &[u16], b: &[u16], case_insensitive: bool) -> usize {
+    let fold = |ch: u16| {
+        if case_insensitive && ch < 128 {
+            (ch as u8).to_ascii_lowercase() as u16
+        } else {
+            ch
+        }
+    };
+
+    // `row[j]` is the length of the longest common subsequence of the prefix
+    // of `a` processed so far and `b[..j]`.
+    let mut row = alloc::vec![0; b.len() + 1];
+    for &ch_a in a {
+        let mut diagonal = 0;
+        for (j, &ch_b) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if fold(ch_a) == fold(ch_b) {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Return true if `offset` in `text` is not between two word characters.
+///
+/// Code units which are not characters on their own, ie. surrogates, are not
+/// word characters.
+fn is_word_boundary(text: &[u16], offset: usize) -> bool {
+    let is_word_char = |i: usize| {
+        text.get(i)
+            .and_then(|&ch| char::from_u32(ch as u32))
+            .is_some_and(char::is_alphanumeric)
+    };
+    offset == 0 || !is_word_char(offset - 1) || !is_word_char(offset)
+}
+
+/// Keep the best matches from `matches` which are separated from each other by
+/// more than `min_gap` code units, in order of position. See
+/// `SearchOptions::min_gap`.
+fn suppress_nearby_matches(matches: &mut Vec<Match>, min_gap: usize) {
+    if min_gap == 0 {
+        return;
+    }
+    matches.sort();
+
+    // Kept matches, keyed by start offset. Since they are separated from each
+    // other, only the closest kept match on either side of a candidate can be
+    // too close to it.
+    let mut kept: BTreeMap<usize, Match> = BTreeMap::new();
+    for m in matches.drain(..) {
+        let near_before = kept
+            .range(..=m.start)
+            .next_back()
+            .is_some_and(|(_, prev)| m.start <= prev.end + min_gap);
+        let near_after = kept
+            .range(m.start + 1..)
+            .next()
+            .is_some_and(|(&start, _)| start <= m.end + min_gap);
+        if !near_before && !near_after {
+            kept.insert(m.start, m);
+        }
+    }
+    matches.extend(kept.into_values());
+}
+
+/// Run the bit-vector search, using the SIMD scan for long patterns with high
+/// error thresholds if the `simd` feature is enabled.
+fn search_bit_parallel(
+    text: &[u16],
+    pattern: &[u16],
+    opts: MatchOptions,
+    report: Report,
+    options: &SearchOptions,
+) -> Vec<Match> {
+    let needs_starts = options.compute_starts
+        || options.min_match_len > 0
+        || options.max_span.is_some()
+        || options.word_boundary
+        || options.similarity_tiebreak
+        || options.min_gap > 0;
+
+    if options.max_errors == 0 {
+        // Without errors this is an exact substring search, which is much
+        // cheaper than the bit-vector scan.
+        let mut matches = find_exact(text, pattern, opts.ignore_ascii_case);
+        if !needs_starts {
+            for m in matches.iter_mut() {
+                m.start = m.end;
+            }
+        }
+        return matches;
+    }
+
+    let mut bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let max_errors = options.max_errors as usize;
+
+    let mut matches = Vec::new();
+    scan_ends_bit_parallel(
+        text,
+        pattern.len(),
+        &mut bits,
+        max_errors,
+        report,
+        &mut matches,
+    );
+
+    if !needs_starts {
+        for m in matches.iter_mut() {
+            m.start = m.end;
+        }
+        return matches;
+    }
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    find_match_starts_preferring(
+        text,
+        pattern.len(),
+        &mut rev_bits,
+        matches,
+        options.length_preference,
+    )
+}
+
+/// Variant of `search_with` which reports invalid inputs instead of ignoring
+/// them. See `try_search`.
+pub fn try_search_with(
+    text: &[u16],
+    pattern: &[u16],
+    options: &SearchOptions,
+) -> Result<Vec<Match>, SearchError> {
+    SearchError::check(pattern, options.max_errors)?;
+    if options.reject_trivial_threshold && options.max_errors as usize == pattern.len() {
+        return Err(SearchError::TrivialThreshold {
+            pattern_len: pattern.len(),
+        });
+    }
+    Ok(search_with(text, pattern, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{search_with, MatchLengthPreference, SearchOptions, SurrogatePolicy};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_combines_options() {
+        let text = utf16_str("Teh cat, THE CAT, the cta");
+        let pattern = utf16_str("the cat");
+
+        let options = SearchOptions::new()
+            .max_errors(1)
+            .case_insensitive(true)
+            .transpositions(true)
+            .report_all(true);
+        let matches = search_with(&text, &pattern, &options);
+        assert!(matches.iter().any(|m| m.range() == (0..7)));
+        assert!(matches
+            .iter()
+            .any(|m| m.range() == (9..16) && m.errors() == 0));
+        assert!(matches.iter().any(|m| m.range() == (18..25)));
+
+        let matches = search_with(&text, &pattern, &options.clone().max_results(Some(1)));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 0..7);
+    }
+
+    #[test]
+    fn it_skips_computing_starts() {
+        let text = utf16_str("a cot, a cat");
+        let pattern = utf16_str("cat");
+
+        let options = SearchOptions::new().max_errors(1).report_all(true);
+        let with_starts = search_with(&text, &pattern, &options);
+        let without_starts = search_with(&text, &pattern, &options.compute_starts(false));
+
+        assert_eq!(with_starts.len(), without_starts.len());
+        for (a, b) in with_starts.iter().zip(without_starts.iter()) {
+            assert_eq!(a.end(), b.end());
+            assert_eq!(a.errors(), b.errors());
+        }
+    }
+
+    #[test]
+    fn it_prefers_shortest_or_longest_match() {
+        let text = utf16_str("axbc");
+        let pattern = utf16_str("abc");
+        let options = SearchOptions::new().max_errors(1);
+
+        let longest = search_with(&text, &pattern, &options);
+        let shortest = search_with(
+            &text,
+            &pattern,
+            &options.length_preference(MatchLengthPreference::Shortest),
+        );
+
+        assert_eq!(longest.len(), 1);
+        assert_eq!(longest[0].range(), 0..4);
+        assert_eq!(shortest.len(), 1);
+        assert_eq!(shortest[0].range(), 2..4);
+        assert_eq!(shortest[0].errors(), longest[0].errors());
+    }
+
+    #[test]
+    fn it_discards_short_matches() {
+        let text = utf16_str("ca, cart");
+        let pattern = utf16_str("cart");
+
+        let options = SearchOptions::new().max_errors(2).report_all(true);
+        let matches = search_with(&text, &pattern, &options);
+        assert!(matches.iter().any(|m| m.range() == (0..2)));
+
+        for compute_starts in [true, false] {
+            let options = options
+                .clone()
+                .min_match_len(3)
+                .compute_starts(compute_starts);
+            let matches = search_with(&text, &pattern, &options);
+            assert!(!matches.is_empty());
+            assert!(matches.iter().all(|m| m.end() - m.start() >= 3));
+        }
+    }
+
+    #[test]
+    fn it_discards_long_matches() {
+        let text = utf16_str("ab....ef");
+        let pattern = utf16_str("abcdef");
+
+        // With enough errors, the pattern matches more than its own length of
+        // text, by inserting characters.
+        let options = SearchOptions::new().report_all(true);
+        let matches = search_with(&text, &pattern, &options.clone().max_errors(4));
+        assert!(matches.iter().any(|m| m.range() == (0..8)));
+
+        for compute_starts in [true, false] {
+            let options = options
+                .clone()
+                .max_errors(4)
+                .max_span(Some(6))
+                .compute_starts(compute_starts);
+            let matches = search_with(&text, &pattern, &options);
+            assert!(!matches.is_empty());
+            assert!(matches.iter().all(|m| m.len() <= 6));
+        }
+    }
+
+    #[test]
+    fn it_collapses_whitespace() {
+        let text = utf16_str("say  hello \n\t world\n\nagain");
+        let options = SearchOptions::new().collapse_whitespace(true);
+
+        for pattern in ["hello world", "hello   world", "hello\nworld"] {
+            let matches = search_with(&text, &utf16_str(pattern), &options);
+            assert_eq!(matches.len(), 1);
+            assert_eq!(matches[0].range(), 5..19);
+            assert_eq!(matches[0].errors(), 0);
+        }
+
+        // A match which includes collapsed whitespace covers the whole run.
+        let matches = search_with(&text, &utf16_str("world again"), &options);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 14..26);
+
+        let matches = search_with(&text, &utf16_str("hello world"), &SearchOptions::new());
+        assert!(matches.is_empty());
+    }
+
+    #[test]
+    fn it_disables_ratcheting() {
+        let text = utf16_str("a cot, a cat");
+        let pattern = utf16_str("cat");
+
+        let ratcheted = search_with(&text, &pattern, &SearchOptions::new().max_errors(1));
+        assert!(ratcheted.iter().all(|m| m.errors() == 0));
+
+        let options = SearchOptions::new().max_errors(1).ratchet(false);
+        let matches = search_with(&text, &pattern, &options);
+        assert!(matches
+            .iter()
+            .any(|m| m.range() == (2..5) && m.errors() == 1));
+        let all = SearchOptions::new().max_errors(1).report_all(true);
+        assert_eq!(matches, search_with(&text, &pattern, &all));
+    }
+
+    #[test]
+    fn it_only_matches_at_word_boundaries() {
+        let options = SearchOptions::new().word_boundary(true);
+        let ranges = |text: &str, pattern: &str| -> Vec<_> {
+            search_with(&utf16_str(text), &utf16_str(pattern), &options)
+                .iter()
+                .map(|m| m.range())
+                .collect()
+        };
+
+        assert!(ranges("concatenate", "cat").is_empty());
+        assert!(ranges("cats", "cat").is_empty());
+        assert_eq!(ranges("cat", "cat"), vec![0..3]);
+        assert_eq!(ranges("a cat", "cat"), vec![2..5]);
+        assert_eq!(ranges("(cat), cat.", "cat"), vec![1..4, 7..10]);
+        assert_eq!(ranges("bobcat, cat", "cat"), vec![8..11]);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn it_reports_matches_in_expanded_characters_once() {
+        // U+0958 is excluded from composition, so it normalizes to two
+        // characters, and matches of either map back to the same range.
+        let options = SearchOptions::new().normalize(true).max_errors(1);
+        let matches = search_with(&utf16_str("a\u{958}b"), &utf16_str("x"), &options);
+        let ranges: Vec<_> = matches.iter().map(|m| m.range()).collect();
+        assert_eq!(ranges, vec![0..2, 2..3]);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn it_normalizes_text_and_pattern() {
+        let composed = "caf\u{e9} au lait";
+        let decomposed = "cafe\u{301} au lait";
+        let options = SearchOptions::new().normalize(true);
+
+        let matches = search_with(&utf16_str(decomposed), &utf16_str("caf\u{e9}"), &options);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 0..5);
+        assert_eq!(matches[0].errors(), 0);
+
+        let matches = search_with(&utf16_str(composed), &utf16_str("cafe\u{301}"), &options);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 0..4);
+        assert_eq!(matches[0].errors(), 0);
+
+        // Offsets after the normalized characters refer to the original text.
+        let matches = search_with(&utf16_str(decomposed), &utf16_str("lait"), &options);
+        assert_eq!(matches[0].range(), 9..13);
+
+        let matches = search_with(
+            &utf16_str(decomposed),
+            &utf16_str("caf\u{e9}"),
+            &SearchOptions::new(),
+        );
+        assert!(matches.is_empty());
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn it_ignores_accents() {
+        let options = SearchOptions::new().accent_insensitive(true);
+        let ranges = |text: &str, pattern: &str, options: &SearchOptions| -> Vec<_> {
+            search_with(&utf16_str(text), &utf16_str(pattern), options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        assert_eq!(ranges("un café noir", "cafe", &options), vec![(3..7, 0)]);
+        assert_eq!(ranges("un cafe noir", "café", &options), vec![(3..7, 0)]);
+
+        // A decomposed accent is included in the match.
+        assert_eq!(
+            ranges("cafe\u{301} noir", "cafe", &options),
+            vec![(0..5, 0)]
+        );
+
+        let options = options.case_insensitive(true);
+        assert_eq!(ranges("UN CAFÉ NOIR", "cafe", &options), vec![(3..7, 0)]);
+    }
+
+    #[test]
+    fn it_orders_ties_by_similarity() {
+        let text = utf16_str("abxyef abdcef");
+        let pattern = utf16_str("abcdef");
+        let ranges = |options: &SearchOptions| -> Vec<_> {
+            search_with(&text, &pattern, options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        let options = SearchOptions::new().max_errors(2);
+        assert_eq!(ranges(&options), vec![(0..6, 2), (7..13, 2)]);
+
+        // "abdcef" has more characters in the same order as the pattern.
+        let options = options.similarity_tiebreak(true);
+        assert_eq!(ranges(&options), vec![(7..13, 2), (0..6, 2)]);
+        assert_eq!(ranges(&options.max_results(Some(1))), vec![(7..13, 2)]);
+    }
+
+    #[test]
+    fn it_does_not_match_across_boundaries() {
+        let text = utf16_str("xc\0atx\0cot");
+        let ranges = |options: &SearchOptions| -> Vec<_> {
+            search_with(&text, &utf16_str("cat"), options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        let options = SearchOptions::new().max_errors(1).report_all(true);
+        assert!(ranges(&options).iter().any(|(r, _)| r.contains(&2)));
+
+        let options = options.boundaries(&[0]);
+        assert_eq!(ranges(&options), vec![(3..5, 1), (7..10, 1)]);
+
+        let options = options.report_all(false).max_results(Some(1));
+        assert_eq!(ranges(&options), vec![(3..5, 1)]);
+    }
+
+    #[test]
+    fn it_ignores_chars() {
+        let text = utf16_str("co\u{ad}op hyphen\u{ad}ation");
+        let ranges = |pattern: &str, options: &SearchOptions| -> Vec<_> {
+            search_with(&text, &utf16_str(pattern), options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        let options = SearchOptions::new();
+        assert_eq!(ranges("hyphenation", &options), vec![]);
+
+        let options = options.ignore_chars(&[0xad]);
+        assert_eq!(ranges("hyphenation", &options), vec![(6..18, 0)]);
+        assert_eq!(ranges("coop", &options), vec![(0..5, 0)]);
+
+        // Matches don't start or end with an ignored character.
+        assert_eq!(ranges("co", &options), vec![(0..2, 0)]);
+        assert_eq!(ranges("op", &options), vec![(3..5, 0)]);
+    }
+
+    #[test]
+    fn it_keeps_surrogate_pairs_whole() {
+        let scalar_aware = SearchOptions::new()
+            .surrogate_policy(SurrogatePolicy::ScalarAware)
+            .report_all(true);
+        let ranges = |text: &[u16], pattern: &[u16], options: &SearchOptions| -> Vec<_> {
+            search_with(text, pattern, options)
+                .iter()
+                .map(|m| m.range())
+                .collect()
+        };
+
+        // "le x" can match the high surrogate of the emoji as a substitution.
+        let text = utf16_str("smile 🙂 please");
+        let pattern = utf16_str("le x");
+        let raw = ranges(&text, &pattern, &SearchOptions::new().max_errors(1));
+        assert!(raw.contains(&(3..7)));
+        let widened = ranges(&text, &pattern, &scalar_aware.clone().max_errors(1));
+        assert!(widened.contains(&(3..8)));
+        assert!(!widened.iter().any(|r| r.start == 7 || r.end == 7));
+
+        // Unpaired high and low surrogates all match each other.
+        let text = [b'a' as u16, 0xd83d, b'b' as u16, 0xdc00, b'c' as u16];
+        assert_eq!(ranges(&text, &[0xd83e], &SearchOptions::new()), vec![]);
+        assert_eq!(ranges(&text, &[0xd83e], &scalar_aware), vec![1..2, 3..4]);
+        assert_eq!(ranges(&text, &[0xdc00], &SearchOptions::new()), vec![3..4]);
+        assert_eq!(ranges(&text, &[0xfffd], &scalar_aware), vec![1..2, 3..4]);
+    }
+
+    #[test]
+    fn it_suppresses_nearby_matches() {
+        let text = utf16_str("the cat sat, a cart");
+        let pattern = utf16_str("cat");
+        let ranges = |options: &SearchOptions| -> Vec<_> {
+            search_with(&text, &pattern, options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        let options = SearchOptions::new().max_errors(1).report_all(true);
+        assert_eq!(
+            ranges(&options),
+            vec![
+                (4..6, 1),
+                (4..7, 0),
+                (4..8, 1),
+                (8..11, 1),
+                (15..17, 1),
+                (15..18, 1),
+                (15..19, 1)
+            ]
+        );
+
+        // Each cluster of three ends collapses to its best match. "sat" starts
+        // one code unit after the end of "cat", so it is dropped too.
+        assert_eq!(ranges(&options.min_gap(1)), vec![(4..7, 0), (15..17, 1)]);
+    }
+
+    #[test]
+    fn it_measures_gaps_from_kept_matches() {
+        let ranges = |text: &str, pattern: &str, options: &SearchOptions| -> Vec<_> {
+            search_with(&utf16_str(text), &utf16_str(pattern), options)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        // A run of evenly spaced exact matches is thinned out rather than
+        // reduced to a single match.
+        let options = SearchOptions::new().report_all(true).min_gap(1);
+        assert_eq!(
+            ranges(&"a".repeat(20), "aa", &options),
+            vec![(0..2, 0), (4..6, 0), (8..10, 0), (12..14, 0), (16..18, 0)]
+        );
+
+        // The exact match of "cat" in "cats" is not at a word boundary, so it
+        // does not suppress the approximate match "cats".
+        let options = SearchOptions::new()
+            .max_errors(1)
+            .report_all(true)
+            .word_boundary(true)
+            .min_gap(1);
+        assert_eq!(
+            ranges("cats   cat", "cat", &options),
+            vec![(0..4, 1), (7..10, 0)]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+// This module implements searching a text in parallel, enabled by the `rayon`
+// feature.
+//
+// The text is split into chunks which are scanned independently. Each chunk
+// "owns" the matches which end within it, but the scan of a chunk starts
+// `pattern.len() + max_errors` characters earlier, since a match with at most
+// `max_errors` errors can be no longer than that. This ensures that the error
+// count computed for every position in the chunk is the same as it would be
+// for a scan of the whole text. Positions before the start of the chunk may get
+// higher error counts than in a full scan, so matches ending there are
+// discarded in favor of those found by the previous chunk.
+
+use alloc::vec::Vec;
+use rayon::prelude::*;
+
+use crate::{
+    find_match_ends, find_match_starts, reverse, BlockWord, Match, MatchOptions, PatternBits,
+    Report,
+};
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors, splitting the work between `num_chunks` parallel tasks.
+///
+/// This returns the same matches as `search`. The chunks overlap by
+/// `pattern.len() + max_errors` characters, so splitting the text into chunks
+/// much shorter than that adds overhead.
+pub fn search_parallel(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+    num_chunks: usize,
+) -> Vec<Match> {
+    if pattern.is_empty() || text.is_empty() {
+        return Vec::new();
+    }
+
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let overlap = pattern.len() + max_errors;
+    let chunk_len = text.len().div_ceil(num_chunks.max(1));
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+
+    let chunk_ends: Vec<Vec<Match>> = (0..text.len())
+        .step_by(chunk_len)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|chunk_start| {
+            let chunk_end = (chunk_start + chunk_len).min(text.len());
+            let window_start = chunk_start.saturating_sub(overlap);
+
+            // Each task needs its own `PatternBits`, but cloning one only
+            // copies references to the shared bit vectors.
+            let mut bits = bits.clone();
+
+            // All matches are needed, since the best matches within the window
+            // may be ones that are discarded.
+            let mut ends = find_match_ends(
+                &text[window_start..chunk_end],
+                pattern.len(),
+                &mut bits,
+                max_errors,
+                Report::All,
+                &mut (),
+            );
+            ends.retain(|m| m.end + window_start > chunk_start);
+            for m in ends.iter_mut() {
+                m.end += window_start;
+            }
+            ends
+        })
+        .collect();
+
+    // Keep only the matches with the lowest error count, as `search` does.
+    let min_errors = chunk_ends.iter().flatten().map(|m| m.errors).min();
+    let ends: Vec<Match> = chunk_ends
+        .into_iter()
+        .flatten()
+        .filter(|m| Some(m.errors) == min_errors)
+        .collect();
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    find_match_starts(text, pattern.len(), &mut rev_bits, ends)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::search_parallel;
+    use crate::search;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_finds_match_on_chunk_boundary() {
+        // With 4 chunks, the chunks of this 40 character text start at multiples
+        // of 10, so the first match straddles a boundary.
+        let text = utf16_str("aaaaaaaquixk brown fox aaaaaaaaaaquickaa");
+        assert_eq!(text.len(), 40);
+        let pattern = utf16_str("quick");
+
+        for max_errors in 0..3 {
+            let expected = search(&text, &pattern, max_errors);
+            for num_chunks in [1, 2, 4, 8, 40, 100] {
+                let actual = search_parallel(&text, &pattern, max_errors, num_chunks);
+                assert_eq!(actual, expected);
+            }
+        }
+
+        let matches = search_parallel(&text, &utf16_str("quixk"), 0, 4);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].range(), 7..12);
+    }
+}
@@ -0,0 +1,219 @@
+// This module implements a straightforward dynamic programming version of the
+// search, which is used as a reference to test the bit-vector implementation
+// against.
+//
+// Everything is computed in the most direct way: the full error count table
+// for the forward scan, and for each match a second table giving the error
+// count of every substring of the text which ends where the match ends. This
+// is far too slow for real use, but small and easy to check by reading.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Match, MatchLengthPreference, Report};
+
+/// Return the edit distance between `a` and each prefix of `b`, ie. entry `j`
+/// is the distance between `a` and `b[..j]`.
+fn prefix_distances(a: &[u16], b: &[u16]) -> Vec<usize> {
+    // `row[i]` is the distance between `a[..i]` and the prefix of `b`
+    // processed so far.
+    let mut row: Vec<usize> = (0..=a.len()).collect();
+    let mut distances = vec![a.len()];
+    for (j, &ch_b) in b.iter().enumerate() {
+        let mut next = vec![j + 1; a.len() + 1];
+        for i in 1..=a.len() {
+            let substitute = row[i - 1] + (a[i - 1] != ch_b) as usize;
+            next[i] = substitute.min(row[i] + 1).min(next[i - 1] + 1);
+        }
+        row = next;
+        distances.push(row[a.len()]);
+    }
+    distances
+}
+
+/// Return the edit distance between `a` and `b`.
+pub(crate) fn edit_distance(a: &[u16], b: &[u16]) -> usize {
+    prefix_distances(a, b)[b.len()]
+}
+
+/// Return the lowest error count of a match of `pattern` ending at each
+/// position in `text`, ie. entry `j` is for a match ending at `j + 1`.
+fn end_scores(text: &[u16], pattern: &[u16]) -> Vec<usize> {
+    // `column[i]` is the lowest error count of a match of `pattern[..i]`
+    // ending at the current text position. Matches may start anywhere, so the
+    // first row is zero.
+    let mut column: Vec<usize> = (0..=pattern.len()).collect();
+    let mut scores = Vec::with_capacity(text.len());
+    for &ch in text {
+        let mut next = vec![0; pattern.len() + 1];
+        for i in 1..=pattern.len() {
+            let substitute = column[i - 1] + (pattern[i - 1] != ch) as usize;
+            next[i] = substitute.min(column[i] + 1).min(next[i - 1] + 1);
+        }
+        column = next;
+        scores.push(column[pattern.len()]);
+    }
+    scores
+}
+
+/// Find the end positions and error counts of matches of `pattern` in `text`,
+/// with `start` set to zero, as `find_match_ends` does.
+pub(crate) fn find_match_ends(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: usize,
+    report: Report,
+) -> Vec<Match> {
+    let scores = end_scores(text, pattern);
+    let threshold = match report {
+        Report::All => max_errors,
+        Report::Best => match scores.iter().min() {
+            Some(&best) if best <= max_errors => best,
+            _ => return Vec::new(),
+        },
+    };
+    scores
+        .iter()
+        .enumerate()
+        .filter(|(_, &errors)| errors <= threshold)
+        .map(|(j, &errors)| Match {
+            start: 0,
+            end: j + 1,
+            errors,
+        })
+        .collect()
+}
+
+/// Find matches of `pattern` in `text`, as `search` (for `Report::Best`) or
+/// `search_all` (for `Report::All`) do.
+///
+/// The start of each match is the earliest or latest position, depending on
+/// `preference`, from which `pattern` matches the text up to the end of the
+/// match with the match's error count.
+pub(crate) fn search(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: usize,
+    report: Report,
+    preference: MatchLengthPreference,
+) -> Vec<Match> {
+    let mut matches = find_match_ends(text, pattern, max_errors, report);
+    let reversed = |chars: &[u16]| -> Vec<u16> { chars.iter().rev().copied().collect() };
+    let pattern_rev = reversed(pattern);
+    for m in matches.iter_mut() {
+        // Entry `len` is the error count of a match of length `len` ending at
+        // `m.end`.
+        let distances = prefix_distances(&pattern_rev, &reversed(&text[..m.end]));
+        let mut starts = (0..=m.end).filter(|&s| distances[m.end - s] == m.errors);
+        m.start = match preference {
+            MatchLengthPreference::Longest => starts.next(),
+            MatchLengthPreference::Shortest => starts.next_back(),
+        }
+        .expect("match should have a start");
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{edit_distance, find_match_ends, search};
+    use crate::test_util::Random;
+    use crate::{
+        search_all, search_with, search_with_word, BlockWord, MatchLengthPreference, MatchOptions,
+        PatternBits, Report, SearchOptions,
+    };
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_computes_edit_distance() {
+        assert_eq!(
+            edit_distance(&utf16_str("kitten"), &utf16_str("sitting")),
+            3
+        );
+        assert_eq!(edit_distance(&utf16_str(""), &utf16_str("abc")), 3);
+        assert_eq!(edit_distance(&utf16_str("abc"), &utf16_str("abc")), 0);
+    }
+
+    #[test]
+    fn it_matches_reference_search() {
+        let mut random = Random::new(1);
+
+        for case in 0..3000 {
+            // Most cases use short patterns, which fit in one block. Some use
+            // patterns spanning several blocks.
+            let (pattern_len, text_len) = if case % 30 == 0 {
+                (1 + random.below(150), random.below(200))
+            } else {
+                (1 + random.below(10), random.below(40))
+            };
+            let pattern = random.chars(pattern_len, b"abc");
+            let text = random.chars(text_len, b"abc");
+            let max_errors = random.below(pattern_len + 2);
+            let context = || {
+                alloc::format!(
+                    "text={:?} pattern={:?} max_errors={}",
+                    alloc::string::String::from_utf16_lossy(&text),
+                    alloc::string::String::from_utf16_lossy(&pattern),
+                    max_errors
+                )
+            };
+
+            for report in [Report::Best, Report::All] {
+                let mut bits =
+                    PatternBits::<u16, BlockWord>::new(&pattern, MatchOptions::default());
+                let ends = crate::find_match_ends(
+                    &text,
+                    pattern.len(),
+                    &mut bits,
+                    max_errors,
+                    report,
+                    &mut (),
+                );
+                assert_eq!(
+                    ends,
+                    find_match_ends(&text, &pattern, max_errors, report),
+                    "{:?} {}",
+                    report,
+                    context()
+                );
+            }
+
+            let k = max_errors as u32;
+            let longest = MatchLengthPreference::Longest;
+            assert_eq!(
+                crate::search(&text, &pattern, k),
+                search(&text, &pattern, max_errors, Report::Best, longest),
+                "search {}",
+                context()
+            );
+            assert_eq!(
+                search_with_word::<u32>(&text, &pattern, k),
+                search(&text, &pattern, max_errors, Report::Best, longest),
+                "search_with_word {}",
+                context()
+            );
+            assert_eq!(
+                search_all(&text, &pattern, k),
+                search(&text, &pattern, max_errors, Report::All, longest),
+                "search_all {}",
+                context()
+            );
+
+            let shortest = MatchLengthPreference::Shortest;
+            let options = SearchOptions::new()
+                .max_errors(k)
+                .length_preference(shortest);
+            assert_eq!(
+                search_with(&text, &pattern, &options),
+                search(&text, &pattern, max_errors, Report::Best, shortest),
+                "shortest {}",
+                context()
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+// This module implements the search for a pattern which consists of a single
+// repeated character, eg. a run of padding characters, which is used instead
+// of the bit-vector scan by `search`.
+//
+// The edit distance between `c` repeated `m` times and a string of length `l`
+// containing `x` copies of `c` is `max(m, l) - min(x, m)`. Extending a string
+// of length `m` or more by one character on the left never lowers this, so the
+// lowest error count of a match ending at some position is `m` minus the
+// number of copies of `c` among the `m` text characters before it. Those counts
+// are maintained over a sliding window, so the scan is a single cheap pass over
+// the text.
+
+use alloc::vec::Vec;
+
+use crate::Match;
+
+/// Return the character which `pattern` consists of, if it is a single
+/// character repeated one or more times.
+pub(crate) fn repeated_char(pattern: &[u16]) -> Option<u16> {
+    let (&first, rest) = pattern.split_first()?;
+    rest.iter().all(|&ch| ch == first).then_some(first)
+}
+
+/// Find the best matches of `ch` repeated `pattern_len` times in `text`,
+/// allowing up to `max_errors` errors, replacing the contents of `out`.
+///
+/// The matches are the same as those which `search` finds using the bit-vector
+/// scan, including the choice of the earliest possible start for each match.
+pub(crate) fn find_repeated(
+    text: &[u16],
+    ch: u16,
+    pattern_len: usize,
+    max_errors: usize,
+    out: &mut Vec<Match>,
+) {
+    out.clear();
+    let mut best = max_errors.min(pattern_len);
+
+    // Number of copies of `ch` in the last `pattern_len` text characters.
+    let mut count = 0;
+
+    for end in 1..=text.len() {
+        if text[end - 1] == ch {
+            count += 1;
+        }
+        if end > pattern_len && text[end - 1 - pattern_len] == ch {
+            count -= 1;
+        }
+
+        let errors = pattern_len - count;
+        if errors > best {
+            continue;
+        }
+        if errors < best {
+            out.clear();
+            best = errors;
+        }
+
+        // A match which uses fewer than `pattern_len` copies of `ch` can be
+        // extended over copies immediately before it, each replacing one of
+        // the missing characters, without changing the error count.
+        let window_start = end.saturating_sub(pattern_len);
+        let extension = text[..window_start]
+            .iter()
+            .rev()
+            .take(errors)
+            .take_while(|&&c| c == ch)
+            .count();
+        out.push(Match {
+            start: window_start - extension,
+            end,
+            errors,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{find_repeated, repeated_char};
+    use crate::test_util::Random;
+    use crate::{reference, search, search_with_word, BlockWord, MatchLengthPreference, Report};
+
+    #[test]
+    fn it_detects_repeated_char_patterns() {
+        assert_eq!(repeated_char(&[b'a' as u16; 5]), Some(b'a' as u16));
+        assert_eq!(repeated_char(&[b'a' as u16]), Some(b'a' as u16));
+        assert_eq!(repeated_char(&[b'a' as u16, b'b' as u16]), None);
+        assert_eq!(repeated_char(&[]), None);
+    }
+
+    #[test]
+    fn it_matches_general_search_for_repeated_char() {
+        let mut random = Random::new(1);
+
+        for case in 0..2000 {
+            let pattern_len = if case % 20 == 0 {
+                1 + random.below(100)
+            } else {
+                1 + random.below(8)
+            };
+            let text_len = random.below(60);
+            let text = random.chars(text_len, b"baa");
+            let pattern = vec![b'a' as u16; pattern_len];
+            let max_errors = random.below(pattern_len + 2);
+
+            let mut matches = Vec::new();
+            find_repeated(&text, b'a' as u16, pattern_len, max_errors, &mut matches);
+            let general = search_with_word::<BlockWord>(&text, &pattern, max_errors as u32);
+            assert_eq!(
+                matches,
+                general,
+                "text={:?} pattern_len={} max_errors={}",
+                alloc::string::String::from_utf16_lossy(&text),
+                pattern_len,
+                max_errors
+            );
+            assert_eq!(
+                matches,
+                reference::search(
+                    &text,
+                    &pattern,
+                    max_errors,
+                    Report::Best,
+                    MatchLengthPreference::Longest
+                )
+            );
+            assert_eq!(search(&text, &pattern, max_errors as u32), matches);
+        }
+    }
+}
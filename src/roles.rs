@@ -0,0 +1,47 @@
+// This module defines wrappers which label the text and pattern of a search, so
+// that passing them in the wrong order is a compile error rather than a search
+// for the wrong thing.
+
+use alloc::vec::Vec;
+
+use crate::{search, Match};
+
+/// The pattern to search for, as UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Needle<'a>(pub &'a [u16]);
+
+/// The text to search in, as UTF-16 code units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Haystack<'a>(pub &'a [u16]);
+
+/// Search for approximate matches of `needle` in `haystack` allowing up to
+/// `max_errors` errors.
+///
+/// This is the same as `search(haystack.0, needle.0, max_errors)`, but since
+/// the arguments have distinct types, they can't be accidentally swapped.
+/// Offsets in the returned matches refer to the haystack.
+pub fn search_needle(needle: Needle, haystack: Haystack, max_errors: u32) -> Vec<Match> {
+    search(haystack.0, needle.0, max_errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{search_needle, Haystack, Needle};
+    use crate::search;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_searches_for_needle_in_haystack() {
+        let text = utf16_str("the cat sat on the mat");
+        let pattern = utf16_str("cta");
+
+        let matches = search_needle(Needle(&pattern), Haystack(&text), 1);
+        assert_eq!(matches, search(&text, &pattern, 1));
+        assert!(!matches.is_empty());
+    }
+}
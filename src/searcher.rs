@@ -0,0 +1,191 @@
+// This module defines `Searcher`, which performs searches using buffers that
+// are reused between calls.
+
+use crate::HashMap;
+use alloc::vec::Vec;
+
+use crate::{
+    find_match_start, scan_match_ends, BlockWord, Match, MatchBits, MatchLengthPreference, Report,
+    ScanBuffers, StartScratch, Word,
+};
+
+/// Bit vectors for each distinct character in a pattern, stored in flat arrays
+/// so that they can be rebuilt for a new pattern without allocating.
+struct FlatBits<W> {
+    block_count: usize,
+
+    // Bit vectors for character codes below 256, `block_count` words each.
+    ascii: Vec<W>,
+
+    // Bit vectors for other characters which occur in the pattern, and a map of
+    // character code to the index of its bit vector.
+    nonascii: Vec<W>,
+    nonascii_index: HashMap<u16, usize>,
+
+    // Bit vector for characters which do not occur in the pattern.
+    zero: Vec<W>,
+}
+
+impl<W: Word> FlatBits<W> {
+    fn new() -> FlatBits<W> {
+        FlatBits {
+            block_count: 0,
+            ascii: Vec::new(),
+            nonascii: Vec::new(),
+            nonascii_index: HashMap::new(),
+            zero: Vec::new(),
+        }
+    }
+
+    fn rebuild(&mut self, pattern: &[u16]) {
+        let block_count = pattern.len().div_ceil(W::BITS);
+        self.block_count = block_count;
+
+        self.ascii.clear();
+        self.ascii.resize(256 * block_count, W::ZERO);
+        self.nonascii.clear();
+        self.nonascii_index.clear();
+        self.zero.clear();
+        self.zero.resize(block_count, W::ZERO);
+
+        for (idx, ch) in pattern.iter().enumerate() {
+            let (table, offset) = if *ch < 256 {
+                (&mut self.ascii, *ch as usize * block_count)
+            } else {
+                let next_index = self.nonascii_index.len();
+                let index = *self.nonascii_index.entry(*ch).or_insert(next_index);
+                if index == next_index {
+                    self.nonascii.resize((index + 1) * block_count, W::ZERO);
+                }
+                (&mut self.nonascii, index * block_count)
+            };
+            let bits = &mut table[offset + idx / W::BITS];
+            *bits = *bits | (W::ONE << (idx % W::BITS));
+        }
+    }
+}
+
+impl<W: Word> MatchBits<u16, W> for FlatBits<W> {
+    fn match_bits(&mut self, ch: u16) -> &[W] {
+        let block_count = self.block_count;
+        if ch < 256 {
+            let offset = ch as usize * block_count;
+            &self.ascii[offset..offset + block_count]
+        } else if let Some(index) = self.nonascii_index.get(&ch) {
+            let offset = index * block_count;
+            &self.nonascii[offset..offset + block_count]
+        } else {
+            &self.zero
+        }
+    }
+}
+
+/// Performs searches using internal buffers which are reused between calls.
+///
+/// Once the buffers have grown to fit the largest pattern, texts and number of
+/// distinct characters searched for, further searches do not allocate, as long
+/// as the output `Vec` also has enough capacity.
+pub struct Searcher {
+    bits: FlatBits<BlockWord>,
+    rev_bits: FlatBits<BlockWord>,
+    pat_rev: Vec<u16>,
+    scan: ScanBuffers<BlockWord>,
+    start_scratch: StartScratch<BlockWord>,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher {
+            bits: FlatBits::new(),
+            rev_bits: FlatBits::new(),
+            pat_rev: Vec::new(),
+            scan: ScanBuffers::new(),
+            start_scratch: StartScratch::new(),
+        }
+    }
+
+    /// Search for approximate matches of `pattern` in `text` allowing up to
+    /// `max_errors` errors, replacing the contents of `out` with the result.
+    ///
+    /// The matches are the same as those returned by `search`.
+    pub fn search_into(
+        &mut self,
+        text: &[u16],
+        pattern: &[u16],
+        max_errors: u32,
+        out: &mut Vec<Match>,
+    ) {
+        out.clear();
+        if pattern.is_empty() {
+            return;
+        }
+
+        self.bits.rebuild(pattern);
+        self.pat_rev.clear();
+        self.pat_rev.extend(pattern.iter().rev());
+        self.rev_bits.rebuild(&self.pat_rev);
+
+        scan_match_ends(
+            text,
+            pattern.len(),
+            &mut self.bits,
+            max_errors as usize,
+            Report::Best,
+            &mut self.scan,
+            &mut (),
+        );
+
+        for m in self.scan.ends.iter() {
+            let start = find_match_start(
+                text,
+                pattern.len(),
+                &mut self.rev_bits,
+                m,
+                MatchLengthPreference::Longest,
+                &mut self.start_scratch,
+            );
+            out.push(Match {
+                start,
+                end: m.end,
+                errors: m.errors,
+            });
+        }
+    }
+}
+
+impl Default for Searcher {
+    fn default() -> Searcher {
+        Searcher::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::Searcher;
+    use crate::search;
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_reuses_buffers_between_searches() {
+        let mut searcher = Searcher::new();
+        let mut out = Vec::new();
+
+        for (text, pattern) in [
+            ("hello world", "wrld"),
+            ("hello world 🙂", "world 🙂"),
+            ("some cases", "some cas"),
+            ("Many years later, as he faced the firing squad, Colonel Aureliano Buendía was to remember that distant afternoon", "Colonel Aureliano Buendia was to remember that distant afternoon w"),
+            ("abc", ""),
+        ] {
+            let text = utf16_str(text);
+            let pattern = utf16_str(pattern);
+            searcher.search_into(&text, &pattern, 2, &mut out);
+            assert_eq!(out, search(&text, &pattern, 2));
+        }
+    }
+}
@@ -0,0 +1,386 @@
+// This module defines helpers for post-processing the matches returned by a
+// search.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::ops::Range;
+
+use crate::{search_all, Match};
+
+/// Select a set of non-overlapping matches from `matches`, such as the output
+/// of `search_all`.
+///
+/// Matches are considered in "best first" order (see `Match`): lowest error
+/// count first, then earliest start, then earliest end. Each match is accepted
+/// if its `start..end` range does not intersect a match that was already
+/// accepted. Empty matches are skipped, since they do not cover any of the
+/// text. The accepted matches are returned in order of their position in the
+/// text.
+pub fn select_non_overlapping(mut matches: Vec<Match>) -> Vec<Match> {
+    matches.retain(|m| !m.is_empty());
+    matches.sort();
+
+    // Accepted matches, keyed by start offset.
+    let mut accepted: BTreeMap<usize, Match> = BTreeMap::new();
+
+    for m in matches {
+        // Since accepted matches don't overlap each other, only the accepted
+        // match which starts closest before the end of `m` can intersect it.
+        let overlaps = accepted
+            .range(..m.end)
+            .next_back()
+            .is_some_and(|(_, prev)| prev.end > m.start);
+        if !overlaps {
+            accepted.insert(m.start, m);
+        }
+    }
+
+    accepted.into_values().collect()
+}
+
+/// Divide `text` into non-overlapping approximate matches of `pattern` with up
+/// to `max_errors` errors, choosing greedily from left to right.
+///
+/// This works like a tokenizer: starting at the beginning of the text, it takes
+/// the match from `search_all` which starts earliest at or after the current
+/// position, choosing the longest if several start there, and then continues
+/// from the end of that match. Unlike `select_non_overlapping`, which prefers
+/// matches with fewer errors wherever they are, a match is never rejected
+/// because a better one overlaps it further on, so this can find more matches.
+/// The matches are returned in order of position in the text.
+pub fn cover_left_to_right(text: &[u16], pattern: &[u16], max_errors: u32) -> Vec<Match> {
+    let mut matches = search_all(text, pattern, max_errors);
+    matches.sort_by_key(|m| (m.start, Reverse(m.end)));
+
+    let mut pos = 0;
+    matches.retain(|m| {
+        let accept = m.start >= pos && !m.is_empty();
+        if accept {
+            pos = m.end;
+        }
+        accept
+    });
+    matches
+}
+
+/// Replace approximate matches of `pattern` in `text` with up to `max_errors`
+/// errors with `replacement`, returning the rewritten text.
+///
+/// The matches which are replaced are chosen from those found by `search_all`
+/// using `select_non_overlapping`, so where candidate matches overlap, the one
+/// with the fewest errors is replaced. Text outside the replaced matches is
+/// copied unchanged. Empty matches, which are only possible when `max_errors`
+/// is at least the pattern length, are not replaced.
+pub fn fuzzy_replace(
+    text: &[u16],
+    pattern: &[u16],
+    replacement: &[u16],
+    max_errors: u32,
+) -> Vec<u16> {
+    let matches = select_non_overlapping(search_all(text, pattern, max_errors));
+    replace_matches(text, &matches, replacement)
+}
+
+/// Return a copy of `text` with each of `matches`, which must not overlap,
+/// replaced by `replacement`.
+pub(crate) fn replace_matches(text: &[u16], matches: &[Match], replacement: &[u16]) -> Vec<u16> {
+    let mut replaced = Vec::with_capacity(text.len());
+    for (segment, part) in split_around_matches(text, matches) {
+        match segment {
+            Segment::Gap => replaced.extend_from_slice(part),
+            Segment::Matched => replaced.extend_from_slice(replacement),
+        }
+    }
+    replaced
+}
+
+/// Combine matches whose ranges overlap, touch or are separated by at most
+/// `max_gap` code units into a single match.
+///
+/// Each combined match spans from the earliest start to the latest end of the
+/// matches it replaces. Its error count is the lowest error count of those
+/// matches. It is not re-measured, so it may be lower than the error count of
+/// the combined range as a whole. The result is sorted by position in the
+/// text.
+pub fn merge_matches(mut matches: Vec<Match>, max_gap: usize) -> Vec<Match> {
+    matches.sort_by_key(|m| (m.start, m.end));
+
+    let mut merged: Vec<Match> = Vec::with_capacity(matches.len());
+    for m in matches {
+        match merged.last_mut() {
+            Some(prev) if m.start <= prev.end.saturating_add(max_gap) => {
+                prev.end = prev.end.max(m.end);
+                prev.errors = prev.errors.min(m.errors);
+            }
+            _ => merged.push(m),
+        }
+    }
+    merged
+}
+
+/// Kind of a part of the text produced by `split_around_matches`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    /// Text between matches, or before the first or after the last match.
+    Gap,
+
+    /// The text of a match.
+    Matched,
+}
+
+/// Split `text` into the parts covered by `matches` and the gaps between them,
+/// in order of position in the text.
+///
+/// Together the segments cover the whole text. Matches are taken in order of
+/// position, and a match which overlaps one before it is skipped, so to choose
+/// between overlapping matches by quality, use `select_non_overlapping` first.
+/// Empty matches are also skipped. No empty gaps are produced, so adjacent
+/// matches give consecutive `Segment::Matched` segments, and if a match is at
+/// the start or end of the text, the first or last segment is a match.
+///
+/// # Panics
+///
+/// Panics if a match extends beyond the end of `text`.
+pub fn split_around_matches<'a>(
+    text: &'a [u16],
+    matches: &[Match],
+) -> impl Iterator<Item = (Segment, &'a [u16])> + 'a {
+    let mut ranges: Vec<Range<usize>> = matches
+        .iter()
+        .filter(|m| m.start < m.end)
+        .map(|m| m.range())
+        .collect();
+    ranges.sort_by_key(|r| (r.start, r.end));
+
+    let mut segments: Vec<(Segment, Range<usize>)> = Vec::with_capacity(ranges.len() * 2 + 1);
+    let mut pos = 0;
+    for range in ranges {
+        if range.start < pos {
+            continue;
+        }
+        if range.start > pos {
+            segments.push((Segment::Gap, pos..range.start));
+        }
+        pos = range.end;
+        segments.push((Segment::Matched, range));
+    }
+    if pos < text.len() {
+        segments.push((Segment::Gap, pos..text.len()));
+    }
+
+    segments
+        .into_iter()
+        .map(move |(segment, range)| (segment, &text[range]))
+}
+
+/// Part of the text produced by `matches_iter`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextPart {
+    /// A match of the pattern.
+    Matched(Match),
+
+    /// The range of text between two consecutive matches, or before the first
+    /// or after the last match.
+    Between(Range<usize>),
+}
+
+/// Find non-overlapping approximate matches of `pattern` in `text` with up to
+/// `max_errors` errors, returning the matches together with the text between
+/// them.
+///
+/// The matches are chosen from those found by `search_all` using
+/// `select_non_overlapping`, skipping empty matches. The items alternate
+/// between `TextPart::Between` and `TextPart::Matched`, in order of position,
+/// starting and ending with `TextPart::Between`, so `n` matches give `n + 1`
+/// gaps and every second item is a match. A gap is empty if two matches are
+/// adjacent, or if a match is at the start or end of the text. If there are no
+/// matches, the only item is a gap covering the whole text.
+pub fn matches_iter(
+    text: &[u16],
+    pattern: &[u16],
+    max_errors: u32,
+) -> impl Iterator<Item = TextPart> {
+    let matches = select_non_overlapping(search_all(text, pattern, max_errors));
+
+    let mut parts = Vec::with_capacity(matches.len() * 2 + 1);
+    let mut pos = 0;
+    for m in matches.into_iter().filter(|m| !m.is_empty()) {
+        parts.push(TextPart::Between(pos..m.start));
+        pos = m.end;
+        parts.push(TextPart::Matched(m));
+    }
+    parts.push(TextPart::Between(pos..text.len()));
+    parts.into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::{
+        cover_left_to_right, fuzzy_replace, matches_iter, merge_matches, select_non_overlapping,
+        split_around_matches, Segment, TextPart,
+    };
+    use crate::{search_all, Match};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_selects_best_non_overlapping_matches() {
+        let text = utf16_str("a cat, a cot and a coat");
+        let pattern = utf16_str("cat");
+
+        let matches = select_non_overlapping(search_all(&text, &pattern, 1));
+        let ranges: Vec<_> = matches.iter().map(|m| m.range()).collect();
+
+        assert_eq!(ranges, vec![2..5, 9..12, 19..23]);
+    }
+
+    #[test]
+    fn it_skips_empty_matches_when_selecting() {
+        let ranges = |matches: Vec<Match>| -> Vec<_> {
+            select_non_overlapping(matches)
+                .iter()
+                .map(|m| (m.range(), m.errors()))
+                .collect()
+        };
+
+        assert_eq!(
+            ranges(vec![Match::new(2, 4, 0), Match::new(2, 2, 1)]),
+            [(2..4, 0)]
+        );
+        assert_eq!(
+            ranges(vec![
+                Match::new(0, 5, 0),
+                Match::new(3, 3, 1),
+                Match::new(4, 6, 1)
+            ]),
+            [(0..5, 0)]
+        );
+    }
+
+    #[test]
+    fn it_covers_text_from_left_to_right() {
+        let text = utf16_str("acababaac");
+        let pattern = utf16_str("aba");
+        let ranges = |matches: Vec<Match>| -> Vec<_> {
+            matches.iter().map(|m| (m.range(), m.errors())).collect()
+        };
+
+        // The exact match in the middle overlaps both of the matches which
+        // the greedy cover finds, so selecting the best matches finds only it.
+        assert_eq!(
+            ranges(cover_left_to_right(&text, &pattern, 1)),
+            [(0..3, 1), (4..8, 1)]
+        );
+        assert_eq!(
+            ranges(select_non_overlapping(search_all(&text, &pattern, 1))),
+            [(2..5, 0)]
+        );
+    }
+
+    #[test]
+    fn it_replaces_matches() {
+        let replace = |text: &str, pattern: &str, replacement: &str, max_errors| {
+            let replaced = fuzzy_replace(
+                &utf16_str(text),
+                &utf16_str(pattern),
+                &utf16_str(replacement),
+                max_errors,
+            );
+            String::from_utf16(&replaced).unwrap()
+        };
+
+        assert_eq!(
+            replace("a grey cat and a gray cat", "gray", "blue", 1),
+            "a blue cat and a blue cat"
+        );
+
+        // Matches at the start and end, with a longer replacement.
+        assert_eq!(
+            replace("cat sat cot", "cat", "kitten", 1),
+            "kitten kitten kitten"
+        );
+        assert_eq!(replace("a dog", "cat", "kitten", 1), "a dog");
+
+        // Overlapping candidates, such as "cat" and "tat", only replace the
+        // better match.
+        assert_eq!(replace("catat", "cat", "X", 1), "Xat");
+    }
+
+    #[test]
+    fn it_merges_nearby_matches() {
+        let m = |start, end, errors| Match { start, end, errors };
+        let matches = vec![m(10, 14, 2), m(0, 4, 1), m(3, 6, 0), m(8, 9, 1)];
+
+        assert_eq!(
+            merge_matches(matches.clone(), 0),
+            vec![m(0, 6, 0), m(8, 9, 1), m(10, 14, 2)]
+        );
+        assert_eq!(
+            merge_matches(matches.clone(), 1),
+            vec![m(0, 6, 0), m(8, 14, 1)]
+        );
+        assert_eq!(merge_matches(matches, 2), vec![m(0, 14, 0)]);
+    }
+
+    #[test]
+    fn it_splits_text_around_matches() {
+        let text = utf16_str("cat and a cot, dog");
+        let m = |start, end| Match {
+            start,
+            end,
+            errors: 0,
+        };
+        let split = |matches: &[Match]| -> Vec<(Segment, String)> {
+            split_around_matches(&text, matches)
+                .map(|(segment, slice)| (segment, String::from_utf16(slice).unwrap()))
+                .collect()
+        };
+
+        // Matches at the start and end of the text, and adjacent matches.
+        // The match overlapping "cot" is skipped.
+        assert_eq!(
+            split(&[m(15, 18), m(10, 13), m(0, 3), m(11, 15), m(13, 15)]),
+            vec![
+                (Segment::Matched, "cat".to_string()),
+                (Segment::Gap, " and a ".to_string()),
+                (Segment::Matched, "cot".to_string()),
+                (Segment::Matched, ", ".to_string()),
+                (Segment::Matched, "dog".to_string()),
+            ]
+        );
+        assert_eq!(
+            split(&[]),
+            vec![(Segment::Gap, String::from_utf16(&text).unwrap())]
+        );
+    }
+
+    #[test]
+    fn it_iterates_over_matches_and_gaps() {
+        let text = utf16_str("ERROR disk full; EROR no route;ERRORERROR");
+        let parts: Vec<_> = matches_iter(&text, &utf16_str("ERROR"), 1).collect();
+        assert_eq!(
+            parts,
+            vec![
+                TextPart::Between(0..0),
+                TextPart::Matched(Match::new(0, 5, 0)),
+                TextPart::Between(5..17),
+                TextPart::Matched(Match::new(17, 21, 1)),
+                TextPart::Between(21..31),
+                TextPart::Matched(Match::new(31, 36, 0)),
+                TextPart::Between(36..36),
+                TextPart::Matched(Match::new(36, 41, 0)),
+                TextPart::Between(41..41),
+            ]
+        );
+
+        let parts: Vec<_> = matches_iter(&text, &utf16_str("WARN"), 0).collect();
+        assert_eq!(parts, vec![TextPart::Between(0..text.len())]);
+    }
+}
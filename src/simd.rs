@@ -0,0 +1,204 @@
+// This module implements a SIMD variant of the forward scan for long patterns,
+// enabled by the `simd` feature.
+//
+// The blocks of a column cannot be processed in parallel, because each block
+// needs the horizontal delta output by the block above it for the same text
+// character. Following [2] the blocks are instead processed in stripes of
+// `LANES` blocks along anti-diagonals: at each step, lane `l` of the stripe
+// processes text character `t - l`, which needs the delta produced by lane
+// `l - 1` in the previous step. Each stripe makes a full pass over the text,
+// recording the deltas output by its bottom block for use by the next stripe.
+//
+// Unlike the scalar scan, this computes every block of every column, so it only
+// pays off for patterns spanning several blocks and error thresholds which
+// leave few blocks to skip. See `is_preferred`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use wide::u64x4;
+
+use crate::{Char, Match, PatternBits, Report};
+
+/// Number of blocks processed in parallel.
+const LANES: usize = 4;
+
+/// Patterns shorter than this are searched with the scalar scan.
+const MIN_PATTERN_LEN: usize = 4 * 64;
+
+/// Return true if the SIMD scan is expected to be faster than the scalar scan
+/// for a given pattern length and error threshold.
+///
+/// The scalar scan skips blocks whose error counts exceed the threshold, which
+/// usually outweighs the benefit of SIMD unless the threshold is high.
+pub(crate) fn is_preferred(pattern_len: usize, max_errors: usize) -> bool {
+    pattern_len >= MIN_PATTERN_LEN && max_errors * 2 >= pattern_len
+}
+
+/// Find the end positions of matches of `pattern` in `text`, as
+/// `find_match_ends` does, processing the blocks of the pattern in stripes.
+pub(crate) fn find_match_ends<C: Char>(
+    text: &[C],
+    pattern_len: usize,
+    bits: &PatternBits<C, u64>,
+    max_errors: usize,
+    report: Report,
+) -> Vec<Match> {
+    let block_count = pattern_len.div_ceil(64);
+    let stripe_count = block_count.div_ceil(LANES);
+    let one = u64x4::splat(1);
+
+    // Horizontal delta output by the bottom block of the previous stripe for
+    // each text character. The first row of the table is zero, so the first
+    // stripe receives no input.
+    let mut h_bottom = vec![0i8; text.len()];
+
+    // Match bits which each lane will use for the current step.
+    let zero_bits = vec![0; block_count];
+
+    for stripe in 0..stripe_count {
+        let first_block = stripe * LANES;
+        let last_block = (first_block + LANES).min(block_count) - 1;
+        let bottom_lane = last_block - first_block;
+
+        let mut plus_v = u64x4::splat(!0);
+        let mut minus_v = u64x4::splat(0);
+        let last_row_mask = u64x4::new(core::array::from_fn(|lane| {
+            if first_block + lane == block_count - 1 {
+                1 << ((pattern_len - 1) % 64)
+            } else {
+                1 << 63
+            }
+        }));
+
+        // Horizontal deltas output by each lane in the previous step, as masks
+        // of the low bit.
+        let mut h_out_positive = u64x4::splat(0);
+        let mut h_out_negative = u64x4::splat(0);
+
+        let mut lane_bits: [&[u64]; LANES] = [&zero_bits; LANES];
+
+        for t in 0..text.len() + LANES - 1 {
+            // Lane `l` processes the text character that lane `l - 1` processed
+            // in the previous step.
+            lane_bits.copy_within(0..LANES - 1, 1);
+            lane_bits[0] = text.get(t).map(|&ch| bits.get(ch)).unwrap_or(&zero_bits);
+
+            let eq_bits = u64x4::new(core::array::from_fn(|lane| {
+                lane_bits[lane]
+                    .get(first_block + lane)
+                    .copied()
+                    .unwrap_or(0)
+            }));
+
+            let h_in = h_bottom.get(t).copied().unwrap_or(0);
+            let pos = h_out_positive.to_array();
+            let neg = h_out_negative.to_array();
+            let h_in_positive = u64x4::new([(h_in > 0) as u64, pos[0], pos[1], pos[2]]);
+            let h_in_negative = u64x4::new([(h_in < 0) as u64, neg[0], neg[1], neg[2]]);
+
+            // This is `advance_block` applied to each lane.
+            let eq = eq_bits | h_in_negative;
+            let x_v = eq | minus_v;
+            let x_h = (((eq & plus_v) + plus_v) ^ plus_v) | eq;
+
+            let mut p_h = minus_v | !(x_h | plus_v);
+            let mut m_h = plus_v & x_h;
+
+            h_out_positive = !(p_h & last_row_mask).cmp_eq(u64x4::splat(0)) & one;
+            h_out_negative = !(m_h & last_row_mask).cmp_eq(u64x4::splat(0)) & one;
+
+            p_h = (p_h << 1) | h_in_positive;
+            m_h = (m_h << 1) | h_in_negative;
+
+            let new_plus_v = m_h | !(x_v | p_h);
+            let new_minus_v = p_h & x_v;
+
+            if t < LANES - 1 {
+                // Lanes which have not yet reached the start of the text keep
+                // their initial state.
+                let started =
+                    u64x4::new(core::array::from_fn(|lane| if lane <= t { !0 } else { 0 }));
+                plus_v = started.blend(new_plus_v, plus_v);
+                minus_v = started.blend(new_minus_v, minus_v);
+            } else {
+                plus_v = new_plus_v;
+                minus_v = new_minus_v;
+            }
+
+            // Record the output of the stripe's bottom block. This overwrites
+            // an entry of `h_bottom` which has already been consumed.
+            if let Some(j) = t.checked_sub(bottom_lane).filter(|&j| j < text.len()) {
+                h_bottom[j] = h_out_positive.to_array()[bottom_lane] as i8
+                    - h_out_negative.to_array()[bottom_lane] as i8;
+            }
+        }
+    }
+
+    // Compute the error count at the last row of each column and report matches
+    // in the same way as `scan_match_ends`.
+    let mut threshold = max_errors.min(pattern_len) as isize;
+    let mut score = pattern_len as isize;
+    let mut matches: Vec<Match> = Vec::new();
+    for (j, &h) in h_bottom.iter().enumerate() {
+        score += h as isize;
+        if score > threshold {
+            continue;
+        }
+        if report == Report::Best {
+            if matches.last().is_some_and(|m| (score as usize) < m.errors) {
+                // Discard any earlier, worse matches.
+                matches.clear();
+            }
+            threshold = score;
+        }
+        matches.push(Match {
+            start: 0,
+            end: j + 1,
+            errors: score as usize,
+        });
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_match_ends;
+    use crate::test_util::Random;
+    use crate::{MatchOptions, PatternBits, Report};
+
+    #[test]
+    fn it_matches_scalar_scan() {
+        let mut random = Random::new(1);
+        for &pattern_len in &[1, 63, 64, 65, 200, 256, 300, 511, 700] {
+            let pattern = random.chars(pattern_len, b"abcd");
+            let mut text = random.chars(1500, b"abcd");
+            // Plant a copy of the pattern, with some edits, in the text.
+            let offset = 300.min(text.len() - pattern.len());
+            for (i, &ch) in pattern.iter().enumerate() {
+                if i % 17 != 5 {
+                    text[offset + i] = ch;
+                }
+            }
+
+            for &max_errors in &[0, pattern_len / 10, pattern_len / 2, pattern_len] {
+                for &report in &[Report::Best, Report::All] {
+                    let mut bits = PatternBits::<u16, u64>::new(&pattern, MatchOptions::default());
+                    let expected = crate::find_match_ends(
+                        &text,
+                        pattern.len(),
+                        &mut bits,
+                        max_errors,
+                        report,
+                        &mut (),
+                    );
+                    let actual = find_match_ends(&text, pattern.len(), &bits, max_errors, report);
+                    assert_eq!(
+                        actual, expected,
+                        "pattern_len={} max_errors={} report={:?}",
+                        pattern_len, max_errors, report
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+// This module defines search functions which accept Rust strings.
+
+use crate::offsets::OffsetMap;
+use crate::select::replace_matches;
+use crate::{search, search_all, select_non_overlapping, snap_to_char_boundaries};
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// An approximate match of a pattern within a `&str`, with offsets expressed as
+/// byte indices into the text.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StrMatch {
+    start: usize,
+    end: usize,
+    errors: usize,
+}
+
+impl StrMatch {
+    /// Byte offset of the first character of the match in the text.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset of the character after the last character of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Number of errors (insertions, deletions or substitutions) in the match.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Return the `start..end` byte range of the match, for slicing the text.
+    ///
+    /// Editor tooling often uses `u32` byte offsets instead, eg.
+    /// `text_size::TextRange`. These can be created from the ends of this
+    /// range with `u32::try_from`, which fails only for texts of 4 GiB or more.
+    pub fn range(&self) -> core::ops::Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// Return the part of `text` covered by `m`.
+///
+/// `m` must be a match found by searching `text` with `search_str`, so its
+/// offsets lie on `char` boundaries.
+///
+/// # Panics
+///
+/// Panics if the range of the match is out of bounds for `text`.
+pub fn matched_str<'a>(text: &'a str, m: &StrMatch) -> &'a str {
+    &text[m.range()]
+}
+
+/// Return the part of `text` covered by `m`, without panicking if its offsets
+/// do not lie on `char` boundaries.
+///
+/// This borrows from `text` whenever the offsets are on `char` boundaries,
+/// which is always the case for a match found by searching `text` with
+/// `search_str`. Otherwise, eg. if `m` was found in a different text, the
+/// covered bytes are copied and any partial characters at either end are
+/// replaced with U+FFFD REPLACEMENT CHARACTER.
+///
+/// # Panics
+///
+/// Panics if the range of the match is out of bounds for `text`.
+pub fn matched_cow<'a>(text: &'a str, m: &StrMatch) -> Cow<'a, str> {
+    String::from_utf8_lossy(&text.as_bytes()[m.range()])
+}
+
+/// Search for approximate matches of `pattern` in `text` allowing up to
+/// `max_errors` errors.
+///
+/// This is a convenience wrapper around `search` which handles conversion to
+/// and from UTF-16. The returned offsets are byte indices into `text`, so
+/// `&text[m.range()]` gives the matched text. Matches which start or end in the
+/// middle of a surrogate pair are widened to include the whole character.
+pub fn search_str(text: &str, pattern: &str, max_errors: u32) -> Vec<StrMatch> {
+    let text_utf16: Vec<u16> = text.encode_utf16().collect();
+    let pattern_utf16: Vec<u16> = pattern.encode_utf16().collect();
+    let offsets = OffsetMap::utf8(text);
+
+    search(&text_utf16, &pattern_utf16, max_errors)
+        .iter()
+        .map(|m| StrMatch {
+            start: offsets.start(m.start()),
+            end: offsets.end(m.end()),
+            errors: m.errors(),
+        })
+        .collect()
+}
+
+/// Replace approximate matches of `pattern` in `text` with up to `max_errors`
+/// errors with `replacement`, as `fuzzy_replace` does.
+///
+/// Candidate matches which start or end in the middle of a surrogate pair are
+/// widened to include the whole character before the matches to replace are
+/// chosen, so the chosen matches never overlap and the result is always valid.
+pub fn fuzzy_replace_str(text: &str, pattern: &str, replacement: &str, max_errors: u32) -> String {
+    let text_utf16: Vec<u16> = text.encode_utf16().collect();
+    let pattern_utf16: Vec<u16> = pattern.encode_utf16().collect();
+    let replacement_utf16: Vec<u16> = replacement.encode_utf16().collect();
+
+    let candidates = search_all(&text_utf16, &pattern_utf16, max_errors);
+    let matches = select_non_overlapping(snap_to_char_boundaries(&text_utf16, &candidates));
+    let replaced = replace_matches(&text_utf16, &matches, &replacement_utf16);
+    String::from_utf16(&replaced).expect("replaced text should not split surrogate pairs")
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::borrow::Cow;
+
+    use super::{fuzzy_replace_str, matched_cow, matched_str, search_str, StrMatch};
+
+    #[test]
+    fn it_reports_byte_offsets() {
+        let text = "Buendía was to remember";
+        let matches = search_str(text, "was", 0);
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(&text[matches[0].range()], "was");
+    }
+
+    #[test]
+    fn it_borrows_matched_text_when_possible() {
+        let text = "Buendía was to remember";
+        let m = &search_str(text, "Buendia", 1)[0];
+        assert!(matches!(matched_cow(text, m), Cow::Borrowed("Buendía")));
+
+        // The end of this range is in the middle of "í".
+        let m = StrMatch {
+            start: 0,
+            end: 6,
+            errors: 0,
+        };
+        let cow = matched_cow(text, &m);
+        assert!(matches!(cow, Cow::Owned(_)));
+        assert_eq!(cow, "Buend\u{fffd}");
+    }
+
+    #[test]
+    fn it_widens_matches_that_split_surrogate_pairs() {
+        let text = "smile 🙂 please";
+
+        // The pattern can match the high surrogate of the emoji as a
+        // substitution, which leaves the end in the middle of the pair.
+        let matches = search_str(text, "le x", 1);
+
+        assert!(matches.iter().any(|m| matched_str(text, m) == "le 🙂"));
+        for m in matches {
+            assert!(text.is_char_boundary(m.start()));
+            assert!(text.is_char_boundary(m.end()));
+        }
+    }
+
+    #[test]
+    fn it_replaces_matches_in_str() {
+        assert_eq!(
+            fuzzy_replace_str("J'ai vu un chât, pas un chien", "chat", "🐈", 1),
+            "J'ai vu un 🐈, pas un chien"
+        );
+        assert_eq!(fuzzy_replace_str("🐈 and 🐕", "🐕", "dog", 0), "🐈 and dog");
+
+        // Candidates which split an emoji only overlap once they are widened.
+        assert_eq!(fuzzy_replace_str("b😀😀b😀😁😀", "ba", "_", 2), "_____");
+    }
+}
@@ -0,0 +1,483 @@
+// This module implements searching a text which arrives in chunks, such as from
+// a socket, without holding the whole text in memory, or which is stored in
+// pieces, such as a rope.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{
+    find_match_start, reverse, BlockWord, ColumnState, Match, MatchLengthPreference, MatchOptions,
+    PatternBits, StartScratch,
+};
+
+/// Searches a stream of text for approximate matches of a pattern.
+///
+/// The text is supplied in chunks via `feed`. The state of the scan is kept
+/// between calls, so matches which span chunks are found, and offsets are
+/// relative to the start of the stream.
+///
+/// Because the best error count is not known until the end of the stream, this
+/// reports every match within the error threshold, like `search_all`.
+///
+/// To find where each match starts, the searcher retains the last
+/// `pattern.len() + max_errors` code units of the stream, since no match with
+/// at most `max_errors` errors can be longer than that. Memory use is therefore
+/// bounded by the pattern length and error threshold plus the size of the
+/// largest chunk.
+pub struct StreamingSearcher {
+    pattern_len: usize,
+    max_errors: usize,
+    bits: PatternBits<u16, BlockWord>,
+    rev_bits: PatternBits<u16, BlockWord>,
+    state: ColumnState<BlockWord>,
+
+    // Trailing text of the stream. `tail[0]` is at offset `tail_offset` in the
+    // stream.
+    tail: Vec<u16>,
+    tail_offset: usize,
+
+    scratch: StartScratch<BlockWord>,
+}
+
+impl StreamingSearcher {
+    /// Create a searcher for `pattern`, allowing up to `max_errors` errors.
+    ///
+    /// `max_errors` is clamped to the length of the pattern, and an empty
+    /// pattern never matches.
+    pub fn new(pattern: &[u16], max_errors: u32) -> StreamingSearcher {
+        let opts = MatchOptions::default();
+        let max_errors = (max_errors as usize).min(pattern.len());
+        let state = if pattern.is_empty() {
+            ColumnState::empty()
+        } else {
+            ColumnState::new(pattern.len(), max_errors, false)
+        };
+
+        StreamingSearcher {
+            pattern_len: pattern.len(),
+            max_errors,
+            bits: PatternBits::new(pattern, opts),
+            rev_bits: PatternBits::new(&reverse(pattern), opts),
+            state,
+            tail: Vec::new(),
+            tail_offset: 0,
+            scratch: StartScratch::new(),
+        }
+    }
+
+    /// Return the number of code units of text fed to the searcher so far.
+    pub fn position(&self) -> usize {
+        self.tail_offset + self.tail.len()
+    }
+
+    /// Capture the state of the scan, so that it can be continued later with
+    /// `resume`, possibly in a different process.
+    ///
+    /// The saved state does not include any of the text. To resume, the caller
+    /// must supply the code units of the stream at the offsets given by
+    /// `SearchState::context`, which are the ones the searcher has retained to
+    /// find the starts of future matches. This is at most
+    /// `pattern.len() + max_errors` code units.
+    pub fn checkpoint(&self) -> SearchState {
+        SearchState {
+            pattern_len: self.pattern_len,
+            max_errors: self.max_errors,
+            position: self.position(),
+            context_len: self.tail.len(),
+            y: self.state.y,
+            blocks: self
+                .state
+                .blocks
+                .iter()
+                .map(|block| SavedBlock {
+                    plus_v: block.plus_v,
+                    minus_v: block.minus_v,
+                    score: block.score,
+                })
+                .collect(),
+        }
+    }
+
+    /// Create a searcher for `pattern` which continues a scan from a state
+    /// saved by `checkpoint`.
+    ///
+    /// `pattern` must be the pattern of the searcher which saved `state` and
+    /// `context` must be the text at the offsets given by `state.context()`.
+    /// Feeding the rest of the stream to the new searcher then finds the same
+    /// matches, with the same offsets, as if the stream had been fed to a
+    /// single searcher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `pattern` or `context` does not match `state`.
+    pub fn resume(pattern: &[u16], state: &SearchState, context: &[u16]) -> StreamingSearcher {
+        assert_eq!(
+            pattern.len(),
+            state.pattern_len,
+            "pattern length does not match the saved state"
+        );
+        assert_eq!(
+            context.len(),
+            state.context_len,
+            "context length does not match the saved state"
+        );
+
+        let mut searcher = StreamingSearcher::new(pattern, state.max_errors as u32);
+        searcher.state.y = state.y;
+        for (block, saved) in searcher.state.blocks.iter_mut().zip(&state.blocks) {
+            block.plus_v = saved.plus_v;
+            block.minus_v = saved.minus_v;
+            block.score = saved.score;
+        }
+        searcher.tail.extend_from_slice(context);
+        searcher.tail_offset = state.position - context.len();
+        searcher
+    }
+
+    /// Process the next chunk of the stream, returning matches which end
+    /// within it.
+    pub fn feed(&mut self, chunk: &[u16]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        if self.pattern_len == 0 {
+            self.tail_offset += chunk.len();
+            return matches;
+        }
+
+        let chunk_start = self.tail.len();
+        self.tail.extend_from_slice(chunk);
+
+        for (i, &ch) in chunk.iter().enumerate() {
+            if let Some(errors) = self.state.advance(self.bits.get(ch), &mut ()) {
+                let mut m = Match {
+                    start: 0,
+                    end: chunk_start + i + 1,
+                    errors,
+                };
+                m.start = find_match_start(
+                    &self.tail,
+                    self.pattern_len,
+                    &mut self.rev_bits,
+                    &m,
+                    MatchLengthPreference::Longest,
+                    &mut self.scratch,
+                );
+                m.start += self.tail_offset;
+                m.end += self.tail_offset;
+                matches.push(m);
+            }
+        }
+
+        // Discard text which is too far back to be part of any future match.
+        let retain = self.pattern_len + self.max_errors;
+        if self.tail.len() > retain {
+            let excess = self.tail.len() - retain;
+            self.tail.drain(..excess);
+            self.tail_offset += excess;
+        }
+
+        matches
+    }
+}
+
+/// State of the scan of a `StreamingSearcher`, saved by `checkpoint`.
+///
+/// With the `serde` feature, this can be serialized to continue the scan in a
+/// different process.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchState {
+    pattern_len: usize,
+    max_errors: usize,
+
+    // Number of code units fed to the searcher.
+    position: usize,
+
+    // Number of code units before `position` needed to resume.
+    context_len: usize,
+
+    y: usize,
+    blocks: Vec<SavedBlock>,
+}
+
+impl SearchState {
+    /// Number of code units of the stream processed before the state was
+    /// saved.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Offsets of the trailing text of the stream which must be supplied to
+    /// `StreamingSearcher::resume`.
+    pub fn context(&self) -> Range<usize> {
+        self.position - self.context_len..self.position
+    }
+}
+
+/// Saved error counts for one block of the column of a `SearchState`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct SavedBlock {
+    plus_v: BlockWord,
+    minus_v: BlockWord,
+    score: isize,
+}
+
+/// Search for approximate matches of `pattern` in a text produced by an
+/// iterator, allowing up to `max_errors` errors.
+///
+/// This returns the same matches as `search`, but the text does not need to be
+/// held in memory. The text is consumed in a single pass. To find where matches
+/// start, the most recent code units of the text are buffered. No match with at
+/// most `max_errors` errors can be longer than `pattern.len() + max_errors`
+/// code units, and the buffer holds at most twice that many.
+pub fn search_iter_text(
+    text: impl IntoIterator<Item = u16>,
+    pattern: &[u16],
+    max_errors: u32,
+) -> Vec<Match> {
+    let mut matches: Vec<Match> = Vec::new();
+    if pattern.is_empty() {
+        return matches;
+    }
+
+    let opts = MatchOptions::default();
+    let max_errors = (max_errors as usize).min(pattern.len());
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors, true);
+    let mut scratch = StartScratch::new();
+
+    // Trailing text. `tail[0]` is at offset `tail_offset` in the text.
+    let retain = pattern.len() + max_errors;
+    let mut tail: Vec<u16> = Vec::with_capacity(retain * 2);
+    let mut tail_offset = 0;
+
+    for ch in text {
+        // Discard text which is too far back to be part of any future match.
+        // This is done in batches so that the cost is amortized.
+        if tail.len() == retain * 2 {
+            tail.drain(..retain);
+            tail_offset += retain;
+        }
+        tail.push(ch);
+
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            if matches.last().is_some_and(|m| errors < m.errors) {
+                // Discard any earlier, worse matches.
+                matches.clear();
+            }
+
+            let m = Match {
+                start: 0,
+                end: tail.len(),
+                errors,
+            };
+            let start = find_match_start(
+                &tail,
+                pattern.len(),
+                &mut rev_bits,
+                &m,
+                MatchLengthPreference::Longest,
+                &mut scratch,
+            );
+            matches.push(Match {
+                start: start + tail_offset,
+                end: m.end + tail_offset,
+                errors,
+            });
+        }
+    }
+
+    matches
+}
+
+/// A text stored in a form which is not a contiguous slice, such as a rope,
+/// whose code units can be read by offset.
+///
+/// This is used by `search_random_access` to read the text before the end of
+/// each match when finding where it starts. A rope would implement this by
+/// finding the chunk which contains `range.start`, using the lengths stored in
+/// its tree, and copying from that chunk and the ones after it until
+/// `range.end` is reached.
+pub trait RandomAccessText {
+    /// Append the code units at offsets `range` in the text to `out`.
+    ///
+    /// `range` is always within the text.
+    fn copy_range(&self, range: Range<usize>, out: &mut Vec<u16>);
+}
+
+impl RandomAccessText for [u16] {
+    fn copy_range(&self, range: Range<usize>, out: &mut Vec<u16>) {
+        out.extend_from_slice(&self[range]);
+    }
+}
+
+/// Search for approximate matches of `pattern` in `text`, which is stored in a
+/// form other than a contiguous slice, allowing up to `max_errors` errors.
+///
+/// `units` must produce the code units of `text` in order, eg. by iterating
+/// over each chunk of a rope in turn. This returns the same matches as
+/// `search`. The ends of matches are found in a single pass over `units`.
+/// Afterwards the start of each of the best matches is found by reading the
+/// part of `text` before its end which it could cover, which is at most
+/// `pattern.len() + max_errors` code units, so the text is never copied as a
+/// whole.
+pub fn search_random_access<T: RandomAccessText + ?Sized>(
+    units: impl IntoIterator<Item = u16>,
+    text: &T,
+    pattern: &[u16],
+    max_errors: u32,
+) -> Vec<Match> {
+    let mut matches: Vec<Match> = Vec::new();
+    if pattern.is_empty() {
+        return matches;
+    }
+
+    let opts = MatchOptions::default();
+    let bits = PatternBits::<u16, BlockWord>::new(pattern, opts);
+    let mut state = ColumnState::<BlockWord>::new(pattern.len(), max_errors as usize, true);
+    for (j, ch) in units.into_iter().enumerate() {
+        if let Some(errors) = state.advance(bits.get(ch), &mut ()) {
+            if matches.last().is_some_and(|m| errors < m.errors) {
+                // Discard any earlier, worse matches.
+                matches.clear();
+            }
+            matches.push(Match {
+                start: 0,
+                end: j + 1,
+                errors,
+            });
+        }
+    }
+
+    let mut rev_bits = PatternBits::<u16, BlockWord>::new(&reverse(pattern), opts);
+    let mut scratch = StartScratch::new();
+    let mut window = Vec::new();
+    for m in matches.iter_mut() {
+        let window_start = m.end.saturating_sub(pattern.len() + m.errors);
+        window.clear();
+        text.copy_range(window_start..m.end, &mut window);
+
+        let in_window = Match {
+            start: 0,
+            end: window.len(),
+            errors: m.errors,
+        };
+        m.start = window_start
+            + find_match_start(
+                &window,
+                pattern.len(),
+                &mut rev_bits,
+                &in_window,
+                MatchLengthPreference::Longest,
+                &mut scratch,
+            );
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{search_iter_text, search_random_access, RandomAccessText, StreamingSearcher};
+    use crate::{search, search_all};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_matches_search_all_for_any_chunk_size() {
+        let text = utf16_str("the cat sat on the mat with another cta and a hat");
+        let pattern = utf16_str("cat");
+
+        for max_errors in 0..3 {
+            let expected = search_all(&text, &pattern, max_errors);
+            for chunk_size in [1, 2, 3, 5, 8, 100] {
+                let mut searcher = StreamingSearcher::new(&pattern, max_errors);
+                let mut actual = Vec::new();
+                for chunk in text.chunks(chunk_size) {
+                    actual.extend(searcher.feed(chunk));
+                }
+                assert_eq!(searcher.position(), text.len());
+                assert_eq!(actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn it_resumes_from_checkpoint() {
+        let text = utf16_str("the cat sat on the mat with another cta and a hat");
+        let pattern = utf16_str("cat");
+        let expected = search_all(&text, &pattern, 1);
+
+        for split in [0, 5, 6, 36, text.len()] {
+            let mut searcher = StreamingSearcher::new(&pattern, 1);
+            let mut actual = searcher.feed(&text[..split]);
+            let state = searcher.checkpoint();
+            assert_eq!(state.position(), split);
+            assert!(state.context().len() <= pattern.len() + 1);
+
+            #[cfg(feature = "serde")]
+            let state: super::SearchState =
+                serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+
+            let mut resumed = StreamingSearcher::resume(&pattern, &state, &text[state.context()]);
+            actual.extend(resumed.feed(&text[split..]));
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn it_searches_text_from_iterator() {
+        let text_str = "the cat sat on the mat with another cta and a hat, ".repeat(5);
+        let text = utf16_str(&text_str);
+
+        for pattern in ["cat", "a hat", "mat with", "dog"] {
+            let pattern = utf16_str(pattern);
+            for max_errors in 0..3 {
+                let matches = search_iter_text(text_str.encode_utf16(), &pattern, max_errors);
+                assert_eq!(matches, search(&text, &pattern, max_errors));
+            }
+        }
+    }
+
+    /// Text stored as a list of chunks, as a simple stand-in for a rope.
+    struct Chunks(Vec<Vec<u16>>);
+
+    impl RandomAccessText for Chunks {
+        fn copy_range(&self, range: core::ops::Range<usize>, out: &mut Vec<u16>) {
+            let mut chunk_start = 0;
+            for chunk in self.0.iter() {
+                let chunk_end = chunk_start + chunk.len();
+                let start = range.start.clamp(chunk_start, chunk_end);
+                let end = range.end.clamp(chunk_start, chunk_end);
+                out.extend_from_slice(&chunk[start - chunk_start..end - chunk_start]);
+                chunk_start = chunk_end;
+            }
+        }
+    }
+
+    #[test]
+    fn it_searches_random_access_text() {
+        let text = utf16_str(&"the cat sat on the mat with another cta and a hat, ".repeat(5));
+        let chunks = Chunks(text.chunks(7).map(|chunk| chunk.to_vec()).collect());
+        let units = || chunks.0.iter().flatten().copied();
+
+        for pattern in ["cat", "a hat", "mat with", "dog"] {
+            let pattern = utf16_str(pattern);
+            for max_errors in 0..3 {
+                let expected = search(&text, &pattern, max_errors);
+                assert_eq!(
+                    search_random_access(units(), &chunks, &pattern, max_errors),
+                    expected
+                );
+                assert_eq!(
+                    search_random_access(text.iter().copied(), &text[..], &pattern, max_errors),
+                    expected
+                );
+            }
+        }
+    }
+}
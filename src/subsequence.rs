@@ -0,0 +1,173 @@
+// This module implements subsequence matching, as used by fuzzy finders, where
+// the characters of the pattern must appear in order in the text but any number
+// of text characters may separate them.
+//
+// Unlike the other searches in this crate, no substitutions are allowed, so
+// this does not use the bit-vector algorithm.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::Match;
+
+/// Find the shortest part of `text` which contains `pattern` as a subsequence,
+/// ie. which contains the characters of the pattern in order, with any number
+/// of other characters between them.
+///
+/// This is the matching used by fuzzy finders, where eg. "fb" matches
+/// "foo/bar". The `errors` of the returned match is the number of text
+/// characters between the pattern characters, ie. the length of the match
+/// minus the length of the pattern. If several parts of the text are equally
+/// short, the earliest is returned. Returns `None` if the pattern is empty or
+/// does not occur as a subsequence.
+///
+/// Each time the scan finds the end of an occurrence, it scans backwards to
+/// find the latest start of one, and then continues from just after that
+/// start. This takes O(n) time when the pattern occurs rarely, and O(m * n)
+/// time in the worst case for a pattern of length `m` and a text of length
+/// `n`.
+pub fn search_subsequence(text: &[u16], pattern: &[u16]) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<Match> = None;
+    let mut pos = 0;
+    loop {
+        // Find the earliest end of an occurrence which starts at or after `pos`.
+        let mut matched = 0;
+        let mut end = pos;
+        while matched < pattern.len() {
+            let Some(offset) = text[end..].iter().position(|&ch| ch == pattern[matched]) else {
+                return best;
+            };
+            end += offset + 1;
+            matched += 1;
+        }
+
+        // Find the latest start of an occurrence which ends there.
+        let mut start = end;
+        for &pattern_ch in pattern.iter().rev() {
+            while text[start - 1] != pattern_ch {
+                start -= 1;
+            }
+            start -= 1;
+        }
+
+        let len = end - start;
+        if best.as_ref().is_none_or(|m| len < m.len()) {
+            best = Some(Match {
+                start,
+                end,
+                errors: len - pattern.len(),
+            });
+        }
+        pos = start + 1;
+    }
+}
+
+/// Variant of `search_subsequence` which prefers occurrences where the pattern
+/// characters are in fewer separate runs of the text.
+///
+/// This ranks occurrences by the number of gaps between pattern characters,
+/// regardless of the length of each gap, so for "abcd", "abc....d" is preferred
+/// to the shorter "a.b.c.d". Ties are broken by choosing the shortest, and
+/// then the earliest, occurrence. The `errors` of the returned match is the
+/// number of text characters between the pattern characters, as with
+/// `search_subsequence`.
+///
+/// This uses dynamic programming over the pattern and text, which takes
+/// O(m * n) time for a pattern of length `m` and a text of length `n`.
+pub fn search_subsequence_contiguous(text: &[u16], pattern: &[u16]) -> Option<Match> {
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // `row[j]` is the best `(gaps, start)` of an occurrence of the pattern
+    // prefix processed so far whose last character is `text[j]`, ordered so
+    // that the fewest gaps and then the latest start is the minimum.
+    let mut row: Vec<Option<(usize, Reverse<usize>)>> = text
+        .iter()
+        .enumerate()
+        .map(|(j, &ch)| (ch == pattern[0]).then_some((0, Reverse(j))))
+        .collect();
+
+    for &pattern_ch in &pattern[1..] {
+        let mut next = vec![None; text.len()];
+
+        // Best entry of `row` before `j - 1`.
+        let mut earlier: Option<(usize, Reverse<usize>)> = None;
+        for j in 1..text.len() {
+            if text[j] == pattern_ch {
+                let after_gap = earlier.map(|(gaps, start)| (gaps + 1, start));
+                next[j] = match (row[j - 1], after_gap) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+            if let Some(entry) = row[j - 1] {
+                earlier = Some(earlier.map_or(entry, |e| e.min(entry)));
+            }
+        }
+        row = next;
+    }
+
+    let (_, len, start) = row
+        .iter()
+        .enumerate()
+        .filter_map(|(j, entry)| {
+            let (gaps, Reverse(start)) = (*entry)?;
+            Some((gaps, j + 1 - start, start))
+        })
+        .min()?;
+
+    Some(Match {
+        start,
+        end: start + len,
+        errors: len - pattern.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{search_subsequence, search_subsequence_contiguous};
+
+    fn utf16_str(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn it_finds_shortest_subsequence_match() {
+        let text = utf16_str("src/foo/bar.rs");
+        let m = search_subsequence(&text, &utf16_str("fb")).unwrap();
+        assert_eq!((m.range(), m.errors()), (4..9, 3));
+
+        // The later "foob" is shorter than "foo/b".
+        let text = utf16_str("foo/bar foobar");
+        let m = search_subsequence(&text, &utf16_str("fb")).unwrap();
+        assert_eq!(m.range(), 8..12);
+
+        assert_eq!(search_subsequence(&text, &utf16_str("rz")), None);
+        assert_eq!(search_subsequence(&text, &[]), None);
+    }
+
+    #[test]
+    fn it_prefers_contiguous_subsequence_match() {
+        let text = utf16_str("a.b.c.d abc....d");
+        let pattern = utf16_str("abcd");
+
+        let m = search_subsequence(&text, &pattern).unwrap();
+        assert_eq!((m.range(), m.errors()), (0..7, 3));
+
+        let m = search_subsequence_contiguous(&text, &pattern).unwrap();
+        assert_eq!((m.range(), m.errors()), (8..16, 4));
+
+        assert_eq!(
+            search_subsequence_contiguous(&text, &utf16_str("d.a")),
+            None
+        );
+    }
+}
@@ -0,0 +1,33 @@
+// This module defines helpers which are shared by the tests of several
+// modules.
+
+use alloc::vec::Vec;
+
+/// Seeded pseudo-random number generator for the randomized tests.
+///
+/// This is a linear congruential generator, so a given seed always produces
+/// the same sequence and any failure can be reproduced.
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    /// Return a number in `0..n`.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % n as u64) as usize
+    }
+
+    /// Generate `len` characters chosen from `alphabet`. A small alphabet
+    /// makes patterns and texts similar enough to produce matches.
+    pub(crate) fn chars(&mut self, len: usize, alphabet: &[u8]) -> Vec<u16> {
+        (0..len)
+            .map(|_| alphabet[self.below(alphabet.len())] as u16)
+            .collect()
+    }
+}
@@ -1,10 +1,14 @@
 // This module defines the WASM API for the library.
 
-use crate::search_impl;
-use crate::Match;
+use std::ffi::{CStr, CString};
+use std::fmt::Write;
+
+use crate::multi::search_multi as search_multi_impl;
+use crate::{best_similarity, to_char_offsets, CompiledPattern, Match};
+use crate::{search_impl, search_into};
 
 // Use `wee_alloc` as the global allocator to reduce library size.
-extern crate wee_alloc;
+#[cfg(feature = "wee-alloc")]
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
@@ -21,7 +25,7 @@ pub extern "C" fn match_vec_len(mv: &Vec<Match>) -> usize {
 
 #[no_mangle]
 pub extern "C" fn match_vec_free(mv: *mut Vec<Match>) {
-    unsafe { Box::from_raw(mv) };
+    unsafe { drop(Box::from_raw(mv)) };
 }
 
 #[no_mangle]
@@ -29,11 +33,16 @@ pub extern "C" fn match_vec_get(mv: &Vec<Match>, index: usize) -> &Match {
     &mv[index]
 }
 
+/// Return the start offset of a match.
+///
+/// Offsets are UTF-16 code unit indices, which can be used directly to index
+/// JavaScript strings, unless the match was found by `search_codepoints`.
 #[no_mangle]
 pub extern "C" fn match_start(m: &Match) -> usize {
     m.start
 }
 
+/// Return the end offset of a match. See `match_start` for the offset space.
 #[no_mangle]
 pub extern "C" fn match_end(m: &Match) -> usize {
     m.end
@@ -57,7 +66,51 @@ pub extern "C" fn char_buf_data(buf: &mut Vec<u16>) -> *mut u16 {
 
 #[no_mangle]
 pub extern "C" fn char_buf_free(buf: *mut Vec<u16>) {
-    unsafe { Box::from_raw(buf) };
+    unsafe { drop(Box::from_raw(buf)) };
+}
+
+#[no_mangle]
+pub extern "C" fn len_buf_alloc(len: usize) -> *mut Vec<u32> {
+    let box_ = Box::new(vec![0; len]);
+    Box::into_raw(box_)
+}
+
+#[no_mangle]
+pub extern "C" fn len_buf_data(buf: &mut Vec<u32>) -> *mut u32 {
+    buf.as_mut_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn len_buf_free(buf: *mut Vec<u32>) {
+    unsafe { drop(Box::from_raw(buf)) };
+}
+
+#[no_mangle]
+pub extern "C" fn multi_match_vec_alloc() -> *mut Vec<(usize, Match)> {
+    let box_ = Box::new(Vec::new());
+    Box::into_raw(box_)
+}
+
+#[no_mangle]
+pub extern "C" fn multi_match_vec_len(mv: &Vec<(usize, Match)>) -> usize {
+    mv.len()
+}
+
+#[no_mangle]
+pub extern "C" fn multi_match_vec_free(mv: *mut Vec<(usize, Match)>) {
+    unsafe { drop(Box::from_raw(mv)) };
+}
+
+/// Return the index of the pattern for the `index`th result of `search_multi`.
+#[no_mangle]
+pub extern "C" fn multi_match_vec_pattern(mv: &Vec<(usize, Match)>, index: usize) -> usize {
+    mv[index].0
+}
+
+/// Return the `index`th match found by `search_multi`.
+#[no_mangle]
+pub extern "C" fn multi_match_vec_get(mv: &Vec<(usize, Match)>, index: usize) -> &Match {
+    &mv[index].1
 }
 
 #[no_mangle]
@@ -67,8 +120,271 @@ pub extern "C" fn search(
     pat: &Vec<u16>,
     max_errors: u32,
 ) -> usize {
-    let search_matches = search_impl(&text, &pat, max_errors);
+    search_into(text, pat, max_errors, match_vec);
+    match_vec.len()
+}
+
+/// Return the similarity of a pattern to its best match in a text, as a number
+/// from 0.0 (no similarity) to 1.0 (an exact match).
+///
+/// The score is left in the range [0, 1] rather than scaled to a percentage, so
+/// that it is the same as the value returned by `best_similarity`.
+///
+/// # Safety
+///
+/// `text_ptr` and `pat_ptr` must point to `text_len` and `pat_len` valid UTF-16
+/// code units respectively.
+#[no_mangle]
+pub unsafe extern "C" fn similarity(
+    text_ptr: *const u16,
+    text_len: usize,
+    pat_ptr: *const u16,
+    pat_len: usize,
+) -> f64 {
+    let text = std::slice::from_raw_parts(text_ptr, text_len);
+    let pat = std::slice::from_raw_parts(pat_ptr, pat_len);
+    best_similarity(text, pat)
+}
+
+/// Preprocess a pattern for searching with `compiled_pattern_search`.
+///
+/// The result must be freed with `compiled_pattern_free`.
+///
+/// # Safety
+///
+/// `pat_ptr` must point to `pat_len` valid UTF-16 code units.
+#[no_mangle]
+pub unsafe extern "C" fn compiled_pattern_new(
+    pat_ptr: *const u16,
+    pat_len: usize,
+) -> *mut CompiledPattern {
+    let pat = std::slice::from_raw_parts(pat_ptr, pat_len);
+    Box::into_raw(Box::new(CompiledPattern::new(pat)))
+}
+
+#[no_mangle]
+pub extern "C" fn compiled_pattern_free(pattern: *mut CompiledPattern) {
+    unsafe { drop(Box::from_raw(pattern)) };
+}
+
+/// Variant of `search` which uses a pattern created by `compiled_pattern_new`.
+#[no_mangle]
+pub extern "C" fn compiled_pattern_search(
+    pattern: &CompiledPattern,
+    match_vec: &mut Vec<Match>,
+    text: &Vec<u16>,
+    max_errors: u32,
+) -> usize {
+    let search_matches = pattern.search(text, max_errors);
     match_vec.clear();
     match_vec.extend_from_slice(&search_matches);
     match_vec.len()
 }
+
+/// Variant of `search` which reports offsets as code point indices rather than
+/// UTF-16 code unit indices.
+///
+/// Code point indices are what JavaScript gives when iterating over a string,
+/// eg. with `Array.from(str)`. They differ from code unit indices after any
+/// character which is encoded as a surrogate pair, such as most emoji. Matches
+/// which start or end in the middle of a surrogate pair are widened to include
+/// the whole character.
+#[no_mangle]
+pub extern "C" fn search_codepoints(
+    match_vec: &mut Vec<Match>,
+    text: &Vec<u16>,
+    pat: &Vec<u16>,
+    max_errors: u32,
+) -> usize {
+    let search_matches = search_impl(text, pat, max_errors);
+    match_vec.clear();
+    match_vec.extend(to_char_offsets(text, &search_matches));
+    match_vec.len()
+}
+
+/// Search for matches of several patterns in one pass over `text`.
+///
+/// `patterns` holds the patterns one after another, and `pattern_lens` holds
+/// the length of each. The results are grouped by pattern index, in order of
+/// position in the text within each group.
+///
+/// # Panics
+///
+/// Panics if the lengths in `pattern_lens` add up to more than `patterns.len()`.
+#[no_mangle]
+pub extern "C" fn search_multi(
+    match_vec: &mut Vec<(usize, Match)>,
+    text: &Vec<u16>,
+    patterns: &Vec<u16>,
+    pattern_lens: &Vec<u32>,
+    max_errors: u32,
+) -> usize {
+    let mut pattern_slices = Vec::with_capacity(pattern_lens.len());
+    let mut offset = 0;
+    for &len in pattern_lens {
+        let end = offset + len as usize;
+        pattern_slices.push(&patterns[offset..end]);
+        offset = end;
+    }
+
+    let mut matches = search_multi_impl(text, &pattern_slices, max_errors);
+    matches.sort_by_key(|(index, _)| *index);
+
+    match_vec.clear();
+    match_vec.extend(matches);
+    match_vec.len()
+}
+
+/// Search for matches and return them as a JSON array of objects with `start`,
+/// `end` and `errors` fields, encoded as a NUL-terminated UTF-8 string.
+///
+/// The result must be freed with `json_free`. Use `json_len` to get its length.
+///
+/// # Safety
+///
+/// `text_ptr` and `pat_ptr` must point to `text_len` and `pat_len` valid UTF-16
+/// code units respectively.
+#[no_mangle]
+pub unsafe extern "C" fn search_json(
+    text_ptr: *const u16,
+    text_len: usize,
+    pat_ptr: *const u16,
+    pat_len: usize,
+    max_errors: u32,
+) -> *mut u8 {
+    let text = std::slice::from_raw_parts(text_ptr, text_len);
+    let pat = std::slice::from_raw_parts(pat_ptr, pat_len);
+
+    let mut json = String::from("[");
+    for (i, m) in search_impl(text, pat, max_errors).iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write!(
+            json,
+            r#"{{"start":{},"end":{},"errors":{}}}"#,
+            m.start, m.end, m.errors
+        )
+        .unwrap();
+    }
+    json.push(']');
+
+    // The JSON only contains ASCII digits and punctuation, so it has no
+    // interior NUL bytes.
+    CString::new(json).unwrap().into_raw() as *mut u8
+}
+
+/// Return the length in bytes of a string returned by `search_json`, excluding
+/// the terminating NUL.
+///
+/// # Safety
+///
+/// `json` must have been returned by `search_json` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn json_len(json: *const u8) -> usize {
+    CStr::from_ptr(json as *const _).to_bytes().len()
+}
+
+/// Free a string returned by `search_json`.
+///
+/// # Safety
+///
+/// `json` must have been returned by `search_json` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn json_free(json: *mut u8) {
+    drop(CString::from_raw(json as *mut _));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compiled_pattern_free, compiled_pattern_new, compiled_pattern_search, json_free, json_len,
+        search, search_codepoints, search_json, search_multi, similarity,
+    };
+    use crate::Match;
+
+    #[test]
+    fn it_returns_matches_as_json() {
+        let text: Vec<u16> = "a cat, a cot".encode_utf16().collect();
+        let pattern: Vec<u16> = "cat".encode_utf16().collect();
+
+        unsafe {
+            let json = search_json(
+                text.as_ptr(),
+                text.len(),
+                pattern.as_ptr(),
+                pattern.len(),
+                1,
+            );
+            let bytes = std::slice::from_raw_parts(json, json_len(json));
+            assert_eq!(
+                std::str::from_utf8(bytes).unwrap(),
+                r#"[{"start":2,"end":5,"errors":0}]"#
+            );
+            json_free(json);
+
+            let json = search_json(text.as_ptr(), text.len(), pattern.as_ptr(), 0, 1);
+            assert_eq!(json_len(json), 2);
+            json_free(json);
+        }
+    }
+
+    #[test]
+    fn it_reports_codepoint_offsets() {
+        let text: Vec<u16> = "🙂 a cat".encode_utf16().collect();
+        let pattern: Vec<u16> = "cat".encode_utf16().collect();
+
+        let mut matches = Vec::new();
+        assert_eq!(search_codepoints(&mut matches, &text, &pattern, 0), 1);
+        assert_eq!(
+            matches,
+            vec![Match {
+                start: 4,
+                end: 7,
+                errors: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn it_searches_packed_patterns() {
+        let text: Vec<u16> = "a cat and a dog".encode_utf16().collect();
+        let patterns: Vec<u16> = "dogcat".encode_utf16().collect();
+        let pattern_lens = vec![3, 3];
+
+        let mut matches = Vec::new();
+        assert_eq!(
+            search_multi(&mut matches, &text, &patterns, &pattern_lens, 0),
+            2
+        );
+        let ranges: Vec<_> = matches.iter().map(|(i, m)| (*i, m.range())).collect();
+        assert_eq!(ranges, vec![(0, 12..15), (1, 2..5)]);
+    }
+
+    #[test]
+    fn it_searches_with_compiled_pattern() {
+        let text: Vec<u16> = "a cat, a cot".encode_utf16().collect();
+        let pattern: Vec<u16> = "cat".encode_utf16().collect();
+
+        let mut expected = Vec::new();
+        search(&mut expected, &text, &pattern, 1);
+
+        let compiled = unsafe { compiled_pattern_new(pattern.as_ptr(), pattern.len()) };
+        let mut matches = Vec::new();
+        for _ in 0..2 {
+            compiled_pattern_search(unsafe { &*compiled }, &mut matches, &text, 1);
+            assert_eq!(matches, expected);
+        }
+        compiled_pattern_free(compiled);
+    }
+
+    #[test]
+    fn it_returns_similarity() {
+        let text: Vec<u16> = "the quick brown fox".encode_utf16().collect();
+        let pattern: Vec<u16> = "quack".encode_utf16().collect();
+
+        let score =
+            unsafe { similarity(text.as_ptr(), text.len(), pattern.as_ptr(), pattern.len()) };
+        assert_eq!(score, 0.8);
+    }
+}